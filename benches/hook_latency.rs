@@ -0,0 +1,132 @@
+//! End-to-end hook latency against stand-in editors.
+//!
+//! Each case parses a PreToolUse Edit and dispatches it to an `Action` that
+//! simulates N editor instances, sleeping a fixed latency per instance per
+//! operation. `baseline/parse` is the floor: parsing alone, no editors.
+//!
+//! Per-op latency defaults to 200µs; override with
+//! `SIDEKICK_BENCH_OP_LATENCY_US`. Events go to a temp file, not your log.
+//!
+//! ```text
+//! cargo bench --bench hook_latency
+//! ```
+
+use std::time::Duration;
+
+use criterion::{BenchmarkId, Criterion, criterion_group};
+use sidekick::action::{Action, BufferStatus, EditorContext, NotifyLevel};
+use sidekick::config::Config;
+use sidekick::handler;
+use sidekick::hook::parse_hook;
+
+const EDIT_HOOK: &str = r#"{"session_id":"bench","transcript_path":"","cwd":".",
+    "hook_event_name":"PreToolUse","tool_name":"Edit",
+    "tool_input":{"file_path":"src/lib.rs","old_string":"a","new_string":"b"}}"#;
+
+/// `instances` editors with the file open and clean, each taking `latency`
+/// to answer any request
+struct SimulatedEditors {
+    instances: usize,
+    latency: Duration,
+}
+
+impl SimulatedEditors {
+    fn round_trip(&self) {
+        for _ in 0..self.instances {
+            std::thread::sleep(self.latency);
+        }
+    }
+}
+
+impl Action for SimulatedEditors {
+    fn buffer_status(&self, _file_path: &str) -> anyhow::Result<Vec<BufferStatus>> {
+        self.round_trip();
+        Ok(vec![
+            BufferStatus {
+                is_current: true,
+                has_unsaved_changes: false,
+                instance: None,
+            };
+            self.instances
+        ])
+    }
+
+    fn buffer_contents(&self, _file_path: &str) -> anyhow::Result<Vec<String>> {
+        self.round_trip();
+        Ok(Vec::new())
+    }
+
+    fn refresh_buffer(&self, _file_path: &str) -> anyhow::Result<()> {
+        self.round_trip();
+        Ok(())
+    }
+
+    fn send_message(&self, _message: &str, _level: NotifyLevel) -> anyhow::Result<()> {
+        self.round_trip();
+        Ok(())
+    }
+
+    fn list_buffers(&self) -> anyhow::Result<Vec<String>> {
+        self.round_trip();
+        Ok(Vec::new())
+    }
+
+    fn get_visual_selections(
+        &self,
+        _max_bytes: Option<usize>,
+    ) -> anyhow::Result<Vec<EditorContext>> {
+        self.round_trip();
+        Ok(Vec::new())
+    }
+}
+
+fn op_latency() -> Duration {
+    let micros = std::env::var("SIDEKICK_BENCH_OP_LATENCY_US")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(200);
+    Duration::from_micros(micros)
+}
+
+fn hook_latency(c: &mut Criterion) {
+    let config = Config::default();
+
+    c.bench_function("baseline/parse", |b| {
+        b.iter(|| parse_hook(EDIT_HOOK).unwrap())
+    });
+
+    let mut group = c.benchmark_group("pre_tool_use");
+    for instances in [0, 1, 5] {
+        let editors = SimulatedEditors {
+            instances,
+            latency: op_latency(),
+        };
+        group.bench_with_input(
+            BenchmarkId::new("instances", instances),
+            &editors,
+            |b, editors| {
+                let action = (editors.instances > 0).then_some(editors as &dyn Action);
+                b.iter(|| {
+                    let hook = parse_hook(EDIT_HOOK).unwrap();
+                    handler::dispatch(hook, action, editors.instances, &config)
+                        .to_json()
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hook_latency);
+
+fn main() {
+    let events = std::env::temp_dir().join(format!("sidekick-bench-{}.jsonl", std::process::id()));
+    // SAFETY: set before criterion starts, while this is the only thread
+    unsafe { std::env::set_var("SIDEKICK_EVENTS_PATH", &events) };
+
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+
+    let _ = std::fs::remove_file(events);
+}
@@ -6,16 +6,16 @@
 //! # Example
 //!
 //! ```no_run
-//! use sidekick::action::{Action, neovim::NeovimAction};
+//! use sidekick::action::{Action, NotifyLevel, neovim::NeovimAction};
 //! use std::path::PathBuf;
 //!
 //! // Create action for Neovim instances
 //! let sockets = vec![PathBuf::from("/tmp/socket.sock")];
-//! let action = NeovimAction::new(sockets);
+//! let action = NeovimAction::from_sockets(sockets);
 //!
-//! // Check buffer status
-//! let status = action.buffer_status("file.txt").unwrap();
-//! if status.has_unsaved_changes && status.is_current {
+//! // Check buffer status in every instance that has the file open
+//! let statuses = action.buffer_status("file.txt").unwrap();
+//! if statuses.iter().any(|s| s.has_unsaved_changes && s.is_current) {
 //!     println!("File has unsaved changes!");
 //! }
 //!
@@ -23,38 +23,636 @@
 //! action.refresh_buffer("file.txt").unwrap();
 //!
 //! // Send message to editor
-//! action.send_message("Hello from Sidekick!").unwrap();
+//! action.send_message("Hello from Sidekick!", NotifyLevel::Info).unwrap();
 //! ```
 
+pub mod jetbrains;
+pub mod kakoune;
 pub mod neovim;
+pub mod record;
+
+use std::fmt;
+use std::path::Path;
+
+use crate::analytics::event::Decision;
+use crate::config::{Config, UnsavedPolicy};
+use crate::constants::READ_LIVE_CONTENT_MAX_BYTES;
+use crate::utils;
+use jetbrains::JetBrainsAction;
+use kakoune::KakouneAction;
+use neovim::NeovimAction;
+use record::{Recorder, Replay};
 
 /// Buffer status information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct BufferStatus {
     pub is_current: bool,
     pub has_unsaved_changes: bool,
+    /// Instance that reported it, as `send_message_to` takes it; `None`
+    /// where the backend can't address a single instance
+    pub instance: Option<String>,
+}
+
+/// Where a buffer's unsaved changes are, relative to the file on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DirtySummary {
+    /// Lines that differ from disk, counted over the span from the first to
+    /// the last change. At least 1: a buffer that matches has no summary.
+    pub changed_lines: usize,
+    /// 1-based line of the first change
+    pub first_line: u32,
+}
+
+impl DirtySummary {
+    /// Compare `buffer` with what's on `disk`, `None` if they match line for
+    /// line
+    pub fn between(disk: &str, buffer: &str) -> Option<Self> {
+        let disk: Vec<&str> = disk.lines().collect();
+        let buffer: Vec<&str> = buffer.lines().collect();
+
+        let prefix = disk.iter().zip(&buffer).take_while(|(d, b)| d == b).count();
+        let suffix = disk[prefix..]
+            .iter()
+            .rev()
+            .zip(buffer[prefix..].iter().rev())
+            .take_while(|(d, b)| d == b)
+            .count();
+        let changed_lines = (disk.len() - prefix - suffix).max(buffer.len() - prefix - suffix);
+
+        (changed_lines > 0).then(|| Self {
+            changed_lines,
+            first_line: prefix as u32 + 1,
+        })
+    }
+
+    /// Compare the first of `buffers` that differs with `file_path` on disk.
+    /// `None` too when the file isn't UTF-8 text or it, or a buffer, is over
+    /// `READ_LIVE_CONTENT_MAX_BYTES`: no summary beats a wrong or slow one.
+    pub fn of(file_path: &str, buffers: &[String]) -> Option<Self> {
+        let disk = match std::fs::metadata(file_path) {
+            Ok(meta) if meta.len() > READ_LIVE_CONTENT_MAX_BYTES as u64 => return None,
+            Ok(_) => std::fs::read_to_string(file_path).ok()?,
+            // A file not yet written is all unsaved changes
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(_) => return None,
+        };
+        buffers
+            .iter()
+            .filter(|buffer| buffer.len() <= READ_LIVE_CONTENT_MAX_BYTES)
+            .find_map(|buffer| Self::between(&disk, buffer))
+    }
+}
+
+impl fmt::Display for DirtySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.changed_lines == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} unsaved change{} near line {}",
+            self.changed_lines, plural, self.first_line
+        )
+    }
+}
+
+/// Severity of an editor message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyLevel {
+    Info,
+    #[default]
+    Warn,
+    Error,
 }
 
 /// Editor context from visual selection
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct EditorContext {
     pub file_path: String,
     pub start_line: u32,
     pub end_line: u32,
-    pub content: String,
+    /// Selected text, `None` when it was larger than the caller's `max_bytes`
+    pub content: Option<String>,
+    /// Whether `content` was left out for size
+    pub truncated: bool,
+    /// Buffer filetype as the editor reports it (e.g. `rust`)
+    pub language: Option<String>,
+    /// Branch checked out in the file's repository, `None` when detached
+    pub git_branch: Option<String>,
+    /// Commit the file's repository is at
+    pub git_sha: Option<String>,
+}
+
+/// The file in an editor's focused window and the lines on screen
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct EditorView {
+    pub file_path: String,
+    pub first_line: u32,
+    pub last_line: u32,
 }
 
 /// Trait for editor actions
 pub trait Action {
-    /// Get the status of a buffer
-    fn buffer_status(&self, file_path: &str) -> anyhow::Result<BufferStatus>;
+    /// Get the status of a buffer, one entry per editor instance that has it open
+    fn buffer_status(&self, file_path: &str) -> anyhow::Result<Vec<BufferStatus>>;
+
+    /// Whether any editor instance has the file open, without working out
+    /// its status where the backend can tell more cheaply. A status query
+    /// that fails counts as not open.
+    fn is_open(&self, file_path: &str) -> anyhow::Result<bool> {
+        Ok(!self.buffer_status(file_path)?.is_empty())
+    }
+
+    /// Live text of a buffer, unsaved changes included, one entry per editor
+    /// instance that has it open
+    fn buffer_contents(&self, file_path: &str) -> anyhow::Result<Vec<String>>;
 
     /// Refresh the buffer (reload from disk)
     fn refresh_buffer(&self, file_path: &str) -> anyhow::Result<()>;
 
     /// Send a message to the editor
-    fn send_message(&self, message: &str) -> anyhow::Result<()>;
+    fn send_message(&self, message: &str, level: NotifyLevel) -> anyhow::Result<()>;
+
+    /// Send a message to one instance, named as in `BufferStatus::instance`.
+    /// Fails where the backend can't address instances or doesn't have it.
+    fn send_message_to(
+        &self,
+        instance: &str,
+        _message: &str,
+        _level: NotifyLevel,
+    ) -> anyhow::Result<()> {
+        anyhow::bail!("couldn't address editor instance {}", instance)
+    }
+
+    /// Show a titled, multi-line message more prominently than
+    /// `send_message`, where the editor can. Others get it as a message.
+    fn notify_rich(&self, title: &str, body: &str) -> anyhow::Result<()> {
+        self.send_message(
+            &format!("{}: {}", title, body.replace('\n', " ")),
+            NotifyLevel::Warn,
+        )
+    }
+
+    /// Whether any editor instance has its window focused, so a message
+    /// sent there would be seen. Backends that can't tell say it is.
+    fn is_focused(&self) -> anyhow::Result<bool> {
+        Ok(true)
+    }
+
+    /// Show (`active`) or clear the editor's "Claude is editing" indicator,
+    /// with `detail` such as the file being edited. Backends without one
+    /// ignore it.
+    fn set_status(&self, active: bool, detail: Option<String>) -> anyhow::Result<()> {
+        let _ = (active, detail);
+        Ok(())
+    }
+
+    /// Open `file_path` as it was at git revision `rev`, read-only, in one
+    /// editor instance
+    fn open_at_revision(&self, file_path: &str, rev: &str) -> anyhow::Result<()> {
+        let _ = rev;
+        anyhow::bail!(
+            "couldn't open {}: this editor can't show revisions",
+            file_path
+        )
+    }
+
+    /// Reload sidekick's editor-side integration in every instance, after an
+    /// upgrade, without restarting the editor. Best-effort: succeeds if any
+    /// instance reloaded.
+    fn reload_plugin(&self) -> anyhow::Result<()> {
+        anyhow::bail!("couldn't reload: this editor has no sidekick plugin")
+    }
+
+    /// Files open across all editor instances, one entry per instance that
+    /// has each open
+    fn list_buffers(&self) -> anyhow::Result<Vec<String>>;
+
+    /// Get visual selections from all editor instances. A selection over
+    /// `max_bytes` comes back as its range only, without the text.
+    fn get_visual_selections(&self, max_bytes: Option<usize>)
+    -> anyhow::Result<Vec<EditorContext>>;
+
+    /// What each editor instance has in its focused window, where the
+    /// editor can tell. Others report nothing.
+    fn current_views(&self) -> anyhow::Result<Vec<EditorView>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Several editor backends behind one `Action`. Statuses and selections are
+/// concatenated across backends; a refresh or message succeeds if any
+/// backend took it.
+pub struct Editors(pub Vec<Box<dyn Action>>);
+
+impl Action for Editors {
+    fn buffer_status(&self, file_path: &str) -> anyhow::Result<Vec<BufferStatus>> {
+        let mut failure = None;
+        let mut statuses = Vec::new();
+        for editor in &self.0 {
+            match editor.buffer_status(file_path) {
+                Ok(found) => statuses.extend(found),
+                Err(e) => {
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+        settle_statuses(statuses, failure, "couldn't query every editor")
+    }
+
+    fn is_open(&self, file_path: &str) -> anyhow::Result<bool> {
+        let mut failure = None;
+        for editor in &self.0 {
+            match editor.is_open(file_path) {
+                Ok(true) => return Ok(true),
+                Ok(false) => {}
+                Err(e) => {
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+        failure.map_or(Ok(false), |e| Err(e.context("couldn't query every editor")))
+    }
+
+    fn buffer_contents(&self, file_path: &str) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .0
+            .iter()
+            .filter_map(|editor| editor.buffer_contents(file_path).ok())
+            .flatten()
+            .collect())
+    }
+
+    fn refresh_buffer(&self, file_path: &str) -> anyhow::Result<()> {
+        let results: Vec<_> = self
+            .0
+            .iter()
+            .map(|editor| editor.refresh_buffer(file_path))
+            .collect();
+        any_ok(results)
+    }
+
+    fn send_message(&self, message: &str, level: NotifyLevel) -> anyhow::Result<()> {
+        let results: Vec<_> = self
+            .0
+            .iter()
+            .map(|editor| editor.send_message(message, level))
+            .collect();
+        any_ok(results)
+    }
+
+    fn send_message_to(
+        &self,
+        instance: &str,
+        message: &str,
+        level: NotifyLevel,
+    ) -> anyhow::Result<()> {
+        let results: Vec<_> = self
+            .0
+            .iter()
+            .map(|editor| editor.send_message_to(instance, message, level))
+            .collect();
+        any_ok(results)
+    }
+
+    fn notify_rich(&self, title: &str, body: &str) -> anyhow::Result<()> {
+        let results: Vec<_> = self
+            .0
+            .iter()
+            .map(|editor| editor.notify_rich(title, body))
+            .collect();
+        any_ok(results)
+    }
+
+    fn is_focused(&self) -> anyhow::Result<bool> {
+        Ok(self
+            .0
+            .iter()
+            .any(|editor| editor.is_focused().unwrap_or(true)))
+    }
+
+    fn open_at_revision(&self, file_path: &str, rev: &str) -> anyhow::Result<()> {
+        // One editor is enough; stop at the first that opens it
+        let mut last_err = None;
+        for editor in &self.0 {
+            match editor.open_at_revision(file_path, rev) {
+                Ok(()) => return Ok(()),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        last_err.map_or(Ok(()), Err)
+    }
+
+    fn reload_plugin(&self) -> anyhow::Result<()> {
+        let results: Vec<_> = self.0.iter().map(|editor| editor.reload_plugin()).collect();
+        any_ok(results)
+    }
+
+    fn set_status(&self, active: bool, detail: Option<String>) -> anyhow::Result<()> {
+        let results: Vec<_> = self
+            .0
+            .iter()
+            .map(|editor| editor.set_status(active, detail.clone()))
+            .collect();
+        any_ok(results)
+    }
+
+    fn list_buffers(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .0
+            .iter()
+            .filter_map(|editor| editor.list_buffers().ok())
+            .flatten()
+            .collect())
+    }
+
+    fn get_visual_selections(
+        &self,
+        max_bytes: Option<usize>,
+    ) -> anyhow::Result<Vec<EditorContext>> {
+        Ok(self
+            .0
+            .iter()
+            .filter_map(|editor| editor.get_visual_selections(max_bytes).ok())
+            .flatten()
+            .collect())
+    }
+
+    fn current_views(&self) -> anyhow::Result<Vec<EditorView>> {
+        Ok(self
+            .0
+            .iter()
+            .filter_map(|editor| editor.current_views().ok())
+            .flatten()
+            .collect())
+    }
+}
+
+/// The decision all editors' reports on a file add up to, whichever
+/// backends they came from. Under `UnsavedPolicy::Current` it takes a single
+/// instance with the file both dirty and current: the two flags are judged
+/// per instance, so dirty in one editor and focused in another means nobody
+/// is mid-edit on it. `UnsavedPolicy::Any` denies on unsaved changes anywhere.
+pub fn merge_statuses(statuses: &[BufferStatus], policy: UnsavedPolicy) -> Decision {
+    let editing = statuses.iter().any(|s| match policy {
+        UnsavedPolicy::Current => s.has_unsaved_changes && s.is_current,
+        UnsavedPolicy::Any => s.has_unsaved_changes,
+    });
+    if editing {
+        Decision::Deny
+    } else {
+        Decision::Allow
+    }
+}
+
+/// Selections from every instance, sorted by file then line, with those
+/// covering the same lines of the same file kept once. Two instances with
+/// the file open report the same selection; the copy with its text wins.
+pub fn dedup_selections(mut selections: Vec<EditorContext>) -> Vec<EditorContext> {
+    selections.sort_by(|a, b| {
+        (&a.file_path, a.start_line, a.end_line, a.content.is_none()).cmp(&(
+            &b.file_path,
+            b.start_line,
+            b.end_line,
+            b.content.is_none(),
+        ))
+    });
+    selections.dedup_by(|later, kept| {
+        (&later.file_path, later.start_line, later.end_line)
+            == (&kept.file_path, kept.start_line, kept.end_line)
+    });
+    selections
+}
+
+/// Every editor instance opened from `dir` behind one `Action`, and how many
+/// instances were found, at most `max_instances`. Failed discovery finds
+/// nothing.
+pub fn discover(dir: &Path, config: &Config) -> (Option<Box<dyn Action>>, usize) {
+    // Neovim is searched first, so under the cap it's the one kept
+    let max = config.max_instances.unwrap_or(usize::MAX);
+    utils::track_dead_sockets(config.dead_socket_ttl.is_some());
+    let socket_paths =
+        utils::find_matching_sockets_up_to(dir, max, config.dead_socket_ttl).unwrap_or_default();
+    let mut kak_sessions = utils::find_matching_kak_sessions_for(dir).unwrap_or_default();
+    kak_sessions.truncate(max - socket_paths.len());
+    let mut jetbrains_ports = utils::find_matching_jetbrains_ports_for(dir).unwrap_or_default();
+    jetbrains_ports.truncate(max - socket_paths.len() - kak_sessions.len());
+    let instances = socket_paths.len() + kak_sessions.len() + jetbrains_ports.len();
+
+    let mut editors: Vec<Box<dyn Action>> = Vec::new();
+    if !socket_paths.is_empty() {
+        editors.push(Box::new(
+            NeovimAction::from_sockets(socket_paths)
+                .with_refresh_policy(config.refresh_policy())
+                .with_visible_as_current(config.visible_is_current)
+                .with_scan_all(config.scan_all_instances)
+                .with_match_mode(config.match_mode),
+        ));
+    }
+    if !kak_sessions.is_empty() {
+        editors.push(Box::new(KakouneAction::new(kak_sessions)));
+    }
+    if !jetbrains_ports.is_empty() {
+        editors.push(Box::new(JetBrainsAction::new(jetbrains_ports)));
+    }
+    let editors = match editors.len() {
+        0 => None,
+        1 => editors.pop(),
+        _ => Some(Box::new(Editors(editors)) as Box<dyn Action>),
+    };
+
+    // Debugging aids: stand a recording in for the editors, or log theirs
+    if let Some(path) = std::env::var_os("SIDEKICK_REPLAY_RPC") {
+        return match Replay::load(Path::new(&path)) {
+            Ok(replay) => (Some(Box::new(replay)), 1),
+            Err(e) => {
+                config.verbosity.warn(format_args!("{:#}", e));
+                (None, 0)
+            }
+        };
+    }
+    let editors = match std::env::var_os("SIDEKICK_RECORD_RPC") {
+        Some(path) => editors.map(|e| Box::new(Recorder::new(e, path.into())) as Box<dyn Action>),
+        None => editors,
+    };
+
+    (editors, instances)
+}
+
+/// Statuses gathered from several instances, some of which couldn't be
+/// asked. One of those might be the one editing the file, so `failure`
+/// stands unless a status already shows the file dirty and current.
+pub(crate) fn settle_statuses(
+    statuses: Vec<BufferStatus>,
+    failure: Option<anyhow::Error>,
+    context: &'static str,
+) -> anyhow::Result<Vec<BufferStatus>> {
+    match failure {
+        Some(e)
+            if !statuses
+                .iter()
+                .any(|s| s.has_unsaved_changes && s.is_current) =>
+        {
+            Err(e.context(context))
+        }
+        _ => Ok(statuses),
+    }
+}
+
+/// `Ok` if any result is, else the last error
+fn any_ok(results: Vec<anyhow::Result<()>>) -> anyhow::Result<()> {
+    let mut last_err = None;
+    for result in results {
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    last_err.map_or(Ok(()), Err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Backend reporting fixed statuses and nothing else
+    struct Reports(Vec<BufferStatus>);
+
+    impl Action for Reports {
+        fn buffer_status(&self, _file_path: &str) -> anyhow::Result<Vec<BufferStatus>> {
+            Ok(self.0.clone())
+        }
+
+        fn buffer_contents(&self, _file_path: &str) -> anyhow::Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn refresh_buffer(&self, _file_path: &str) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn send_message(&self, _message: &str, _level: NotifyLevel) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn list_buffers(&self) -> anyhow::Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn get_visual_selections(
+            &self,
+            _max_bytes: Option<usize>,
+        ) -> anyhow::Result<Vec<EditorContext>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn reports(is_current: bool, has_unsaved_changes: bool) -> Box<dyn Action> {
+        Box::new(Reports(vec![BufferStatus {
+            is_current,
+            has_unsaved_changes,
+            instance: None,
+        }]))
+    }
+
+    #[test]
+    fn conflicting_backends_merge_per_policy() {
+        let decide = |editors: Editors, policy| {
+            merge_statuses(&editors.buffer_status("f.rs").unwrap(), policy)
+        };
+
+        // One backend mid-edit outvotes a clean one
+        let one_editing = || Editors(vec![reports(false, false), reports(true, true)]);
+        assert_eq!(
+            decide(one_editing(), UnsavedPolicy::Current),
+            Decision::Deny
+        );
+        assert_eq!(decide(one_editing(), UnsavedPolicy::Any), Decision::Deny);
+
+        // Dirty in one, focused in the other: nobody is mid-edit
+        let split = || Editors(vec![reports(false, true), reports(true, false)]);
+        assert_eq!(decide(split(), UnsavedPolicy::Current), Decision::Allow);
+        assert_eq!(decide(split(), UnsavedPolicy::Any), Decision::Deny);
+
+        let clean = Editors(vec![reports(true, false), reports(false, false)]);
+        assert_eq!(decide(clean, UnsavedPolicy::Any), Decision::Allow);
+    }
+
+    #[test]
+    fn duplicate_selections_are_kept_once_in_file_then_line_order() {
+        let selection = |file: &str, start_line, end_line, content: Option<&str>| EditorContext {
+            file_path: file.to_string(),
+            start_line,
+            end_line,
+            content: content.map(str::to_string),
+            truncated: content.is_none(),
+            language: None,
+            git_branch: None,
+            git_sha: None,
+        };
+        let merged = dedup_selections(vec![
+            selection("/p/b.rs", 1, 4, Some("b")),
+            selection("/p/a.rs", 10, 12, None),
+            selection("/p/a.rs", 3, 8, Some("a")),
+            selection("/p/a.rs", 10, 12, Some("a2")),
+            // Overlapping but not the same lines: both stay
+            selection("/p/b.rs", 2, 6, Some("b2")),
+            selection("/p/b.rs", 1, 4, Some("b")),
+        ]);
+
+        let ranges: Vec<_> = merged
+            .iter()
+            .map(|s| (s.file_path.as_str(), s.start_line, s.end_line))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                ("/p/a.rs", 3, 8),
+                ("/p/a.rs", 10, 12),
+                ("/p/b.rs", 1, 4),
+                ("/p/b.rs", 2, 6)
+            ]
+        );
+        assert_eq!(merged[1].content.as_deref(), Some("a2"));
+    }
+
+    #[test]
+    fn dirty_summary_points_at_the_changed_lines() {
+        let disk = "a\nb\nc\nd\ne\n";
+        assert_eq!(DirtySummary::between(disk, disk), None);
+
+        let edited = DirtySummary::between(disk, "a\nB\nC\nD\ne\n").unwrap();
+        assert_eq!(edited.to_string(), "3 unsaved changes near line 2");
+
+        let appended = DirtySummary::between(disk, "a\nb\nc\nd\ne\nf\n").unwrap();
+        assert_eq!(appended.to_string(), "1 unsaved change near line 6");
+
+        let deleted = DirtySummary::between(disk, "a\ne\n").unwrap();
+        assert_eq!((deleted.changed_lines, deleted.first_line), (3, 2));
+    }
+
+    #[test]
+    fn dirty_summary_skips_binary_and_oversized_files() {
+        let dir = std::env::temp_dir().join(format!("sidekick-summary-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let text = dir.join("text.txt");
+        let binary = dir.join("binary.bin");
+        let huge = dir.join("huge.txt");
+        std::fs::write(&text, "a\nb\n").unwrap();
+        std::fs::write(&binary, [0xff, 0xfe, b'\n']).unwrap();
+        std::fs::write(&huge, "x\n".repeat(READ_LIVE_CONTENT_MAX_BYTES)).unwrap();
+        let path = |p: &std::path::Path| p.to_string_lossy().into_owned();
+        let edited = ["a\nB\n".to_string()];
+
+        let summary = DirtySummary::of(&path(&text), &edited);
+        let unwritten = DirtySummary::of(&path(&dir.join("new.txt")), &edited);
+        let of_binary = DirtySummary::of(&path(&binary), &edited);
+        let of_huge = DirtySummary::of(&path(&huge), &edited);
+        let huge_buffer =
+            DirtySummary::of(&path(&text), &["y\n".repeat(READ_LIVE_CONTENT_MAX_BYTES)]);
+        std::fs::remove_dir_all(&dir).unwrap();
 
-    /// Get visual selections from all editor instances
-    fn get_visual_selections(&self) -> anyhow::Result<Vec<EditorContext>>;
+        assert_eq!(summary.map(|s| s.first_line), Some(2));
+        assert_eq!(unwritten.map(|s| s.first_line), Some(1));
+        assert_eq!((of_binary, of_huge, huge_buffer), (None, None, None));
+    }
 }
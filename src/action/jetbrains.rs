@@ -0,0 +1,433 @@
+//! JetBrains IDE integration over the IDE's built-in HTTP server.
+//!
+//! IntelliJ-based IDEs serve plugin REST endpoints on `127.0.0.1`, from port
+//! 63342 upward. Editor state isn't exposed there by default, so a small
+//! companion plugin registers the endpoints below under `/api/sidekick`.
+//!
+//! # Discovery
+//!
+//! There is no socket to glob for. Instead the plugin, when a project opens,
+//! writes `/tmp/<blake3(project dir)>-<pid>.jetbrains` holding the port its
+//! IDE listens on, and removes it on close. The hash is the same one the
+//! Neovim sockets use (see `utils::find_matching_jetbrains_ports_for`).
+//! Files a crashed IDE left behind are deleted when found.
+//!
+//! # Plugin endpoints
+//!
+//! These make up version `PLUGIN_PROTOCOL_VERSION` of the protocol.
+//! Paths are absolute and canonical. Anything but a 2xx is an error, with
+//! an optional `{"code":<n>,"message":"..."}` body whose code is one of
+//! `RpcErrorCode`. From `/buffer`, `FileNotOpen` means the same as
+//! `{"open":false}`; any other error leaves that IDE's state unknown.
+//!
+//! - `GET /api/sidekick/buffer?path=<file>` — the file's document:
+//!   `{"open":true,"current":<bool>,"modified":<bool>,"text":"<live text>"}`,
+//!   or `{"open":false}` when no editor tab holds it. `current` is whether
+//!   it is the focused editor. The request accepts `text/plain` too, for
+//!   bridges that would rather not write JSON: `dirty <0|1> current <0|1>`
+//!   for an open file, `closed` otherwise, and no live text.
+//! - `POST /api/sidekick/reload` with `{"path":"<file>"}` — reload the
+//!   document from disk, keeping carets
+//! - `POST /api/sidekick/notify` with `{"message":"...","level":"info|warn|error"}`
+//!   — show a balloon notification
+//! - `GET /api/sidekick/focus` — `{"focused":<bool>}`, whether an IDE
+//!   window is the active one. Plugins without it count as focused.
+//! - `POST /api/sidekick/status` with `{"active":<bool>,"detail":"..."|null}`
+//!   — show or clear a "Claude is editing" status bar item. Plugins without
+//!   it just don't show one.
+//! - `GET /api/sidekick/buffers` — `["<file>", ...]`, every open document
+//! - `GET /api/sidekick/selection[?max_bytes=<n>]` — `null`, or the focused
+//!   editor's selection as
+//!   `{"file_path","start_line","end_line","content","language","truncated"}`
+//!   with 1-based lines. Over `max_bytes`, `content` is left out and
+//!   `truncated` is `true`.
+
+use crate::action::{Action, BufferStatus, EditorContext, NotifyLevel, settle_statuses};
+use crate::constants::{JETBRAINS_TIMEOUT, rpc_timeout};
+use crate::utils;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Error codes shared with editor extensions, so a backend can tell an
+/// expected answer apart from a failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcErrorCode {
+    /// The file isn't open in that editor
+    FileNotOpen = 1,
+    /// The editor can't answer right now (indexing, modal dialog)
+    Busy = 2,
+    /// The extension doesn't implement the request
+    Unsupported = 3,
+}
+
+impl RpcErrorCode {
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            1 => Some(Self::FileNotOpen),
+            2 => Some(Self::Busy),
+            3 => Some(Self::Unsupported),
+            _ => None,
+        }
+    }
+}
+
+/// A non-2xx answer from the plugin
+#[derive(Debug)]
+pub struct RpcError {
+    pub status: u16,
+    /// `None` when the body carried no code, or one we don't know
+    pub code: Option<RpcErrorCode>,
+    pub message: String,
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "JetBrains IDE answered {}", self.status)?;
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl RpcError {
+    fn from_body(status: u16, body: &str) -> Self {
+        #[derive(Deserialize)]
+        struct Body {
+            code: i32,
+            #[serde(default)]
+            message: String,
+        }
+
+        match serde_json::from_str::<Body>(body) {
+            Ok(body) => Self {
+                status,
+                code: RpcErrorCode::from_code(body.code),
+                message: body.message,
+            },
+            Err(_) => Self {
+                status,
+                code: None,
+                message: String::new(),
+            },
+        }
+    }
+
+    /// Whether `error` is a plugin answer carrying `code`
+    fn is(error: &anyhow::Error, code: RpcErrorCode) -> bool {
+        error
+            .downcast_ref::<RpcError>()
+            .is_some_and(|e| e.code == Some(code))
+    }
+}
+
+/// JetBrains action implementation that supports multiple IDE instances
+pub struct JetBrainsAction {
+    ports: Vec<u16>,
+    agent: ureq::Agent,
+}
+
+impl JetBrainsAction {
+    pub fn new(ports: Vec<u16>) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(rpc_timeout(JETBRAINS_TIMEOUT))
+            .build();
+        Self { ports, agent }
+    }
+
+    fn url(port: u16, endpoint: &str) -> String {
+        format!("http://127.0.0.1:{}/api/sidekick/{}", port, endpoint)
+    }
+
+    fn get(&self, port: u16, endpoint: &str, query: &[(&str, &str)]) -> Result<String> {
+        answer(self.request(port, endpoint, query).call(), port)?
+            .into_string()
+            .context("couldn't read JetBrains response")
+    }
+
+    fn request(&self, port: u16, endpoint: &str, query: &[(&str, &str)]) -> ureq::Request {
+        let mut request = self.agent.get(&Self::url(port, endpoint));
+        for (name, value) in query {
+            request = request.query(name, value);
+        }
+        request
+    }
+
+    fn post(&self, port: u16, endpoint: &str, body: serde_json::Value) -> Result<()> {
+        let response = self
+            .agent
+            .post(&Self::url(port, endpoint))
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string());
+        answer(response, port)?;
+        Ok(())
+    }
+
+    /// The file's document in one IDE, `None` if it isn't open there
+    fn document(&self, port: u16, file_path: &str) -> Result<Option<Document>> {
+        let request = self
+            .request(port, "buffer", &[("path", &utils::canonical(file_path))])
+            .set("Accept", "application/json, text/plain;q=0.5");
+        let response = match answer(request.call(), port) {
+            Err(e) if RpcError::is(&e, RpcErrorCode::FileNotOpen) => return Ok(None),
+            response => response?,
+        };
+        let plain = response.content_type() == "text/plain";
+        let body = response
+            .into_string()
+            .context("couldn't read JetBrains response")?;
+        if plain {
+            parse_plain_document(&body)
+        } else {
+            parse_document(&body)
+        }
+    }
+}
+
+/// The response to a request, or why there is none: an `RpcError` when the
+/// plugin answered with an error status
+fn answer(response: Result<ureq::Response, ureq::Error>, port: u16) -> Result<ureq::Response> {
+    match response {
+        Ok(response) => Ok(response),
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            Err(RpcError::from_body(status, &body).into())
+        }
+        Err(e) => Err(e).with_context(|| format!("couldn't reach JetBrains IDE on port {}", port)),
+    }
+}
+
+impl Action for JetBrainsAction {
+    fn buffer_status(&self, file_path: &str) -> Result<Vec<BufferStatus>> {
+        let mut failure = None;
+        let statuses: Vec<_> = self
+            .ports
+            .iter()
+            .filter_map(|&port| match self.document(port, file_path) {
+                Ok(doc) => doc,
+                Err(e) => {
+                    failure.get_or_insert(e);
+                    None
+                }
+            })
+            .map(|doc| BufferStatus {
+                is_current: doc.current,
+                has_unsaved_changes: doc.modified,
+                instance: None,
+            })
+            .collect();
+
+        settle_statuses(statuses, failure, "couldn't query every JetBrains IDE")
+    }
+
+    fn buffer_contents(&self, file_path: &str) -> Result<Vec<String>> {
+        Ok(self
+            .ports
+            .iter()
+            .filter_map(|&port| self.document(port, file_path).ok().flatten())
+            .filter_map(|doc| doc.text)
+            .collect())
+    }
+
+    fn refresh_buffer(&self, file_path: &str) -> Result<()> {
+        let body = serde_json::json!({ "path": utils::canonical(file_path) });
+        let any_success = self
+            .ports
+            .iter()
+            .any(|&port| self.post(port, "reload", body.clone()).is_ok());
+
+        if any_success {
+            Ok(())
+        } else {
+            anyhow::bail!("couldn't refresh JetBrains IDE")
+        }
+    }
+
+    fn send_message(&self, message: &str, level: NotifyLevel) -> Result<()> {
+        let body = serde_json::json!({ "message": message, "level": level_name(level) });
+        let any_success = self
+            .ports
+            .iter()
+            .any(|&port| self.post(port, "notify", body.clone()).is_ok());
+
+        if any_success {
+            Ok(())
+        } else {
+            anyhow::bail!("couldn't send to JetBrains IDE")
+        }
+    }
+
+    fn is_focused(&self) -> Result<bool> {
+        Ok(self.ports.iter().any(|&port| {
+            self.get(port, "focus", &[])
+                .ok()
+                .and_then(|body| serde_json::from_str::<serde_json::Value>(&body).ok())
+                .and_then(|answer| answer["focused"].as_bool())
+                .unwrap_or(true)
+        }))
+    }
+
+    fn set_status(&self, active: bool, detail: Option<String>) -> Result<()> {
+        let body = serde_json::json!({ "active": active, "detail": detail });
+        let results: Vec<_> = self
+            .ports
+            .iter()
+            .map(|&port| self.post(port, "status", body.clone()))
+            .collect();
+
+        if results.iter().any(Result::is_ok) {
+            Ok(())
+        } else {
+            anyhow::bail!("couldn't set status in JetBrains IDE")
+        }
+    }
+
+    fn list_buffers(&self) -> Result<Vec<String>> {
+        Ok(self
+            .ports
+            .iter()
+            .filter_map(|&port| self.get(port, "buffers", &[]).ok())
+            .filter_map(|body| serde_json::from_str::<Vec<String>>(&body).ok())
+            .flatten()
+            .collect())
+    }
+
+    fn get_visual_selections(&self, max_bytes: Option<usize>) -> Result<Vec<EditorContext>> {
+        let max_bytes = max_bytes.map(|n| n.to_string());
+        let query: Vec<_> = max_bytes
+            .iter()
+            .map(|n| ("max_bytes", n.as_str()))
+            .collect();
+        Ok(self
+            .ports
+            .iter()
+            .filter_map(|&port| self.get(port, "selection", &query).ok())
+            .filter_map(|body| parse_selection(&body).ok().flatten())
+            .collect())
+    }
+}
+
+/// One IDE's view of a file
+#[derive(Debug, Deserialize)]
+struct Document {
+    #[serde(default)]
+    current: bool,
+    #[serde(default)]
+    modified: bool,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+/// Parse a `/buffer` answer, `None` when the file isn't open
+fn parse_document(body: &str) -> Result<Option<Document>> {
+    #[derive(Deserialize)]
+    struct Answer {
+        open: bool,
+        #[serde(flatten)]
+        document: Document,
+    }
+
+    let answer: Answer =
+        serde_json::from_str(body).context("couldn't parse JetBrains buffer state")?;
+    Ok(answer.open.then_some(answer.document))
+}
+
+/// Parse a plain-text `/buffer` answer: `dirty <0|1> current <0|1>`, in
+/// either order, or `closed`
+fn parse_plain_document(body: &str) -> Result<Option<Document>> {
+    let words: Vec<&str> = body.split_whitespace().collect();
+    if words == ["closed"] {
+        return Ok(None);
+    }
+
+    let flag = |value: &str| match value {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    };
+    let (mut modified, mut current) = (None, None);
+    for pair in words.chunks(2) {
+        match pair {
+            ["dirty", value] => modified = flag(value),
+            ["current", value] => current = flag(value),
+            _ => {}
+        }
+    }
+    let (modified, current) = modified
+        .zip(current)
+        .with_context(|| format!("couldn't parse JetBrains buffer state {:?}", body.trim()))?;
+    Ok(Some(Document {
+        current,
+        modified,
+        text: None,
+    }))
+}
+
+/// Parse a `/selection` answer, `None` when nothing is selected
+fn parse_selection(body: &str) -> Result<Option<EditorContext>> {
+    #[derive(Deserialize)]
+    struct SelectionData {
+        file_path: String,
+        start_line: u32,
+        end_line: u32,
+        #[serde(default)]
+        content: Option<String>,
+        #[serde(default)]
+        language: Option<String>,
+        #[serde(default)]
+        truncated: bool,
+    }
+
+    let data: Option<SelectionData> =
+        serde_json::from_str(body).context("couldn't parse JetBrains selection")?;
+    Ok(data.map(|data| EditorContext {
+        file_path: data.file_path,
+        start_line: data.start_line,
+        end_line: data.end_line,
+        content: data.content,
+        truncated: data.truncated,
+        language: data.language,
+        git_branch: None,
+        git_sha: None,
+    }))
+}
+
+fn level_name(level: NotifyLevel) -> &'static str {
+    match level {
+        NotifyLevel::Info => "info",
+        NotifyLevel::Warn => "warn",
+        NotifyLevel::Error => "error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_open_and_closed_documents() {
+        let open = parse_document(r#"{"open":true,"current":true,"modified":true,"text":"x"}"#)
+            .unwrap()
+            .unwrap();
+        assert!(open.current && open.modified);
+        assert_eq!(open.text.as_deref(), Some("x"));
+
+        assert!(parse_document(r#"{"open":false}"#).unwrap().is_none());
+        assert!(parse_document("<html>").is_err());
+    }
+
+    #[test]
+    fn parses_selection_or_its_absence() {
+        assert!(parse_selection("null").unwrap().is_none());
+
+        let ctx = parse_selection(
+            r#"{"file_path":"/p/A.kt","start_line":2,"end_line":4,"content":"fun a()","language":"kotlin"}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!((ctx.start_line, ctx.end_line), (2, 4));
+        assert_eq!(ctx.language.as_deref(), Some("kotlin"));
+    }
+}
@@ -0,0 +1,381 @@
+//! Kakoune integration for performing editor actions.
+//!
+//! Kakoune has no request/response RPC. Commands are sent to a session with
+//! `kak -p <session>`, which pipes stdin to the server and returns at once.
+//! To read state back, the commands write their answer with
+//! `echo -to-file <path>` and we poll for that file, bounded by
+//! `KAKOUNE_TIMEOUT`.
+//!
+//! # Sessions
+//!
+//! `sidekick kakoune` launches `kak -s <blake3(cwd)>-<pid>`, mirroring the
+//! Neovim socket naming, so discovery is a glob over Kakoune's socket
+//! directory (see `utils::find_matching_kak_sessions_for`).
+//!
+//! # Commands
+//!
+//! All strings are quoted Kakoune-style (`'...'`, with `''` for a quote).
+//!
+//! - Status, round one — the buffer's modified flag and the connected clients:
+//!   `try %{ evaluate-commands -buffer '<file>' %{ echo -to-file '<out>'
+//!   -quoting kakoune -- %val{modified} %val{client_list} } } catch %{
+//!   echo -to-file '<out>' -quoting kakoune -- missing }`
+//! - Status, round two — the buffer each client is showing, one file per
+//!   client: `evaluate-commands -client '<client>' %{ echo -to-file '<out>'
+//!   -quoting kakoune -- %val{buffile} }`
+//! - Contents: the status query's shape, selecting the whole buffer in a
+//!   draft context and answering `open %val{selection}`
+//! - Buffers: `echo -to-file '<out>' -quoting kakoune -- %val{buflist}`,
+//!   dropping `*scratch*`-style buffers
+//! - Refresh: `try %{ evaluate-commands -buffer '<file>' %{ edit! } }`
+//! - Message: `evaluate-commands -client '<client>' %{ echo -markup
+//!   '{Information}<message>' }` for every client in `%val{client_list}`
+//!
+//! Kakoune has no separate visual mode — there is always a selection — so
+//! this backend contributes no visual selections.
+
+use crate::action::{Action, BufferStatus, EditorContext, NotifyLevel, settle_statuses};
+use crate::constants::{KAKOUNE_TIMEOUT, rpc_timeout};
+use crate::utils;
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Kakoune action implementation that supports multiple sessions
+pub struct KakouneAction {
+    sessions: Vec<String>,
+}
+
+impl KakouneAction {
+    pub fn new(sessions: Vec<String>) -> Self {
+        Self { sessions }
+    }
+}
+
+impl Action for KakouneAction {
+    fn buffer_status(&self, file_path: &str) -> Result<Vec<BufferStatus>> {
+        let mut failure = None;
+        let mut statuses = Vec::new();
+        for session in &self.sessions {
+            match session_buffer_status(session, file_path) {
+                Ok(status) => statuses.extend(status),
+                // A session that has exited can't be editing the file
+                Err(_) if session_gone(session) => {}
+                Err(e) => {
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+        settle_statuses(statuses, failure, "couldn't query every Kakoune session")
+    }
+
+    fn buffer_contents(&self, file_path: &str) -> Result<Vec<String>> {
+        Ok(self
+            .sessions
+            .iter()
+            .filter_map(|session| session_buffer_contents(session, file_path).ok().flatten())
+            .collect())
+    }
+
+    fn refresh_buffer(&self, file_path: &str) -> Result<()> {
+        let command = refresh_command(file_path);
+        let any_success = self
+            .sessions
+            .iter()
+            .any(|session| send(session, &command).is_ok());
+
+        if any_success {
+            Ok(())
+        } else {
+            anyhow::bail!("couldn't refresh Kakoune")
+        }
+    }
+
+    fn send_message(&self, message: &str, level: NotifyLevel) -> Result<()> {
+        let any_success = self.sessions.iter().any(|session| {
+            list_clients(session)
+                .and_then(|clients| send(session, &message_command(&clients, message, level)))
+                .is_ok()
+        });
+
+        if any_success {
+            Ok(())
+        } else {
+            anyhow::bail!("couldn't send to Kakoune")
+        }
+    }
+
+    fn list_buffers(&self) -> Result<Vec<String>> {
+        Ok(self
+            .sessions
+            .iter()
+            .filter_map(|session| {
+                let out = scratch_path();
+                query(session, &buflist_command(&out), &out).ok()
+            })
+            .flatten()
+            .filter(|name| !is_scratch_buffer(name))
+            .collect())
+    }
+
+    fn get_visual_selections(&self, _max_bytes: Option<usize>) -> Result<Vec<EditorContext>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Status of `file_path` in one session, `None` if the buffer isn't open
+fn session_buffer_status(session: &str, file_path: &str) -> Result<Option<BufferStatus>> {
+    let target = utils::canonical(file_path);
+    let out = scratch_path();
+    let answer = query(session, &status_command(&target, &out), &out)?;
+
+    let mut fields = answer.into_iter();
+    let has_unsaved_changes = match fields.next().as_deref() {
+        Some("true") => true,
+        Some("false") => false,
+        _ => return Ok(None),
+    };
+    let clients: Vec<String> = fields.collect();
+
+    let is_current = clients.iter().any(|client| {
+        let out = scratch_path();
+        query(session, &client_buffile_command(client, &out), &out).is_ok_and(|buffile| {
+            buffile
+                .first()
+                .is_some_and(|f| utils::canonical(f) == target)
+        })
+    });
+
+    Ok(Some(BufferStatus {
+        is_current,
+        has_unsaved_changes,
+        instance: None,
+    }))
+}
+
+/// Text of `file_path` in one session, `None` if the buffer isn't open
+fn session_buffer_contents(session: &str, file_path: &str) -> Result<Option<String>> {
+    let out = scratch_path();
+    let answer = query(
+        session,
+        &contents_command(&utils::canonical(file_path), &out),
+        &out,
+    )?;
+
+    let mut fields = answer.into_iter();
+    match (fields.next().as_deref(), fields.next()) {
+        (Some("open"), Some(text)) => {
+            Ok(Some(text.strip_suffix('\n').unwrap_or(&text).to_string()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Clients connected to a session
+fn list_clients(session: &str) -> Result<Vec<String>> {
+    let out = scratch_path();
+    query(session, &client_list_command(&out), &out)
+}
+
+fn status_command(file_path: &str, out: &Path) -> String {
+    format!(
+        "try %{{ evaluate-commands -buffer {file} %{{ echo -to-file {out} -quoting kakoune -- \
+         %val{{modified}} %val{{client_list}} }} }} catch %{{ echo -to-file {out} \
+         -quoting kakoune -- missing }}",
+        file = quote(file_path),
+        out = quote(&out.to_string_lossy()),
+    )
+}
+
+fn contents_command(file_path: &str, out: &Path) -> String {
+    format!(
+        "try %{{ evaluate-commands -buffer {file} -draft %{{ execute-keys '%'; echo -to-file {out} \
+         -quoting kakoune -- open %val{{selection}} }} }} catch %{{ echo -to-file {out} \
+         -quoting kakoune -- missing }}",
+        file = quote(file_path),
+        out = quote(&out.to_string_lossy()),
+    )
+}
+
+fn client_buffile_command(client: &str, out: &Path) -> String {
+    format!(
+        "evaluate-commands -client {} %{{ echo -to-file {} -quoting kakoune -- %val{{buffile}} }}",
+        quote(client),
+        quote(&out.to_string_lossy()),
+    )
+}
+
+fn client_list_command(out: &Path) -> String {
+    format!(
+        "echo -to-file {} -quoting kakoune -- %val{{client_list}}",
+        quote(&out.to_string_lossy())
+    )
+}
+
+fn buflist_command(out: &Path) -> String {
+    format!(
+        "echo -to-file {} -quoting kakoune -- %val{{buflist}}",
+        quote(&out.to_string_lossy())
+    )
+}
+
+/// Kakoune's own buffers (`*debug*`, `*scratch*`) aren't files
+fn is_scratch_buffer(name: &str) -> bool {
+    name.len() > 1 && name.starts_with('*') && name.ends_with('*')
+}
+
+fn refresh_command(file_path: &str) -> String {
+    format!(
+        "try %{{ evaluate-commands -buffer {} %{{ edit! }} }}",
+        quote(&utils::canonical(file_path))
+    )
+}
+
+fn message_command(clients: &[String], message: &str, level: NotifyLevel) -> String {
+    let face = match level {
+        NotifyLevel::Error => "Error",
+        NotifyLevel::Info | NotifyLevel::Warn => "Information",
+    };
+    clients
+        .iter()
+        .map(|client| {
+            format!(
+                "evaluate-commands -client {} %{{ echo -markup {} }}",
+                quote(client),
+                quote(&format!("{{{}}}{}", face, message.replace('{', "\\{")))
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Send commands to a session and wait for the answer written to `out`
+fn query(session: &str, command: &str, out: &Path) -> Result<Vec<String>> {
+    send(session, command)?;
+
+    let deadline = Instant::now() + rpc_timeout(KAKOUNE_TIMEOUT);
+    loop {
+        if let Ok(content) = std::fs::read_to_string(out) {
+            let _ = std::fs::remove_file(out);
+            return Ok(parse_quoted(&content));
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!("Kakoune session {} didn't answer", session);
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Whether a session's socket is missing or refuses: the session has
+/// exited, as opposed to failing to answer
+fn session_gone(session: &str) -> bool {
+    std::os::unix::net::UnixStream::connect(utils::kak_socket_dir().join(session)).is_err_and(|e| {
+        matches!(
+            e.kind(),
+            std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::NotFound
+        )
+    })
+}
+
+/// Pipe commands to a session through `kak -p`
+fn send(session: &str, command: &str) -> Result<()> {
+    let mut child = Command::new("kak")
+        .arg("-p")
+        .arg(session)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("couldn't run kak")?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(command.as_bytes())
+            .context("couldn't send to Kakoune")?;
+    }
+
+    let status = child.wait().context("couldn't send to Kakoune")?;
+    if !status.success() {
+        anyhow::bail!("couldn't reach Kakoune session {}", session);
+    }
+    Ok(())
+}
+
+/// Quote a string for Kakoune: single quotes, embedded quotes doubled
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Parse `echo -quoting kakoune` output back into its words
+fn parse_quoted(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chars = s.trim().chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\'' {
+            continue;
+        }
+        let mut word = String::new();
+        while let Some(c) = chars.next() {
+            if c == '\'' {
+                if chars.peek() == Some(&'\'') {
+                    chars.next();
+                    word.push('\'');
+                } else {
+                    break;
+                }
+            } else {
+                word.push(c);
+            }
+        }
+        words.push(word);
+    }
+
+    words
+}
+
+/// A fresh path for Kakoune to write one answer to
+fn scratch_path() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "sidekick-kak-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_and_parses_round_trip() {
+        let words = ["client0", "it's", "", "a b"];
+        let line = words.iter().map(|w| quote(w)).collect::<Vec<_>>().join(" ");
+        assert_eq!(line, "'client0' 'it''s' '' 'a b'");
+        assert_eq!(parse_quoted(&line), words);
+    }
+
+    #[test]
+    fn status_command_scopes_to_buffer_and_reports_missing() {
+        let command = status_command("/p/it's.rs", Path::new("/tmp/out"));
+        assert!(command.contains("evaluate-commands -buffer '/p/it''s.rs'"));
+        assert!(command.contains("%val{modified} %val{client_list}"));
+        assert!(
+            command.contains("catch %{ echo -to-file '/tmp/out' -quoting kakoune -- missing }")
+        );
+    }
+
+    #[test]
+    fn message_command_targets_every_client() {
+        let clients = vec!["client0".to_string(), "client1".to_string()];
+        let command = message_command(&clients, "Edit blocked", NotifyLevel::Warn);
+        assert_eq!(command.lines().count(), 2);
+        assert!(
+            command.contains("-client 'client1' %{ echo -markup '{Information}Edit blocked' }")
+        );
+    }
+}
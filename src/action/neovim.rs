@@ -1,66 +1,231 @@
 //! Neovim integration for performing editor actions via RPC.
 //!
 //! This module provides the `NeovimAction` implementation that connects to a running
-//! Neovim instance via Unix socket to check buffer status, refresh buffers, and send messages.
+//! Neovim instance via Unix socket or TCP to check buffer status, refresh buffers, and send messages.
 
 mod buffer;
 mod connection;
 mod lua;
+mod version;
 
-use crate::action::{Action, BufferStatus, EditorContext};
-use anyhow::Result;
+pub use connection::NeovimEndpoint;
+pub use version::NeovimVersion;
+
+use crate::action::{
+    Action, BufferStatus, EditorContext, EditorView, NotifyLevel, settle_statuses,
+};
+use crate::constants::RICH_NOTIFY_DURATION;
+use anyhow::{Context, Result};
 use neovim_lib::NeovimApi;
 use std::path::PathBuf;
 
+/// Per-instance outcome of a refresh
+#[derive(Debug, Default)]
+pub struct RefreshReport {
+    /// Instances that took the request, whether or not they had the file loaded
+    pub succeeded: Vec<NeovimEndpoint>,
+    /// Instances that couldn't be reached or failed to reload, with why
+    pub failed: Vec<(NeovimEndpoint, String)>,
+}
+
+impl RefreshReport {
+    /// Run `refresh` against every instance and record how each went
+    fn collect(
+        endpoints: &[NeovimEndpoint],
+        mut refresh: impl FnMut(&NeovimEndpoint) -> Result<bool>,
+    ) -> Self {
+        let mut report = Self::default();
+        for endpoint in endpoints {
+            match refresh(endpoint) {
+                Ok(_) => report.succeeded.push(endpoint.clone()),
+                Err(e) => report.failed.push((endpoint.clone(), format!("{:#}", e))),
+            }
+        }
+        report
+    }
+}
+
+/// Which buffers get only a cheap `:checktime` on refresh instead of a full
+/// `:edit` reload
+#[derive(Debug, Clone, Default)]
+pub struct RefreshPolicy {
+    /// Every buffer
+    pub checktime_only: bool,
+    /// Buffers of these filetypes
+    pub exclude_filetypes: Vec<String>,
+}
+
+impl RefreshPolicy {
+    /// Whether a buffer of `filetype` skips the full reload
+    pub fn checktime_only_for(&self, filetype: &str) -> bool {
+        self.checktime_only
+            || self
+                .exclude_filetypes
+                .iter()
+                .any(|ft| ft.eq_ignore_ascii_case(filetype))
+    }
+}
+
+/// How a buffer's name is compared with the path the AI gave
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    /// The exact same string
+    Strict,
+    /// The same file once both are canonicalized
+    #[default]
+    Canonical,
+    /// As `Canonical`, falling back to one relative path being a trailing
+    /// part of the other, for buffers named relative to a changed cwd
+    Suffix,
+}
+
 /// Neovim action implementation that supports multiple instances
 pub struct NeovimAction {
-    socket_paths: Vec<PathBuf>,
+    endpoints: Vec<NeovimEndpoint>,
+    refresh_policy: RefreshPolicy,
+    visible_is_current: bool,
+    scan_all: bool,
+    match_mode: MatchMode,
 }
 
 impl NeovimAction {
-    pub fn new(socket_paths: Vec<PathBuf>) -> Self {
-        Self { socket_paths }
+    pub fn new(endpoints: Vec<NeovimEndpoint>) -> Self {
+        Self {
+            endpoints,
+            refresh_policy: RefreshPolicy::default(),
+            visible_is_current: false,
+            scan_all: false,
+            match_mode: MatchMode::default(),
+        }
+    }
+
+    /// Instances listening on Unix sockets, as discovery finds them
+    pub fn from_sockets(socket_paths: Vec<PathBuf>) -> Self {
+        Self::new(socket_paths.into_iter().map(NeovimEndpoint::from).collect())
+    }
+
+    /// Count a buffer shown in any window of the current tab as current,
+    /// not only the focused one
+    pub fn with_visible_as_current(mut self, visible_is_current: bool) -> Self {
+        self.visible_is_current = visible_is_current;
+        self
+    }
+
+    /// Ask every instance in `buffer_status`, even once one has the file
+    /// dirty and current
+    pub fn with_scan_all(mut self, scan_all: bool) -> Self {
+        self.scan_all = scan_all;
+        self
+    }
+
+    /// Compare buffer names with file paths per `mode`
+    pub fn with_match_mode(mut self, mode: MatchMode) -> Self {
+        self.match_mode = mode;
+        self
+    }
+
+    /// Refresh per `policy` instead of always reloading in full
+    pub fn with_refresh_policy(mut self, policy: RefreshPolicy) -> Self {
+        self.refresh_policy = policy;
+        self
+    }
+
+    /// Reload `file_path` in every instance, reporting which ones failed.
+    /// Only instances with the buffer loaded actually reload.
+    pub fn refresh_buffer_detailed(&self, file_path: &str) -> RefreshReport {
+        RefreshReport::collect(&self.endpoints, |endpoint| {
+            let mut nvim = connection::connect(endpoint)?;
+            buffer::refresh_buffer(&mut nvim, file_path, self.match_mode, &self.refresh_policy)
+        })
+    }
+
+    /// Reload sidekick in every instance, reporting which ones failed
+    pub fn reload_plugin_detailed(&self) -> RefreshReport {
+        RefreshReport::collect(&self.endpoints, |endpoint| {
+            connection::connect(endpoint)?
+                .execute_lua(lua::reload_plugin_lua(), vec![])
+                .map(|_| true)
+                .map_err(|e| anyhow::anyhow!("couldn't reload in Neovim: {}", e))
+        })
+    }
+
+    /// `buffer_status`, stopping at the first instance with the file dirty
+    /// and current unless `scan_all`
+    fn buffer_status_scan(&self, file_path: &str, scan_all: bool) -> Result<Vec<BufferStatus>> {
+        let answers = self.endpoints.iter().filter_map(|endpoint| {
+            let status = match connection::connect(endpoint) {
+                Ok(mut nvim) => buffer::get_buffer_status(
+                    &mut nvim,
+                    file_path,
+                    self.match_mode,
+                    self.visible_is_current,
+                ),
+                // Nothing listening there: no instance to have the file open
+                Err(e) if connection::is_absent(&e) => return None,
+                Err(e) => Err(e),
+            };
+            Some((endpoint.to_string(), status))
+        });
+        gather_statuses(answers, scan_all)
+    }
+
+    /// Version of each reachable instance, in endpoint order
+    pub fn versions(&self) -> Vec<NeovimVersion> {
+        connection::collect_all(&self.endpoints, |nvim| version::get_version(nvim).map(Some))
     }
 }
 
 impl Action for NeovimAction {
-    fn buffer_status(&self, file_path: &str) -> Result<BufferStatus> {
-        let status = connection::try_fold_instances(
-            &self.socket_paths,
-            (false, false),
-            |(is_current_acc, unsaved_acc), nvim| {
-                let status = buffer::get_buffer_status(nvim, file_path)?;
-
-                *is_current_acc = *is_current_acc || status.is_current;
-                *unsaved_acc = *unsaved_acc || status.has_unsaved_changes;
-
-                // Early exit if we found unsaved changes
-                Ok(!*unsaved_acc)
-            },
-        )
-        .unwrap_or((false, false));
+    fn buffer_status(&self, file_path: &str) -> Result<Vec<BufferStatus>> {
+        self.buffer_status_scan(file_path, self.scan_all)
+    }
 
-        Ok(BufferStatus {
-            is_current: status.0,
-            has_unsaved_changes: status.1,
+    fn is_open(&self, file_path: &str) -> Result<bool> {
+        // Names only: no current-buffer or modified-flag round trips
+        let mut failure = None;
+        for endpoint in &self.endpoints {
+            let lookup = match connection::connect(endpoint) {
+                Ok(mut nvim) => buffer::find_buffers(&mut nvim, file_path, self.match_mode),
+                Err(e) if connection::is_absent(&e) => continue,
+                Err(e) => buffer::BufferLookup::Err(e),
+            };
+            match lookup {
+                buffer::BufferLookup::Found(_) => return Ok(true),
+                buffer::BufferLookup::NotOpen => {}
+                buffer::BufferLookup::Err(e) => {
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+        failure.map_or(Ok(false), |e| {
+            Err(e.context("couldn't query every Neovim instance"))
         })
     }
 
-    fn refresh_buffer(&self, file_path: &str) -> Result<()> {
-        let any_success = connection::for_each_instance(&self.socket_paths, |nvim| {
-            buffer::refresh_buffer(nvim, file_path)
-        });
+    fn buffer_contents(&self, file_path: &str) -> Result<Vec<String>> {
+        Ok(connection::collect_all(&self.endpoints, |nvim| {
+            buffer::get_buffer_contents(nvim, file_path, self.match_mode)
+        }))
+    }
 
-        if any_success {
-            Ok(())
-        } else {
-            anyhow::bail!("couldn't refresh Neovim")
+    fn refresh_buffer(&self, file_path: &str) -> Result<()> {
+        let report = self.refresh_buffer_detailed(file_path);
+        if !report.succeeded.is_empty() {
+            return Ok(());
         }
+
+        let reasons: Vec<String> = report
+            .failed
+            .iter()
+            .map(|(endpoint, reason)| format!("{}: {}", endpoint, reason))
+            .collect();
+        anyhow::bail!("couldn't refresh Neovim ({})", reasons.join("; "))
     }
 
-    fn send_message(&self, message: &str) -> Result<()> {
-        let lua_code = lua::send_notification_lua(message);
-        let any_success = connection::for_each_instance(&self.socket_paths, |nvim| {
+    fn send_message(&self, message: &str, level: NotifyLevel) -> Result<()> {
+        let lua_code = lua::send_notification_lua(message, level);
+        let any_success = connection::for_each_instance(&self.endpoints, |nvim| {
             nvim.execute_lua(&lua_code, vec![])
                 .map(|_| ())
                 .map_err(|e| anyhow::anyhow!("couldn't send to Neovim: {}", e))
@@ -73,9 +238,237 @@ impl Action for NeovimAction {
         }
     }
 
-    fn get_visual_selections(&self) -> Result<Vec<EditorContext>> {
-        Ok(connection::collect_all(&self.socket_paths, |nvim| {
-            buffer::get_visual_selection(nvim)
+    fn send_message_to(&self, instance: &str, message: &str, level: NotifyLevel) -> Result<()> {
+        let endpoint = self
+            .endpoints
+            .iter()
+            .find(|endpoint| endpoint.to_string() == instance)
+            .with_context(|| format!("couldn't find Neovim instance {}", instance))?;
+        connection::connect(endpoint)?
+            .execute_lua(&lua::send_notification_lua(message, level), vec![])
+            .map(|_| ())
+            .map_err(|e| anyhow::anyhow!("couldn't send to Neovim: {}", e))
+    }
+
+    fn notify_rich(&self, title: &str, body: &str) -> Result<()> {
+        let lua_code = lua::rich_notification_lua(RICH_NOTIFY_DURATION.as_millis() as u64);
+        let sent =
+            connection::try_fold_instances(&self.endpoints, false, |sent, endpoint, nvim| {
+                // Titled floats need 0.9; older instances get the plain message
+                if !version::cached_version(endpoint, nvim)?.supports_float_title() {
+                    let message = format!("{}: {}", title, body.replace('\n', " "));
+                    nvim.execute_lua(
+                        &lua::send_notification_lua(&message, NotifyLevel::Warn),
+                        vec![],
+                    )
+                } else {
+                    nvim.execute_lua(&lua_code, vec![title.into(), body.into()])
+                }
+                .map_err(|e| anyhow::anyhow!("couldn't send to Neovim: {}", e))?;
+                // One instance showing it is enough
+                *sent = true;
+                Ok(false)
+            });
+
+        if sent == Some(true) {
+            Ok(())
+        } else {
+            anyhow::bail!("couldn't send to Neovim")
+        }
+    }
+
+    fn open_at_revision(&self, file_path: &str, rev: &str) -> Result<()> {
+        let mut failure = None;
+        let opened = connection::for_each_instance(&self.endpoints, |nvim| {
+            nvim.execute_lua(
+                lua::open_at_revision_lua(),
+                vec![file_path.into(), rev.into()],
+            )
+            .map(|_| ())
+            .map_err(|e| {
+                let e = anyhow::anyhow!("couldn't open {}@{} in Neovim: {}", file_path, rev, e);
+                failure.get_or_insert(e.to_string());
+                e
+            })
+        });
+
+        match (opened, failure) {
+            (true, _) => Ok(()),
+            (false, Some(reason)) => anyhow::bail!(reason),
+            (false, None) => anyhow::bail!("couldn't reach Neovim"),
+        }
+    }
+
+    fn reload_plugin(&self) -> Result<()> {
+        let report = self.reload_plugin_detailed();
+        if !report.succeeded.is_empty() {
+            return Ok(());
+        }
+
+        let reasons: Vec<String> = report
+            .failed
+            .iter()
+            .map(|(endpoint, reason)| format!("{}: {}", endpoint, reason))
+            .collect();
+        anyhow::bail!("couldn't reload Neovim ({})", reasons.join("; "))
+    }
+
+    fn set_status(&self, active: bool, detail: Option<String>) -> Result<()> {
+        // Every instance shows it, not just the first that answers
+        let detail = detail.map_or(neovim_lib::Value::Nil, Into::into);
+        let report = RefreshReport::collect(&self.endpoints, |endpoint| {
+            connection::connect(endpoint)?
+                .execute_lua(lua::set_status_lua(), vec![active.into(), detail.clone()])
+                .map(|_| true)
+                .map_err(|e| anyhow::anyhow!("couldn't set status in Neovim: {}", e))
+        });
+
+        if report.succeeded.is_empty() {
+            anyhow::bail!("couldn't set status in Neovim")
+        }
+        Ok(())
+    }
+
+    fn list_buffers(&self) -> Result<Vec<String>> {
+        let names =
+            connection::collect_all(&self.endpoints, |nvim| buffer::list_buffers(nvim).map(Some));
+        Ok(names.into_iter().flatten().collect())
+    }
+
+    fn get_visual_selections(&self, max_bytes: Option<usize>) -> Result<Vec<EditorContext>> {
+        Ok(connection::collect_all(&self.endpoints, |nvim| {
+            buffer::get_visual_selection(nvim, max_bytes)
         }))
     }
+
+    fn is_focused(&self) -> Result<bool> {
+        // Instances that can't be asked don't count against focus
+        Ok(
+            connection::try_fold_instances(&self.endpoints, false, |focused, _, nvim| {
+                *focused = buffer::is_focused(nvim)?;
+                Ok(!*focused)
+            })
+            .unwrap_or(true),
+        )
+    }
+
+    fn current_views(&self) -> Result<Vec<EditorView>> {
+        Ok(connection::collect_all(
+            &self.endpoints,
+            buffer::get_current_view,
+        ))
+    }
+}
+
+/// Statuses from each reachable instance's answer, pulled in turn. Unless
+/// `scan_all`, stops pulling at the first with the file dirty and current:
+/// no other instance can overturn it.
+fn gather_statuses(
+    answers: impl Iterator<Item = (String, Result<Option<BufferStatus>>)>,
+    scan_all: bool,
+) -> Result<Vec<BufferStatus>> {
+    let mut failure = None;
+    let mut statuses = Vec::new();
+    for (instance, answer) in answers {
+        let mut status = match answer {
+            Ok(Some(status)) => status,
+            Ok(None) => continue,
+            Err(e) => {
+                failure.get_or_insert(e);
+                continue;
+            }
+        };
+        status.instance = Some(instance);
+        let dirty_and_current = status.has_unsaved_changes && status.is_current;
+        statuses.push(status);
+        if dirty_and_current && !scan_all {
+            break;
+        }
+    }
+
+    settle_statuses(statuses, failure, "couldn't query every Neovim instance")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NeovimAction, NeovimEndpoint, RefreshReport, gather_statuses};
+    use crate::action::BufferStatus;
+    use std::cell::Cell;
+    use std::path::PathBuf;
+
+    #[test]
+    fn reload_is_sent_to_every_instance() {
+        let dir = std::env::temp_dir().join(format!("sidekick-reload-{}", std::process::id()));
+        let endpoints: Vec<_> = ["a.sock", "b.sock"]
+            .iter()
+            .map(|name| NeovimEndpoint::from(dir.join(name)))
+            .collect();
+
+        // Neither is listening, so each one's attempt shows up as a failure
+        let report = NeovimAction::new(endpoints.clone()).reload_plugin_detailed();
+        let tried: Vec<_> = report.failed.iter().map(|(e, _)| e.clone()).collect();
+        assert_eq!(tried, endpoints);
+        assert!(report.succeeded.is_empty());
+    }
+
+    #[test]
+    fn scan_all_keeps_asking_after_a_dirty_current_instance() {
+        let status = |is_current, has_unsaved_changes| BufferStatus {
+            is_current,
+            has_unsaved_changes,
+            instance: None,
+        };
+        let answers = [
+            status(true, true),
+            status(false, false),
+            status(true, false),
+        ];
+        let asked = Cell::new(0);
+        let answer_all = || {
+            asked.set(0);
+            answers.iter().enumerate().map(|(i, s)| {
+                asked.set(asked.get() + 1);
+                (format!("nvim-{i}"), Ok(Some(s.clone())))
+            })
+        };
+
+        let early = gather_statuses(answer_all(), false).unwrap();
+        assert_eq!((early.len(), asked.get()), (1, 1));
+
+        let all = gather_statuses(answer_all(), true).unwrap();
+        assert_eq!(asked.get(), 3);
+        let instances: Vec<_> = all.iter().filter_map(|s| s.instance.as_deref()).collect();
+        assert_eq!(instances, ["nvim-0", "nvim-1", "nvim-2"]);
+    }
+
+    #[test]
+    fn refresh_report_separates_failed_instances() {
+        let a = NeovimEndpoint::from(PathBuf::from("/tmp/a.sock"));
+        let b = NeovimEndpoint::from(PathBuf::from("/tmp/b.sock"));
+        let report = RefreshReport::collect(&[a.clone(), b.clone()], |endpoint| {
+            if *endpoint == a {
+                anyhow::bail!("couldn't connect to Neovim")
+            }
+            Ok(true)
+        });
+
+        assert_eq!(report.succeeded, [b]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, a);
+        assert!(report.failed[0].1.contains("couldn't connect"));
+    }
+
+    #[test]
+    fn builds_from_sockets_or_mixed_endpoints() {
+        let socket = PathBuf::from("/tmp/a.sock");
+        let from_sockets = NeovimAction::from_sockets(vec![socket.clone()]);
+        assert_eq!(
+            from_sockets.endpoints,
+            [NeovimEndpoint::Unix(socket.clone())]
+        );
+
+        let addr = "127.0.0.1:6666".parse().unwrap();
+        let mixed = NeovimAction::new(vec![socket.into(), NeovimEndpoint::Tcp(addr)]);
+        assert_eq!(mixed.endpoints[1].to_string(), "127.0.0.1:6666");
+    }
 }
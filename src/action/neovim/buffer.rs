@@ -1,71 +1,367 @@
 //! Buffer operations for Neovim instances.
 
-use super::lua;
-use crate::action::{BufferStatus, EditorContext};
+use super::{MatchMode, RefreshPolicy, lua};
+use crate::action::{BufferStatus, EditorContext, EditorView};
+use crate::utils;
 use anyhow::{Context, Result};
-use neovim_lib::{Neovim, NeovimApi, neovim_api::Buffer};
-use std::path::PathBuf;
+use neovim_lib::{Neovim, NeovimApi, Value, neovim_api::Buffer};
+use std::path::Path;
+use std::process::Command;
 
-/// Find buffer by file path
-pub fn find_buffer(nvim: &mut Neovim, file_path: &str) -> Result<Buffer> {
-    let buffers = nvim.list_bufs().context("couldn't list buffers")?;
+/// Outcome of looking a file up among an instance's buffers. Not having the
+/// file open is an answer; failing to find out is not.
+#[derive(Debug)]
+pub enum BufferLookup<B = Buffer> {
+    /// Every buffer holding the file, never empty. Usually one, but a plugin
+    /// or a renamed file can leave two buffers on the same path.
+    Found(Vec<B>),
+    NotOpen,
+    Err(anyhow::Error),
+}
 
-    let target_path = PathBuf::from(file_path)
-        .canonicalize()
-        .unwrap_or_else(|_| PathBuf::from(file_path));
+/// Find the buffers holding a file path, comparing names per `mode`
+pub fn find_buffers(nvim: &mut Neovim, file_path: &str, mode: MatchMode) -> BufferLookup {
+    let buffers = match nvim.list_bufs().context("couldn't list buffers") {
+        Ok(buffers) => buffers,
+        Err(e) => return BufferLookup::Err(e),
+    };
 
-    for buffer in buffers {
-        let buf_name = buffer.get_name(nvim).context("couldn't read buffer name")?;
+    let candidates: Vec<_> = buffers
+        .into_iter()
+        .map(|buffer| {
+            let name = file_name(nvim, &buffer);
+            (buffer, name)
+        })
+        .collect();
+
+    match_buffer(candidates, file_path, mode)
+}
+
+/// The file a buffer holds, `None` for unnamed and special buffers
+fn file_name(nvim: &mut Neovim, buffer: &Buffer) -> Result<Option<String>> {
+    let name = buffer.get_name(nvim).context("couldn't read buffer name")?;
+    if name.is_empty() || !is_file_buffer(nvim, buffer)? {
+        return Ok(None);
+    }
+    Ok(Some(name))
+}
+
+/// Pick the buffers holding `file_path`, comparing names per `mode`. A
+/// buffer whose name couldn't be read might have been it, so with no match
+/// that makes the lookup an error rather than `NotOpen`.
+fn match_buffer<B>(
+    candidates: impl IntoIterator<Item = (B, Result<Option<String>>)>,
+    file_path: &str,
+    mode: MatchMode,
+) -> BufferLookup<B> {
+    let target_path = utils::normalize_path(Path::new(file_path));
+
+    let mut found = Vec::new();
+    let mut by_suffix = Vec::new();
+    let mut failure = None;
+    for (buffer, name) in candidates {
+        let buf_name = match name {
+            Ok(Some(name)) => name,
+            Ok(None) => continue,
+            Err(e) => {
+                failure.get_or_insert(e);
+                continue;
+            }
+        };
 
-        if buf_name.is_empty() {
+        if mode == MatchMode::Strict {
+            if buf_name == file_path {
+                found.push(buffer);
+            }
             continue;
         }
 
-        let buf_path = PathBuf::from(&buf_name)
-            .canonicalize()
-            .unwrap_or_else(|_| PathBuf::from(&buf_name));
+        let buf_path = utils::normalize_path(Path::new(&buf_name));
+
+        if same_path(&buf_path, &target_path, CASE_INSENSITIVE_FS) {
+            found.push(buffer);
+        } else if mode == MatchMode::Suffix && is_suffix_of_either(&buf_path, &target_path) {
+            by_suffix.push(buffer);
+        }
+    }
+
+    if found.is_empty() {
+        found = by_suffix;
+    }
+    if !found.is_empty() {
+        return BufferLookup::Found(found);
+    }
+    failure.map_or(BufferLookup::NotOpen, BufferLookup::Err)
+}
+
+/// Whether the relative one of two paths is a trailing part of the other,
+/// component by component
+fn is_suffix_of_either(a: &Path, b: &Path) -> bool {
+    (a.is_relative() && b.ends_with(a)) || (b.is_relative() && a.ends_with(b))
+}
+
+/// Whether the platform's default filesystem ignores case: APFS on macOS,
+/// NTFS on Windows
+const CASE_INSENSITIVE_FS: bool = cfg!(any(target_os = "macos", target_os = "windows"));
+
+/// Path equality. Canonicalizing settles case for files that exist, but a
+/// path that doesn't resolve keeps whatever case the caller spelled it in.
+fn same_path(a: &Path, b: &Path, case_insensitive: bool) -> bool {
+    if !case_insensitive {
+        return a == b;
+    }
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+/// Whether a buffer holds a file. Terminal, quickfix, help and other special
+/// buffers set `buftype` and can carry a name that looks like a path.
+fn is_file_buffer(nvim: &mut Neovim, buffer: &Buffer) -> Result<bool> {
+    let buftype = buffer
+        .get_option(nvim, "buftype")
+        .context("couldn't read buftype")?;
+    Ok(is_file_buftype(buftype.as_str().unwrap_or("")))
+}
+
+fn is_file_buftype(buftype: &str) -> bool {
+    buftype.is_empty() || buftype == "acwrite"
+}
+
+/// Names of the listed, file-backed buffers
+pub fn list_buffers(nvim: &mut Neovim) -> Result<Vec<String>> {
+    let buffers = nvim.list_bufs().context("couldn't list buffers")?;
 
-        if buf_path == target_path {
-            return Ok(buffer);
+    let mut names = Vec::new();
+    for buffer in buffers {
+        let listed = buffer
+            .get_option(nvim, "buflisted")?
+            .as_bool()
+            .unwrap_or(false);
+        let name = buffer.get_name(nvim).context("couldn't read buffer name")?;
+        if listed && !name.is_empty() && is_file_buffer(nvim, &buffer)? {
+            names.push(name);
         }
     }
 
-    anyhow::bail!("file not open in Neovim: {}", file_path)
+    Ok(names)
+}
+
+/// Whether the instance's window has focus, as far as it knows
+pub fn is_focused(nvim: &mut Neovim) -> Result<bool> {
+    let focused = nvim
+        .execute_lua(lua::focus_state_lua(), vec![])
+        .context("couldn't read focus state")?;
+    Ok(focused.as_bool().unwrap_or(true))
+}
+
+/// The file in the focused window and the lines on screen, `None` when
+/// that window holds no file
+pub fn get_current_view(nvim: &mut Neovim) -> Result<Option<EditorView>> {
+    let buffer = nvim
+        .get_current_buf()
+        .context("couldn't read current buffer")?;
+    let name = buffer.get_name(nvim).context("couldn't read buffer name")?;
+    if name.is_empty() || !is_file_buffer(nvim, &buffer)? {
+        return Ok(None);
+    }
+
+    let range = nvim
+        .eval("[line('w0'), line('w$')]")
+        .context("couldn't read visible lines")?;
+    let line = |i: usize| {
+        range
+            .as_array()
+            .and_then(|lines| lines.get(i)?.as_u64())
+            .map(|n| n as u32)
+    };
+    Ok(line(0)
+        .zip(line(1))
+        .map(|(first_line, last_line)| EditorView {
+            file_path: name,
+            first_line,
+            last_line,
+        }))
 }
 
-/// Get buffer status (whether it's current and has unsaved changes)
-pub fn get_buffer_status(nvim: &mut Neovim, file_path: &str) -> Result<BufferStatus> {
-    let buffer = find_buffer(nvim, file_path)?;
+/// Get buffer status (whether it's current and has unsaved changes), `None`
+/// if the instance doesn't have the file open. With `visible_is_current`, a
+/// buffer shown in any window of the current tab counts as current. With
+/// several buffers on the file, any one current or modified counts.
+pub fn get_buffer_status(
+    nvim: &mut Neovim,
+    file_path: &str,
+    mode: MatchMode,
+    visible_is_current: bool,
+) -> Result<Option<BufferStatus>> {
+    let buffers = match find_buffers(nvim, file_path, mode) {
+        BufferLookup::Found(buffers) => buffers,
+        BufferLookup::NotOpen => return Ok(None),
+        BufferLookup::Err(e) => return Err(e),
+    };
     let current_buf = nvim.get_current_buf()?;
-    let is_current = buffer == current_buf;
+    let is_current = counts_as_current(&buffers, &current_buf, visible_is_current, || {
+        visible_buffers(nvim)
+    })?;
 
-    let modified = buffer.get_option(nvim, "modified")?;
-    let has_unsaved_changes = modified.as_bool().unwrap_or(false);
+    let has_unsaved_changes = any_modified(&buffers, |buffer| is_modified(nvim, buffer))?;
 
-    Ok(BufferStatus {
+    Ok(Some(BufferStatus {
         is_current,
         has_unsaved_changes,
-    })
+        instance: None,
+    }))
+}
+
+/// Whether one of `buffers` is current: it is the active buffer or, when
+/// visible buffers count, `displayed` (only asked for then) includes it
+fn counts_as_current<B: PartialEq>(
+    buffers: &[B],
+    current: &B,
+    visible_is_current: bool,
+    displayed: impl FnOnce() -> Result<Vec<B>>,
+) -> Result<bool> {
+    if buffers.contains(current) {
+        return Ok(true);
+    }
+    if !visible_is_current {
+        return Ok(false);
+    }
+    let displayed = displayed()?;
+    Ok(buffers.iter().any(|buffer| displayed.contains(buffer)))
+}
+
+/// Whether any of `buffers` has unsaved changes, asking no further once one
+/// does
+fn any_modified<B>(buffers: &[B], mut modified: impl FnMut(&B) -> Result<bool>) -> Result<bool> {
+    for buffer in buffers {
+        if modified(buffer)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
 }
 
-/// Refresh buffer from disk while preserving cursor positions
-pub fn refresh_buffer(nvim: &mut Neovim, file_path: &str) -> Result<()> {
-    let buffer = find_buffer(nvim, file_path)?;
+fn is_modified(nvim: &mut Neovim, buffer: &Buffer) -> Result<bool> {
+    let modified = buffer
+        .get_option(nvim, "modified")
+        .context("couldn't read modified")?;
+    Ok(modified.as_bool().unwrap_or(false))
+}
+
+/// Buffers shown in the windows of the current tab
+fn visible_buffers(nvim: &mut Neovim) -> Result<Vec<Buffer>> {
+    let windows = nvim
+        .get_current_tabpage()
+        .and_then(|tab| tab.list_wins(nvim))
+        .context("couldn't list windows")?;
+    windows
+        .iter()
+        .map(|win| win.get_buf(nvim).context("couldn't read window buffer"))
+        .collect()
+}
+
+/// Buffer text as the user sees it, unsaved changes included, `None` if the
+/// instance doesn't have the file open. Of several buffers on the file, the
+/// first with unsaved changes.
+pub fn get_buffer_contents(
+    nvim: &mut Neovim,
+    file_path: &str,
+    mode: MatchMode,
+) -> Result<Option<String>> {
+    let mut buffers = match find_buffers(nvim, file_path, mode) {
+        BufferLookup::Found(buffers) => buffers,
+        BufferLookup::NotOpen => return Ok(None),
+        BufferLookup::Err(e) => return Err(e),
+    };
+    // One buffer is the norm; only look for the dirty one among several
+    let mut index = 0;
+    if buffers.len() > 1 {
+        for (i, buffer) in buffers.iter().enumerate() {
+            if is_modified(nvim, buffer)? {
+                index = i;
+                break;
+            }
+        }
+    }
+    let buffer = buffers.swap_remove(index);
+    let lines = buffer
+        .get_lines(nvim, 0, -1, false)
+        .context("couldn't read buffer lines")?;
+    Ok(Some(lines.join("\n")))
+}
+
+/// Refresh buffer from disk while preserving cursor positions. Returns
+/// whether anything was reloaded: an instance without the file loaded is
+/// skipped before any Lua runs, sparing it a reload and redraw. Filetypes
+/// the policy excludes only get `:checktime`. Every loaded buffer on the
+/// file is refreshed.
+pub fn refresh_buffer(
+    nvim: &mut Neovim,
+    file_path: &str,
+    mode: MatchMode,
+    policy: &RefreshPolicy,
+) -> Result<bool> {
+    let lookup = find_buffers(nvim, file_path, mode);
+    let buffers = reload_targets(lookup, |buffer| {
+        buffer
+            .is_loaded(nvim)
+            .context("couldn't check whether the buffer is loaded")
+    })?;
+    for buffer in &buffers {
+        reload(nvim, buffer, policy)?;
+    }
+    Ok(!buffers.is_empty())
+}
+
+fn reload(nvim: &mut Neovim, buffer: &Buffer, policy: &RefreshPolicy) -> Result<()> {
     let buf_number = buffer.get_number(nvim)?;
+    // The filetype costs a round trip, so only ask when it could matter
+    let checktime_only = if policy.checktime_only || policy.exclude_filetypes.is_empty() {
+        policy.checktime_only
+    } else {
+        let filetype = buffer
+            .get_option(nvim, "filetype")
+            .context("couldn't read filetype")?;
+        policy.checktime_only_for(filetype.as_str().unwrap_or(""))
+    };
 
-    let lua_code = lua::refresh_buffer_lua(buf_number);
+    let lua_code = lua::refresh_buffer_lua(buf_number, checktime_only);
 
     nvim.execute_lua(&lua_code, vec![])
         .map(|_| ())
         .context("couldn't reload buffer")
 }
 
-/// Get visual selection from current buffer
-pub fn get_visual_selection(nvim: &mut Neovim) -> Result<Option<EditorContext>> {
+/// The buffers to reload: those holding the file that are loaded
+fn reload_targets<B>(
+    lookup: BufferLookup<B>,
+    mut is_loaded: impl FnMut(&B) -> Result<bool>,
+) -> Result<Vec<B>> {
+    match lookup {
+        BufferLookup::Found(buffers) => {
+            let mut loaded = Vec::new();
+            for buffer in buffers {
+                if is_loaded(&buffer)? {
+                    loaded.push(buffer);
+                }
+            }
+            Ok(loaded)
+        }
+        BufferLookup::NotOpen => Ok(Vec::new()),
+        BufferLookup::Err(e) => Err(e),
+    }
+}
+
+/// Get visual selection from current buffer. Text over `max_bytes` stays in
+/// Neovim; only the range comes back.
+pub fn get_visual_selection(
+    nvim: &mut Neovim,
+    max_bytes: Option<usize>,
+) -> Result<Option<EditorContext>> {
     let lua_code = lua::get_visual_selection_lua();
+    let max_bytes = max_bytes.map_or(Value::Nil, |n| Value::from(n as u64));
 
     let result = nvim
-        .execute_lua(lua_code, vec![])
+        .execute_lua(lua_code, vec![max_bytes])
         .context("couldn't read visual selection")?;
 
     // Lua returns nil if no selection, or a JSON string
@@ -74,22 +370,252 @@ pub fn get_visual_selection(nvim: &mut Neovim) -> Result<Option<EditorContext>>
     }
 
     let json_str = result.as_str().context("unexpected response from Neovim")?;
+    let mut context = parse_visual_selection(json_str)?;
+
+    if context.git_branch.is_none() && context.git_sha.is_none() {
+        (context.git_branch, context.git_sha) = git_head(&context.file_path);
+    }
 
+    Ok(Some(context))
+}
+
+/// Parse the JSON the selection Lua returns. `language`, the git fields and
+/// `truncated` are optional so older editor-side payloads still deserialize.
+fn parse_visual_selection(json_str: &str) -> Result<EditorContext> {
     #[derive(serde::Deserialize)]
     struct SelectionData {
         file_path: String,
         start_line: u32,
         end_line: u32,
-        content: String,
+        #[serde(default)]
+        content: Option<String>,
+        #[serde(default)]
+        truncated: bool,
+        #[serde(default)]
+        language: Option<String>,
+        #[serde(default)]
+        git_branch: Option<String>,
+        #[serde(default)]
+        git_sha: Option<String>,
     }
 
     let data: SelectionData =
         serde_json::from_str(json_str).context("couldn't parse visual selection")?;
 
-    Ok(Some(EditorContext {
+    Ok(EditorContext {
         file_path: data.file_path,
         start_line: data.start_line,
         end_line: data.end_line,
         content: data.content,
-    }))
+        truncated: data.truncated,
+        language: data.language,
+        git_branch: data.git_branch,
+        git_sha: data.git_sha,
+    })
+}
+
+/// Branch and commit of the repository containing `file_path`, from a single
+/// `git rev-parse`. Best-effort: outside a repo, or without git, both are `None`.
+fn git_head(file_path: &str) -> (Option<String>, Option<String>) {
+    let dir = Path::new(file_path).parent().unwrap_or(Path::new("."));
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--abbrev-ref", "HEAD", "HEAD"])
+        .output();
+
+    let Ok(output) = output else {
+        return (None, None);
+    };
+    if !output.status.success() {
+        return (None, None);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines().map(str::trim).map(String::from);
+    // `--abbrev-ref` prints a bare `HEAD` when detached
+    let branch = lines.next().filter(|b| !b.is_empty() && b != "HEAD");
+    let sha = lines.next().filter(|s| !s.is_empty());
+    (branch, sha)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BufferLookup, CASE_INSENSITIVE_FS, any_modified, counts_as_current, is_file_buftype,
+        match_buffer, parse_visual_selection, reload_targets, same_path,
+    };
+    use crate::action::neovim::MatchMode;
+    use std::path::Path;
+
+    #[test]
+    fn refresh_skips_instances_without_the_buffer_loaded() {
+        let mut checked = false;
+        let not_open = reload_targets(BufferLookup::<u32>::NotOpen, |_| {
+            checked = true;
+            Ok(true)
+        });
+        assert!(not_open.unwrap().is_empty());
+        assert!(!checked, "no RPC for an instance without the file");
+
+        let unloaded = reload_targets(BufferLookup::Found(vec![7]), |_| Ok(false));
+        assert!(unloaded.unwrap().is_empty());
+
+        let loaded = reload_targets(BufferLookup::Found(vec![7, 8]), |&b| Ok(b == 8));
+        assert_eq!(loaded.unwrap(), [8]);
+    }
+
+    #[test]
+    fn buffer_in_an_inactive_split_counts_as_current_under_the_flag() {
+        // Buffer 2 is shown in a split; buffer 1 has focus
+        let displayed = || Ok(vec![1, 2]);
+        assert!(counts_as_current(&[2], &1, true, displayed).unwrap());
+        assert!(!counts_as_current(&[2], &1, false, displayed).unwrap());
+        assert!(!counts_as_current(&[3], &1, true, displayed).unwrap());
+
+        let unasked = || -> anyhow::Result<Vec<u32>> { panic!("no window RPC without the flag") };
+        assert!(counts_as_current(&[1], &1, true, unasked).unwrap());
+        assert!(!counts_as_current(&[2], &1, false, unasked).unwrap());
+    }
+
+    #[test]
+    fn lookup_finds_open_file_past_special_buffers() {
+        let candidates = vec![
+            (1, Ok(None)),
+            (2, Ok(Some("/p/a.rs".to_string()))),
+            (3, Ok(Some("/p/b.rs".to_string()))),
+        ];
+        assert!(matches!(
+            match_buffer(candidates, "/p/b.rs", MatchMode::Canonical),
+            BufferLookup::Found(found) if found == [3]
+        ));
+    }
+
+    #[test]
+    fn case_only_differences_match_on_case_insensitive_filesystems() {
+        let (claude, buffer) = (Path::new("/p/src/Foo.rs"), Path::new("/p/src/foo.rs"));
+        assert!(same_path(claude, buffer, true));
+        assert!(!same_path(claude, buffer, false));
+
+        let candidates = vec![(1, Ok(Some("/p/src/foo.rs".to_string())))];
+        let found = matches!(
+            match_buffer(candidates, "/p/src/Foo.rs", MatchMode::Canonical),
+            BufferLookup::Found(found) if found == [1]
+        );
+        assert_eq!(found, CASE_INSENSITIVE_FS);
+    }
+
+    #[test]
+    fn two_buffers_on_one_path_are_both_found_and_either_dirties_it() {
+        let candidates = vec![
+            (1, Ok(Some("/p/a.rs".to_string()))),
+            (2, Ok(Some("/p/b.rs".to_string()))),
+            (3, Ok(Some("/p/a.rs".to_string()))),
+        ];
+        let BufferLookup::Found(buffers) =
+            match_buffer(candidates, "/p/a.rs", MatchMode::Canonical)
+        else {
+            panic!("expected both buffers");
+        };
+        assert_eq!(buffers, [1, 3]);
+
+        // The first match is clean; the second holds the unsaved changes
+        assert!(any_modified(&buffers, |&b| Ok(b == 3)).unwrap());
+        assert!(!any_modified(&buffers, |_| Ok(false)).unwrap());
+        // Another window on the second buffer being focused makes it current
+        assert!(counts_as_current(&buffers, &3, false, || Ok(vec![])).unwrap());
+    }
+
+    #[test]
+    fn match_mode_sets_how_loosely_names_compare() {
+        let lookup = |name: &str, target: &str, mode| {
+            let candidates = vec![(1, Ok(Some(name.to_string())))];
+            matches!(
+                match_buffer(candidates, target, mode),
+                BufferLookup::Found(_)
+            )
+        };
+
+        // Same path, spelled differently
+        let (name, target) = ("/p/./src/foo.rs", "/p/src/foo.rs");
+        assert!(!lookup(name, target, MatchMode::Strict));
+        assert!(lookup(name, target, MatchMode::Canonical));
+        assert!(lookup(name, target, MatchMode::Suffix));
+
+        // A file not written yet can't be canonicalized, so `..` folds as text
+        let (name, target) = ("src/new.rs", "./src/../src/new.rs");
+        assert!(!lookup(name, target, MatchMode::Strict));
+        assert!(lookup(name, target, MatchMode::Canonical));
+
+        // Buffer named relative to a cwd that has since moved
+        let (name, target) = ("src/foo.rs", "/p/src/foo.rs");
+        assert!(!lookup(name, target, MatchMode::Strict));
+        assert!(!lookup(name, target, MatchMode::Canonical));
+        assert!(lookup(name, target, MatchMode::Suffix));
+
+        assert!(lookup(target, target, MatchMode::Strict));
+        assert!(!lookup("/q/src/foo.rs", target, MatchMode::Suffix));
+    }
+
+    #[test]
+    fn lookup_reports_not_open_when_every_buffer_was_read() {
+        let candidates = vec![(1, Ok(Some("/p/a.rs".to_string()))), (2, Ok(None))];
+        assert!(matches!(
+            match_buffer(candidates, "/p/b.rs", MatchMode::Canonical),
+            BufferLookup::NotOpen
+        ));
+    }
+
+    #[test]
+    fn lookup_fails_when_an_unreadable_buffer_might_be_the_file() {
+        let candidates = vec![
+            (1, Ok(Some("/p/a.rs".to_string()))),
+            (2, Err(anyhow::anyhow!("couldn't read buffer name"))),
+        ];
+        assert!(matches!(
+            match_buffer(candidates, "/p/b.rs", MatchMode::Canonical),
+            BufferLookup::Err(_)
+        ));
+    }
+
+    #[test]
+    fn only_file_buftypes_are_protectable() {
+        assert!(is_file_buftype(""));
+        assert!(is_file_buftype("acwrite"));
+        for special in ["terminal", "quickfix", "help", "nofile", "prompt"] {
+            assert!(!is_file_buftype(special), "{special}");
+        }
+    }
+
+    #[test]
+    fn parses_selection_without_optional_fields() {
+        let json = r#"{"file_path":"/p/a.rs","start_line":3,"end_line":5,"content":"fn a() {}"}"#;
+        let ctx = parse_visual_selection(json).unwrap();
+        assert_eq!(ctx.file_path, "/p/a.rs");
+        assert_eq!((ctx.start_line, ctx.end_line), (3, 5));
+        assert_eq!(ctx.content.as_deref(), Some("fn a() {}"));
+        assert!(!ctx.truncated);
+        assert_eq!(ctx.language, None);
+        assert_eq!(ctx.git_branch, None);
+        assert_eq!(ctx.git_sha, None);
+    }
+
+    #[test]
+    fn parses_selection_elided_for_size() {
+        let json = r#"{"file_path":"/p/a.rs","start_line":1,"end_line":90000,"truncated":true}"#;
+        let ctx = parse_visual_selection(json).unwrap();
+        assert_eq!((ctx.start_line, ctx.end_line), (1, 90000));
+        assert_eq!(ctx.content, None);
+        assert!(ctx.truncated);
+    }
+
+    #[test]
+    fn parses_selection_with_language_and_git() {
+        let json = r#"{"file_path":"/p/a.rs","start_line":1,"end_line":1,"content":"x",
+            "language":"rust","git_branch":"main","git_sha":"abc123"}"#;
+        let ctx = parse_visual_selection(json).unwrap();
+        assert_eq!(ctx.language.as_deref(), Some("rust"));
+        assert_eq!(ctx.git_branch.as_deref(), Some("main"));
+        assert_eq!(ctx.git_sha.as_deref(), Some("abc123"));
+    }
 }
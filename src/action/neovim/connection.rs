@@ -1,62 +1,201 @@
 //! Neovim connection management and multi-instance operations.
 
-use crate::constants::NEOVIM_RPC_TIMEOUT;
+use crate::constants::{NEOVIM_CONNECT_BACKOFF, NEOVIM_RPC_TIMEOUT, rpc_timeout};
+use crate::utils;
 use anyhow::{Context, Result};
 use neovim_lib::{Neovim, Session};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
-/// Connect to Neovim via Unix socket and return Neovim client
-pub fn connect(socket_path: &PathBuf) -> Result<Neovim> {
-    let mut session =
-        Session::new_unix_socket(socket_path).context("couldn't connect to Neovim")?;
-    session.set_timeout(NEOVIM_RPC_TIMEOUT);
+/// Where a Neovim instance listens for RPC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NeovimEndpoint {
+    /// A Unix socket, as `sidekick neovim` launches with
+    Unix(PathBuf),
+    /// A TCP address, as `nvim --listen host:port` serves
+    Tcp(SocketAddr),
+}
+
+impl From<PathBuf> for NeovimEndpoint {
+    fn from(path: PathBuf) -> Self {
+        Self::Unix(path)
+    }
+}
+
+impl From<SocketAddr> for NeovimEndpoint {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Tcp(addr)
+    }
+}
+
+impl fmt::Display for NeovimEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unix(path) => write!(f, "{}", path.display()),
+            Self::Tcp(addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
+/// Connect to the Neovim at `endpoint` and return Neovim client.
+///
+/// There is no explicit close: `neovim_lib` owns the stream and its event
+/// loop thread keeps a reader clone, neither of which is reachable from
+/// here. Neovim sees the connection end when the hook process exits, which
+/// follows within milliseconds, so there's no long-lived half-open client.
+///
+/// A refused connection is retried per `NEOVIM_CONNECT_BACKOFF`. Once
+/// connected there's no second retry: an nvim still starting up queues the
+/// first request and answers it within `NEOVIM_RPC_TIMEOUT`. A socket that
+/// still refuses goes in the dead-socket cache.
+pub fn connect(endpoint: &NeovimEndpoint) -> Result<Neovim> {
+    let session = retry_refused(|| match endpoint {
+        NeovimEndpoint::Unix(path) => Session::new_unix_socket(path),
+        NeovimEndpoint::Tcp(addr) => Session::new_tcp(&addr.to_string()),
+    });
+    if let (Err(e), NeovimEndpoint::Unix(path)) = (&session, endpoint)
+        && e.kind() == io::ErrorKind::ConnectionRefused
+    {
+        utils::record_dead_socket(path);
+    }
+    let mut session = session.context("couldn't connect to Neovim")?;
+    session.set_timeout(rpc_timeout(NEOVIM_RPC_TIMEOUT));
     session.start_event_loop();
     Ok(Neovim::new(session))
 }
 
+/// Whether `connect` failed because nothing is there: the socket is gone or
+/// refuses. That instance has exited rather than failed to answer.
+pub fn is_absent(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<io::Error>().is_some_and(|e| {
+        matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::NotFound
+        )
+    })
+}
+
+/// Run `attempt`, retrying after each backoff pause while it's refused
+fn retry_refused<T>(mut attempt: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    for pause in NEOVIM_CONNECT_BACKOFF {
+        match attempt() {
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => std::thread::sleep(pause),
+            other => return other,
+        }
+    }
+    attempt()
+}
+
 /// Execute a closure for each successfully connected Neovim instance
 /// Returns whether any instance was successfully processed
-pub fn for_each_instance<F>(socket_paths: &[PathBuf], mut f: F) -> bool
+pub fn for_each_instance<F>(endpoints: &[NeovimEndpoint], mut f: F) -> bool
 where
     F: FnMut(&mut Neovim) -> Result<()>,
 {
-    socket_paths
+    endpoints
         .iter()
-        .filter_map(|path| connect(path).ok())
+        .filter_map(|endpoint| connect(endpoint).ok())
         .any(|mut nvim| f(&mut nvim).is_ok())
 }
 
 /// Fold over successfully connected Neovim instances with early exit support
 /// Returns None if no instances were processed, otherwise returns the accumulated value
 /// Closure updates accumulator in place and returns whether to continue
-pub fn try_fold_instances<T, F>(socket_paths: &[PathBuf], init: T, mut f: F) -> Option<T>
+pub fn try_fold_instances<T, F>(endpoints: &[NeovimEndpoint], init: T, mut f: F) -> Option<T>
 where
-    F: FnMut(&mut T, &mut Neovim) -> Result<bool>,
+    F: FnMut(&mut T, &NeovimEndpoint, &mut Neovim) -> Result<bool>,
 {
     let mut any_processed = false;
 
-    let result = socket_paths
+    let result = endpoints
         .iter()
-        .filter_map(|path| connect(path).ok())
-        .try_fold(init, |mut acc, mut nvim| match f(&mut acc, &mut nvim) {
-            Ok(should_continue) => {
-                any_processed = true;
-                if should_continue { Ok(acc) } else { Err(acc) }
+        .filter_map(|endpoint| Some((endpoint, connect(endpoint).ok()?)))
+        .try_fold(init, |mut acc, (endpoint, mut nvim)| {
+            match f(&mut acc, endpoint, &mut nvim) {
+                Ok(should_continue) => {
+                    any_processed = true;
+                    if should_continue { Ok(acc) } else { Err(acc) }
+                }
+                Err(_) => Ok(acc),
             }
-            Err(_) => Ok(acc),
         });
 
     any_processed.then(|| result.unwrap_or_else(|acc| acc))
 }
 
 /// Collect all non-None results from all Neovim instances
-pub fn collect_all<T, F>(socket_paths: &[PathBuf], mut f: F) -> Vec<T>
+pub fn collect_all<T, F>(endpoints: &[NeovimEndpoint], mut f: F) -> Vec<T>
 where
     F: FnMut(&mut Neovim) -> Result<Option<T>>,
 {
-    socket_paths
+    endpoints
         .iter()
-        .filter_map(|path| connect(path).ok())
+        .filter_map(|endpoint| connect(endpoint).ok())
         .filter_map(|mut nvim| f(&mut nvim).ok().flatten())
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::time::Duration;
+
+    #[test]
+    fn connect_waits_for_a_socket_that_starts_listening() {
+        let dir = std::env::temp_dir().join(format!("sidekick-connect-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("nvim.sock");
+        let _ = std::fs::remove_file(&socket);
+
+        // A socket file nobody listens on refuses, like nvim mid-startup
+        drop(UnixListener::bind(&socket).unwrap());
+        assert_eq!(
+            std::os::unix::net::UnixStream::connect(&socket)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::ConnectionRefused
+        );
+
+        let ready = {
+            let socket = socket.clone();
+            let staging = dir.join("staging.sock");
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(10));
+                let listener = UnixListener::bind(&staging).unwrap();
+                std::fs::rename(&staging, &socket).unwrap();
+                listener.accept().unwrap()
+            })
+        };
+
+        assert!(connect(&NeovimEndpoint::Unix(socket.clone())).is_ok());
+        ready.join().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn connect_reaches_unix_and_tcp_endpoints() {
+        let socket =
+            std::env::temp_dir().join(format!("sidekick-endpoint-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let unix = UnixListener::bind(&socket).unwrap();
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let endpoints = [
+            NeovimEndpoint::from(socket.clone()),
+            NeovimEndpoint::from(tcp.local_addr().unwrap()),
+        ];
+        assert!(matches!(endpoints[1], NeovimEndpoint::Tcp(_)));
+        for endpoint in &endpoints {
+            assert!(connect(endpoint).is_ok(), "{}", endpoint);
+        }
+
+        drop(unix);
+        std::fs::remove_file(&socket).unwrap();
+        let closed = tcp.local_addr().unwrap();
+        drop(tcp);
+        assert!(connect(&NeovimEndpoint::Tcp(closed)).is_err());
+    }
+}
@@ -1,10 +1,38 @@
 //! Lua code templates for Neovim buffer operations.
 
-/// Lua code to refresh a buffer while preserving cursor positions across all windows
-pub fn refresh_buffer_lua(buf_number: i64) -> String {
+use crate::action::NotifyLevel;
+
+/// Lua code to refresh a buffer while preserving cursor positions across all
+/// windows. With `checktime_only` the buffer is only checked for changes on
+/// disk, leaving any reload to `autoread`, which is cheap for huge files.
+/// Otherwise `:edit` follows only when `checktime` left the buffer untouched
+/// (`b:changedtick` unchanged) and it still differs from disk, so a no-op
+/// reload doesn't reset marks or fire autocommands (e.g. LSP re-attach).
+pub fn refresh_buffer_lua(buf_number: i64, checktime_only: bool) -> String {
+    let reload = if checktime_only {
+        "vim.cmd('checktime')"
+    } else {
+        r#"local tick = vim.b[buf].changedtick
+            vim.cmd('checktime')
+            if vim.b[buf].changedtick == tick
+                and not vim.deep_equal(
+                    vim.fn.readfile(vim.api.nvim_buf_get_name(buf)),
+                    vim.api.nvim_buf_get_lines(buf, 0, -1, false)
+                )
+            then
+                vim.cmd('edit')
+            end"#
+    };
     format!(
         r#"
         local buf = {}
+
+        -- Only file buffers can be reloaded; terminal, quickfix, help and
+        -- other special buffers are left alone
+        if vim.bo[buf].buftype ~= '' or not vim.bo[buf].modifiable then
+            return
+        end
+
         local cursor_positions = {{}}
         local is_current_buf = vim.api.nvim_get_current_buf() == buf
 
@@ -17,8 +45,7 @@ pub fn refresh_buffer_lua(buf_number: i64) -> String {
 
         -- Refresh the buffer (checktime triggers file change detection)
         vim.api.nvim_buf_call(buf, function()
-            vim.cmd('checktime')
-            vim.cmd('edit')
+            {}
         end)
 
         -- Restore cursor positions
@@ -33,21 +60,174 @@ pub fn refresh_buffer_lua(buf_number: i64) -> String {
             vim.cmd('redraw')
         end
         "#,
-        buf_number
+        buf_number, reload
     )
 }
 
 /// Lua code to send a notification message to Neovim
-pub fn send_notification_lua(message: &str) -> String {
+pub fn send_notification_lua(message: &str, level: NotifyLevel) -> String {
     format!(
-        r#"vim.notify("{}", vim.log.levels.WARN)"#,
-        message.replace('"', r#"\""#)
+        r#"vim.notify("{}", vim.log.levels.{})"#,
+        message.replace('"', r#"\""#),
+        log_level(level)
+    )
+}
+
+/// Name of the `vim.log.levels` constant for a level
+fn log_level(level: NotifyLevel) -> &'static str {
+    match level {
+        NotifyLevel::Info => "INFO",
+        NotifyLevel::Warn => "WARN",
+        NotifyLevel::Error => "ERROR",
+    }
+}
+
+/// Lua code to show a titled message in a small floating window that closes
+/// itself after `close_after_ms`. Title and body are passed as the two Lua
+/// arguments rather than spliced in, so they need no escaping. Falls back to
+/// `vim.notify` when a float can't be opened.
+pub fn rich_notification_lua(close_after_ms: u64) -> String {
+    format!(
+        r#"
+        local title, body = ...
+        local lines = vim.split(body, "\n")
+        local width = #title
+        for _, line in ipairs(lines) do
+            width = math.max(width, vim.fn.strdisplaywidth(line))
+        end
+
+        local ok = pcall(function()
+            local buf = vim.api.nvim_create_buf(false, true)
+            vim.api.nvim_buf_set_lines(buf, 0, -1, false, lines)
+            vim.bo[buf].bufhidden = 'wipe'
+
+            local win = vim.api.nvim_open_win(buf, false, {{
+                relative = 'editor',
+                anchor = 'NE',
+                row = 1,
+                col = vim.o.columns - 1,
+                width = width + 2,
+                height = #lines,
+                style = 'minimal',
+                border = 'rounded',
+                title = ' ' .. title .. ' ',
+                focusable = false,
+                noautocmd = true,
+            }})
+
+            vim.defer_fn(function()
+                if vim.api.nvim_win_is_valid(win) then
+                    vim.api.nvim_win_close(win, true)
+                end
+            end, {})
+        end)
+
+        if not ok then
+            vim.notify(title .. ": " .. body, vim.log.levels.WARN)
+        end
+        "#,
+        close_after_ms
     )
 }
 
-/// Lua code to get visual selection from the current buffer
+/// Lua code returning whether Neovim's window has focus. Focus is tracked
+/// from `FocusGained`/`FocusLost` with autocommands set up on the first
+/// call, so until the terminal reports a change it counts as focused.
+pub fn focus_state_lua() -> &'static str {
+    r#"
+    if vim.g.sidekick_focused == nil then
+        local group = vim.api.nvim_create_augroup('sidekick_focus', { clear = true })
+        vim.api.nvim_create_autocmd('FocusGained', {
+            group = group,
+            callback = function() vim.g.sidekick_focused = true end,
+        })
+        vim.api.nvim_create_autocmd('FocusLost', {
+            group = group,
+            callback = function() vim.g.sidekick_focused = false end,
+        })
+        vim.g.sidekick_focused = true
+    end
+    return vim.g.sidekick_focused
+    "#
+}
+
+/// Lua code to set or clear `g:sidekick_active`, for statuslines to show
+/// while Claude is editing, with `g:sidekick_status` holding the detail.
+/// Takes both as its arguments. Fires `User SidekickStatus` so plugins can
+/// redraw on the change.
+pub fn set_status_lua() -> &'static str {
+    r#"
+    local active, detail = ...
+    vim.g.sidekick_active = active
+    vim.g.sidekick_status = active and detail or nil
+    vim.cmd('redrawstatus!')
+    vim.api.nvim_exec_autocmds('User', { pattern = 'SidekickStatus', modeline = false })
+    "#
+}
+
+/// Lua code to open a file as it was at a git revision, taking the file's
+/// absolute path and the revision as its arguments. With vim-fugitive
+/// loaded that's `:Gedit <rev>:<path>`; otherwise `git show` fills a
+/// read-only scratch buffer named `sidekick://<rev>:<path>`, reused if it is
+/// already open. Paths are from the repository root, as git wants them.
+pub fn open_at_revision_lua() -> &'static str {
+    r#"
+    local path, rev = ...
+    local dir, name = vim.fn.fnamemodify(path, ':h'), vim.fn.fnamemodify(path, ':t')
+    local prefix = vim.fn.systemlist({ 'git', '-C', dir, 'rev-parse', '--show-prefix' })
+    if vim.v.shell_error ~= 0 then
+        error(path .. ' is not in a git repository')
+    end
+    local object = rev .. ':' .. (prefix[1] or '') .. name
+
+    if vim.fn.exists(':Gedit') == 2 then
+        vim.cmd('Gedit ' .. vim.fn.fnameescape(object))
+        return
+    end
+
+    local bufname = 'sidekick://' .. object
+    for _, buf in ipairs(vim.api.nvim_list_bufs()) do
+        if vim.api.nvim_buf_get_name(buf) == bufname then
+            vim.api.nvim_set_current_buf(buf)
+            return
+        end
+    end
+
+    local lines = vim.fn.systemlist({ 'git', '-C', dir, 'show', object })
+    if vim.v.shell_error ~= 0 then
+        error(table.concat(lines, ' '))
+    end
+    local buf = vim.api.nvim_create_buf(true, true)
+    vim.api.nvim_buf_set_lines(buf, 0, -1, false, lines)
+    vim.api.nvim_buf_set_name(buf, bufname)
+    vim.bo[buf].modifiable = false
+    local filetype = vim.filetype.match({ filename = name })
+    if filetype then
+        vim.bo[buf].filetype = filetype
+    end
+    vim.api.nvim_set_current_buf(buf)
+    "#
+}
+
+/// Lua code to reload sidekick's side of an instance: drop the
+/// `sidekick_focus` autocommands and the `g:sidekick_*` state earlier calls
+/// set up, so the next one installs them from the current binary. Nothing
+/// else is touched; other plugins aren't re-sourced.
+pub fn reload_plugin_lua() -> &'static str {
+    r#"
+    pcall(vim.api.nvim_del_augroup_by_name, 'sidekick_focus')
+    vim.g.sidekick_focused = nil
+    vim.g.sidekick_active = nil
+    vim.g.sidekick_status = nil
+    vim.cmd('redrawstatus!')
+    "#
+}
+
+/// Lua code to get visual selection from the current buffer. Takes the
+/// byte cap, or nil, as its argument; past it the text is left out.
 pub fn get_visual_selection_lua() -> &'static str {
     r#"
+    local max_bytes = ...
     local mode = vim.fn.mode()
     local start_pos, end_pos, sel_type
 
@@ -77,16 +257,117 @@ pub fn get_visual_selection_lua() -> &'static str {
     -- getregion handles all visual modes (v, V, Ctrl-V) correctly
     local lines = vim.fn.getregion(start_pos, end_pos, { type = sel_type })
     local content = table.concat(lines, "\n")
+    local truncated = false
+    if max_bytes ~= nil and #content > max_bytes then
+        content = nil
+        truncated = true
+    end
 
     -- Get ordered line numbers
     local start_line = math.min(start_pos[2], end_pos[2])
     local end_line = math.max(start_pos[2], end_pos[2])
 
+    -- Empty filetype means "unknown"; leave it out rather than send ""
+    local filetype = vim.bo.filetype
+    if filetype == "" then
+        filetype = nil
+    end
+
     return vim.fn.json_encode({
         file_path = file_path,
         start_line = start_line,
         end_line = end_line,
-        content = content
+        content = content,
+        truncated = truncated,
+        language = filetype
     })
     "#
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        open_at_revision_lua, refresh_buffer_lua, rich_notification_lua, send_notification_lua,
+        set_status_lua,
+    };
+    use crate::action::neovim::RefreshPolicy;
+    use crate::config::{Config, Verbosity};
+
+    #[test]
+    fn excluded_filetypes_get_checktime_only_lua() {
+        let policy = RefreshPolicy {
+            checktime_only: false,
+            exclude_filetypes: vec!["log".to_string()],
+        };
+
+        let excluded = refresh_buffer_lua(3, policy.checktime_only_for("log"));
+        assert!(excluded.contains("vim.cmd('checktime')"));
+        assert!(!excluded.contains("vim.cmd('edit')"));
+
+        let reloaded = refresh_buffer_lua(3, policy.checktime_only_for("rust"));
+        assert!(reloaded.contains("vim.cmd('edit')"));
+    }
+
+    #[test]
+    fn full_reload_only_edits_when_checktime_changed_nothing() {
+        let lua = refresh_buffer_lua(3, false);
+        let guard = lua.find("if vim.b[buf].changedtick == tick").unwrap();
+        let edit = lua.find("vim.cmd('edit')").unwrap();
+        assert!(lua.find("local tick = vim.b[buf].changedtick").unwrap() < guard);
+        assert!(guard < edit);
+        assert!(lua.contains("vim.fn.readfile"));
+    }
+
+    #[test]
+    fn notify_level_setting_picks_the_lua_level() {
+        for (value, constant) in [
+            (None, "vim.log.levels.WARN"),
+            (Some("info"), "vim.log.levels.INFO"),
+            (Some("ERROR"), "vim.log.levels.ERROR"),
+        ] {
+            let config = Config::from_sources(
+                |name| {
+                    value
+                        .filter(|_| name == "SIDEKICK_NOTIFY_LEVEL")
+                        .map(String::from)
+                },
+                None,
+                None,
+                Verbosity::default(),
+            );
+            let lua = send_notification_lua("Edit blocked", config.notify_level);
+            assert!(lua.ends_with(&format!("{})", constant)), "{lua}");
+        }
+    }
+
+    #[test]
+    fn rich_notification_opens_a_closing_float_with_fallback() {
+        let lua = rich_notification_lua(4000);
+        assert!(lua.contains("local title, body = ..."));
+        assert!(lua.contains("vim.api.nvim_open_win(buf, false, {"));
+        assert!(lua.contains("end, 4000)"));
+        assert!(lua.contains("vim.notify(title .. \": \" .. body"));
+    }
+
+    #[test]
+    fn revision_lua_prefers_fugitive_then_shows_the_blob() {
+        let lua = open_at_revision_lua();
+        assert!(lua.contains("local path, rev = ..."));
+        assert!(lua.contains("local object = rev .. ':' .. (prefix[1] or '') .. name"));
+        let fugitive = lua
+            .find("vim.cmd('Gedit ' .. vim.fn.fnameescape(object))")
+            .unwrap();
+        let show = lua.find("{ 'git', '-C', dir, 'show', object }").unwrap();
+        assert!(fugitive < show);
+        assert!(lua.contains("vim.bo[buf].modifiable = false"));
+    }
+
+    #[test]
+    fn status_lua_sets_and_clears_the_global_from_its_arguments() {
+        let lua = set_status_lua();
+        assert!(lua.contains("local active, detail = ..."));
+        assert!(lua.contains("vim.g.sidekick_active = active"));
+        assert!(lua.contains("vim.g.sidekick_status = active and detail or nil"));
+        assert!(lua.contains("pattern = 'SidekickStatus'"));
+    }
+}
@@ -0,0 +1,185 @@
+//! Neovim version detection for feature-gating version-sensitive APIs.
+//!
+//! A running instance's version can't change, so what a socket reported is
+//! kept in the private state dir, keyed by the socket and the pid in its
+//! name, and later hooks don't ask again.
+
+use super::NeovimEndpoint;
+use crate::utils;
+use anyhow::{Context, Result};
+use neovim_lib::{Neovim, NeovimApi, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Version a Neovim instance reports in `nvim_get_api_info`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct NeovimVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl NeovimVersion {
+    /// Floating windows take a `title` (Neovim 0.9+)
+    pub fn supports_float_title(&self) -> bool {
+        *self >= NeovimVersion::new(0, 9, 0)
+    }
+
+    const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl fmt::Display for NeovimVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Ask an instance for its version
+pub fn get_version(nvim: &mut Neovim) -> Result<NeovimVersion> {
+    let info = nvim
+        .get_api_info()
+        .context("couldn't read Neovim API info")?;
+    parse_api_info(&info).context("unexpected Neovim API info")
+}
+
+/// `get_version`, answered from the cache when this socket's instance was
+/// asked before. Sockets without a pid in their name, and TCP addresses,
+/// are always asked.
+pub fn cached_version(endpoint: &NeovimEndpoint, nvim: &mut Neovim) -> Result<NeovimVersion> {
+    let NeovimEndpoint::Unix(socket) = endpoint else {
+        return get_version(nvim);
+    };
+    let Some(pid) = socket
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(utils::socket_pid)
+    else {
+        return get_version(nvim);
+    };
+    let path = VersionCache::path();
+    let mut cache = path.as_deref().map(VersionCache::load).unwrap_or_default();
+    if let Some(version) = cache.get(socket, pid) {
+        return Ok(version);
+    }
+
+    let version = get_version(nvim)?;
+    if let Some(path) = path
+        && !utils::answer_overdue()
+    {
+        cache.insert(socket, pid, version);
+        // Best-effort: a failed write only means asking again next time
+        let _ = cache.save(&path);
+    }
+    Ok(version)
+}
+
+/// What each socket's instance reported, with the pid that launched it
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VersionCache(BTreeMap<PathBuf, (u32, NeovimVersion)>);
+
+impl VersionCache {
+    fn path() -> Option<PathBuf> {
+        Some(utils::state_dir().ok()?.join("sidekick-nvim-versions.json"))
+    }
+
+    fn load(path: &Path) -> Self {
+        std::fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// The version `socket` reported, if the same process still owns it
+    fn get(&self, socket: &Path, pid: u32) -> Option<NeovimVersion> {
+        self.0
+            .get(socket)
+            .and_then(|&(seen, version)| (seen == pid).then_some(version))
+    }
+
+    fn insert(&mut self, socket: &Path, pid: u32, version: NeovimVersion) {
+        self.0.insert(socket.to_path_buf(), (pid, version));
+    }
+
+    /// Write back, dropping instances that have since exited
+    fn save(&mut self, path: &Path) -> std::io::Result<()> {
+        self.0.retain(|_, (pid, _)| utils::process_alive(*pid));
+        let bytes = serde_json::to_vec(&self.0).map_err(std::io::Error::other)?;
+        utils::write_atomically(path, &bytes)
+    }
+}
+
+/// Version from `nvim_get_api_info`'s `[channel, metadata]` reply
+fn parse_api_info(info: &[Value]) -> Option<NeovimVersion> {
+    let version = lookup(info.get(1)?, "version")?;
+    let part = |key| lookup(version, key)?.as_u64();
+    Some(NeovimVersion::new(
+        part("major")?,
+        part("minor")?,
+        part("patch")?,
+    ))
+}
+
+fn lookup<'a>(map: &'a Value, key: &str) -> Option<&'a Value> {
+    map.as_map()?
+        .iter()
+        .find(|(k, _)| k.as_str() == Some(key))
+        .map(|(_, v)| v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_info(major: u64, minor: u64, patch: u64) -> Vec<Value> {
+        let version = Value::Map(vec![
+            ("major".into(), major.into()),
+            ("minor".into(), minor.into()),
+            ("patch".into(), patch.into()),
+            ("api_level".into(), 11.into()),
+        ]);
+        vec![1.into(), Value::Map(vec![("version".into(), version)])]
+    }
+
+    #[test]
+    fn recorded_version_gates_float_title() {
+        let old = parse_api_info(&api_info(0, 8, 3)).unwrap();
+        let new = parse_api_info(&api_info(0, 10, 1)).unwrap();
+
+        assert_eq!(old.to_string(), "v0.8.3");
+        assert!(!old.supports_float_title());
+        assert!(new.supports_float_title());
+        assert_eq!(parse_api_info(&[1.into()]), None);
+    }
+
+    #[test]
+    fn versions_are_remembered_per_socket_and_pid() {
+        let dir = std::env::temp_dir().join(format!("sidekick-versions-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("versions.json");
+        let live = std::process::id();
+        let socket = dir.join(format!("abc-{}.sock", live));
+        let gone = dir.join("abc-0.sock");
+        let version = NeovimVersion::new(0, 10, 1);
+
+        let mut cache = VersionCache::default();
+        cache.insert(&socket, live, version);
+        cache.insert(&gone, 0, version);
+        cache.save(&path).unwrap();
+        let cache = VersionCache::load(&path);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(cache.get(&socket, live), Some(version));
+        // A new process behind the same path is asked afresh
+        assert_eq!(cache.get(&socket, live + 1), None);
+        // Exited instances are dropped on the way out
+        assert_eq!(cache.get(&gone, 0), None);
+    }
+}
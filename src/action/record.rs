@@ -0,0 +1,352 @@
+//! Recording and replaying editor interactions, for debugging.
+//!
+//! With `SIDEKICK_RECORD_RPC=<path>`, discovery wraps the editors in a
+//! `Recorder` that appends every `Action` call and its outcome to `path`, one
+//! JSON line each:
+//!
+//! ```json
+//! {"call":"buffer_status","args":["/p/main.rs"],"result":{"ok":[...]}}
+//! ```
+//!
+//! With `SIDEKICK_REPLAY_RPC=<path>`, discovery finds no editors but a
+//! `Replay` of such a file, answering the same calls in the same order. A
+//! flaky session can then be reproduced without the editor that misbehaved.
+//! Each hook runs in a process of its own, so how far the replay has got is
+//! kept in `<path>.cursor`: the next hook picks up where the last one left
+//! off. Delete it to replay from the start.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::{Action, BufferStatus, EditorContext, EditorView, NotifyLevel};
+use crate::utils;
+
+/// How a call ended
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Ok(Value),
+    Err(String),
+}
+
+/// One line of a recording
+#[derive(Debug, Serialize, Deserialize)]
+struct Recorded {
+    call: String,
+    #[serde(default)]
+    args: Value,
+    result: Outcome,
+}
+
+/// An `Action` that logs every call to its editors. Recording is
+/// best-effort: a failed write never changes the answer.
+pub struct Recorder {
+    inner: Box<dyn Action>,
+    path: PathBuf,
+}
+
+impl Recorder {
+    pub fn new(inner: Box<dyn Action>, path: PathBuf) -> Self {
+        Self { inner, path }
+    }
+
+    fn record<T: Serialize>(&self, call: &str, args: Value, result: Result<T>) -> Result<T> {
+        let outcome = match &result {
+            Ok(value) => Outcome::Ok(serde_json::to_value(value).unwrap_or_default()),
+            Err(e) => Outcome::Err(format!("{:#}", e)),
+        };
+        let _ = self.append(&Recorded {
+            call: call.to_string(),
+            args,
+            result: outcome,
+        });
+        result
+    }
+
+    fn append(&self, recorded: &Recorded) -> Result<()> {
+        let mut line = serde_json::to_vec(recorded)?;
+        line.push(b'\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        Ok(())
+    }
+}
+
+impl Action for Recorder {
+    fn buffer_status(&self, file_path: &str) -> Result<Vec<BufferStatus>> {
+        let result = self.inner.buffer_status(file_path);
+        self.record("buffer_status", json!([file_path]), result)
+    }
+
+    fn is_open(&self, file_path: &str) -> Result<bool> {
+        let result = self.inner.is_open(file_path);
+        self.record("is_open", json!([file_path]), result)
+    }
+
+    fn buffer_contents(&self, file_path: &str) -> Result<Vec<String>> {
+        let result = self.inner.buffer_contents(file_path);
+        self.record("buffer_contents", json!([file_path]), result)
+    }
+
+    fn refresh_buffer(&self, file_path: &str) -> Result<()> {
+        let result = self.inner.refresh_buffer(file_path);
+        self.record("refresh_buffer", json!([file_path]), result)
+    }
+
+    fn send_message(&self, message: &str, level: NotifyLevel) -> Result<()> {
+        let result = self.inner.send_message(message, level);
+        self.record("send_message", json!([message, level]), result)
+    }
+
+    fn send_message_to(&self, instance: &str, message: &str, level: NotifyLevel) -> Result<()> {
+        let result = self.inner.send_message_to(instance, message, level);
+        self.record("send_message_to", json!([instance, message, level]), result)
+    }
+
+    fn notify_rich(&self, title: &str, body: &str) -> Result<()> {
+        let result = self.inner.notify_rich(title, body);
+        self.record("notify_rich", json!([title, body]), result)
+    }
+
+    fn is_focused(&self) -> Result<bool> {
+        let result = self.inner.is_focused();
+        self.record("is_focused", json!([]), result)
+    }
+
+    fn open_at_revision(&self, file_path: &str, rev: &str) -> Result<()> {
+        let result = self.inner.open_at_revision(file_path, rev);
+        self.record("open_at_revision", json!([file_path, rev]), result)
+    }
+
+    fn reload_plugin(&self) -> Result<()> {
+        let result = self.inner.reload_plugin();
+        self.record("reload_plugin", json!([]), result)
+    }
+
+    fn set_status(&self, active: bool, detail: Option<String>) -> Result<()> {
+        let result = self.inner.set_status(active, detail.clone());
+        self.record("set_status", json!([active, detail]), result)
+    }
+
+    fn list_buffers(&self) -> Result<Vec<String>> {
+        let result = self.inner.list_buffers();
+        self.record("list_buffers", json!([]), result)
+    }
+
+    fn get_visual_selections(&self, max_bytes: Option<usize>) -> Result<Vec<EditorContext>> {
+        let result = self.inner.get_visual_selections(max_bytes);
+        self.record("get_visual_selections", json!([max_bytes]), result)
+    }
+
+    fn current_views(&self) -> Result<Vec<EditorView>> {
+        let result = self.inner.current_views();
+        self.record("current_views", json!([]), result)
+    }
+}
+
+/// An `Action` answering from a recording, call by call in order. A call
+/// other than the one recorded next, or past the end, fails.
+pub struct Replay {
+    calls: RefCell<VecDeque<Recorded>>,
+    /// Calls replayed so far, by this process and earlier ones
+    replayed: Cell<usize>,
+    cursor: PathBuf,
+}
+
+impl Replay {
+    /// Read the recording at `path`, from where the last replay of it left
+    /// off
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("couldn't read recording {}", path.display()))?;
+        let cursor = cursor_path(path);
+        let replayed = std::fs::read_to_string(&cursor)
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(0);
+        let calls = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .skip(replayed)
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("couldn't parse recording {}", path.display()))?;
+        Ok(Self {
+            calls: RefCell::new(calls),
+            replayed: Cell::new(replayed),
+            cursor,
+        })
+    }
+
+    fn next<T: DeserializeOwned>(&self, call: &str) -> Result<T> {
+        let recorded = self
+            .calls
+            .borrow_mut()
+            .pop_front()
+            .with_context(|| format!("couldn't replay {}: recording has ended", call))?;
+        // Best-effort, like recording: a lost cursor only replays again
+        self.replayed.set(self.replayed.get() + 1);
+        let _ = utils::write_atomically(&self.cursor, self.replayed.get().to_string().as_bytes());
+        anyhow::ensure!(
+            recorded.call == call,
+            "couldn't replay {}: {} was recorded next",
+            call,
+            recorded.call
+        );
+        match recorded.result {
+            Outcome::Ok(value) => {
+                serde_json::from_value(value).with_context(|| format!("couldn't replay {}", call))
+            }
+            Outcome::Err(message) => Err(anyhow::anyhow!(message)),
+        }
+    }
+}
+
+/// Where a replay of the recording at `path` keeps its place
+fn cursor_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".cursor");
+    path.with_file_name(name)
+}
+
+impl Action for Replay {
+    fn buffer_status(&self, _file_path: &str) -> Result<Vec<BufferStatus>> {
+        self.next("buffer_status")
+    }
+
+    fn is_open(&self, _file_path: &str) -> Result<bool> {
+        self.next("is_open")
+    }
+
+    fn buffer_contents(&self, _file_path: &str) -> Result<Vec<String>> {
+        self.next("buffer_contents")
+    }
+
+    fn refresh_buffer(&self, _file_path: &str) -> Result<()> {
+        self.next("refresh_buffer")
+    }
+
+    fn send_message(&self, _message: &str, _level: NotifyLevel) -> Result<()> {
+        self.next("send_message")
+    }
+
+    fn send_message_to(&self, _instance: &str, _message: &str, _level: NotifyLevel) -> Result<()> {
+        self.next("send_message_to")
+    }
+
+    fn notify_rich(&self, _title: &str, _body: &str) -> Result<()> {
+        self.next("notify_rich")
+    }
+
+    fn is_focused(&self) -> Result<bool> {
+        self.next("is_focused")
+    }
+
+    fn open_at_revision(&self, _file_path: &str, _rev: &str) -> Result<()> {
+        self.next("open_at_revision")
+    }
+
+    fn reload_plugin(&self) -> Result<()> {
+        self.next("reload_plugin")
+    }
+
+    fn set_status(&self, _active: bool, _detail: Option<String>) -> Result<()> {
+        self.next("set_status")
+    }
+
+    fn list_buffers(&self) -> Result<Vec<String>> {
+        self.next("list_buffers")
+    }
+
+    fn get_visual_selections(&self, _max_bytes: Option<usize>) -> Result<Vec<EditorContext>> {
+        self.next("get_visual_selections")
+    }
+
+    fn current_views(&self) -> Result<Vec<EditorView>> {
+        self.next("current_views")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Editor with one dirty file open that can't be messaged
+    struct Editor;
+
+    impl Action for Editor {
+        fn buffer_status(&self, _file_path: &str) -> Result<Vec<BufferStatus>> {
+            Ok(vec![BufferStatus {
+                is_current: true,
+                has_unsaved_changes: true,
+                instance: Some("/tmp/a.sock".to_string()),
+            }])
+        }
+
+        fn buffer_contents(&self, _file_path: &str) -> Result<Vec<String>> {
+            Ok(vec!["live".to_string()])
+        }
+
+        fn refresh_buffer(&self, _file_path: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn send_message(&self, _message: &str, _level: NotifyLevel) -> Result<()> {
+            anyhow::bail!("couldn't send to Neovim")
+        }
+
+        fn list_buffers(&self) -> Result<Vec<String>> {
+            Ok(vec!["/p/a.rs".to_string()])
+        }
+
+        fn get_visual_selections(&self, _max_bytes: Option<usize>) -> Result<Vec<EditorContext>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn a_recorded_session_replays_in_order() {
+        let path =
+            std::env::temp_dir().join(format!("sidekick-record-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let recorder = Recorder::new(Box::new(Editor), path.clone());
+        recorder.buffer_status("/p/a.rs").unwrap();
+        recorder
+            .send_message("blocked", NotifyLevel::Warn)
+            .unwrap_err();
+        recorder.list_buffers().unwrap();
+
+        let _ = std::fs::remove_file(cursor_path(&path));
+        let replay = Replay::load(&path).unwrap();
+        let statuses = replay.buffer_status("/p/a.rs").unwrap();
+        assert!(statuses[0].is_current && statuses[0].has_unsaved_changes);
+        assert_eq!(statuses[0].instance.as_deref(), Some("/tmp/a.sock"));
+
+        // A later hook process carries on from there
+        let replay = Replay::load(&path).unwrap();
+        let err = replay
+            .send_message("blocked", NotifyLevel::Warn)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "couldn't send to Neovim");
+        // Out of order, then past the end
+        assert!(replay.is_focused().is_err());
+        assert!(replay.list_buffers().is_err());
+
+        std::fs::remove_file(cursor_path(&path)).unwrap();
+        let restarted = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(restarted.buffer_status("/p/a.rs").is_ok());
+        std::fs::remove_file(cursor_path(&path)).unwrap();
+    }
+}
@@ -7,7 +7,7 @@ use std::collections::{BTreeMap, HashSet};
 
 use chrono::{DateTime, Duration, NaiveDate, Timelike, Utc};
 
-use crate::analytics::event::{Decision, DecisionReason, Event, ToolKind};
+use crate::analytics::event::{Decision, DecisionReason, Event, HookDecision, ToolKind};
 
 #[derive(Debug, Clone, Copy)]
 pub enum TimeRange {
@@ -195,20 +195,17 @@ pub fn aggregate(events: Vec<Event>, range: TimeRange) -> Stats {
                     .or_default();
                 *pf_total += 1;
 
-                match d.decision {
-                    Decision::Allow => allowed += 1,
-                    Decision::Deny => {
-                        if matches!(d.reason, DecisionReason::BufferDirtyAndCurrent) {
-                            saves += 1;
-                            day.saves += 1;
-                            *save_count += 1;
-                            *pf_saves += 1;
-                            file_save_timeline
-                                .entry(d.file.clone())
-                                .or_default()
-                                .push(ts);
-                        }
-                    }
+                if d.decision == Decision::Allow {
+                    allowed += 1;
+                } else if is_save(d) {
+                    saves += 1;
+                    day.saves += 1;
+                    *save_count += 1;
+                    *pf_saves += 1;
+                    file_save_timeline
+                        .entry(d.file.clone())
+                        .or_default()
+                        .push(ts);
                 }
             }
             Event::BufferRefresh(r) => {
@@ -331,9 +328,7 @@ pub fn aggregate(events: Vec<Event>, range: TimeRange) -> Stats {
             }
             Event::HookDecision(d) => {
                 ai_buckets[idx] += 1;
-                if matches!(d.decision, Decision::Deny)
-                    && matches!(d.reason, DecisionReason::BufferDirtyAndCurrent)
-                {
+                if is_save(d) {
                     save_buckets[idx] += 1;
                     // Note: `you_buckets` deliberately does NOT include saves.
                     // Letting saves bump both lines makes them look parallel and
@@ -421,14 +416,33 @@ fn tool_label(t: ToolKind) -> &'static str {
         ToolKind::Edit => "Edit",
         ToolKind::Write => "Write",
         ToolKind::MultiEdit => "MultiEdit",
+        ToolKind::Bash => "Bash",
     }
 }
 
+/// A decision that stopped the AI from clobbering unsaved work, whether
+/// denied outright or put to the user.
+fn is_save(d: &HookDecision) -> bool {
+    d.decision != Decision::Allow
+        && matches!(
+            d.reason,
+            DecisionReason::BufferDirtyAndCurrent
+                | DecisionReason::BufferDirty
+                | DecisionReason::StaleContent
+        )
+}
+
 fn reason_label(r: DecisionReason) -> &'static str {
     match r {
         DecisionReason::NoNvimRunning => "no_nvim_running",
         DecisionReason::StatusCheckFailed => "status_check_failed",
         DecisionReason::BufferDirtyAndCurrent => "buffer_dirty_and_current",
+        DecisionReason::BufferDirty => "buffer_dirty",
         DecisionReason::BufferAvailable => "buffer_available",
+        DecisionReason::UserOverride => "user_override",
+        DecisionReason::StaleContent => "stale_content",
+        DecisionReason::SoftBlockRetry => "soft_block_retry",
+        DecisionReason::Unprotected => "unprotected",
+        DecisionReason::SwapFile => "swap_file",
     }
 }
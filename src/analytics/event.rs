@@ -35,6 +35,8 @@ pub enum ToolKind {
     Edit,
     Write,
     MultiEdit,
+    /// A shell command deleting or moving a file, under `guard_bash_deletes`
+    Bash,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -42,6 +44,8 @@ pub enum ToolKind {
 pub enum Decision {
     Allow,
     Deny,
+    /// The user was asked to confirm rather than the call being denied
+    Ask,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -53,8 +57,25 @@ pub enum DecisionReason {
     StatusCheckFailed,
     /// File is open as the current buffer and has unsaved changes. The save.
     BufferDirtyAndCurrent,
+    /// File has unsaved changes in a buffer that isn't current, which the
+    /// `any` unsaved policy blocks too.
+    BufferDirty,
     /// File was checked against nvim but was not dirty-and-current. Allowed.
     BufferAvailable,
+    /// File was dirty-and-current but the user asked the AI to overwrite it.
+    /// Allowed.
+    UserOverride,
+    /// Edit's `old_string` no longer occurs in the unsaved buffer. Denied.
+    StaleContent,
+    /// File was dirty-and-current, but this exact edit was denied moments
+    /// ago and soft blocking lets the retry through. Allowed.
+    SoftBlockRetry,
+    /// File is outside every `SIDEKICK_PROTECT_DIRS` root, so the editor
+    /// wasn't asked. Allowed.
+    Unprotected,
+    /// No editor was reachable, but the file has a swap file, so the user
+    /// was asked.
+    SwapFile,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
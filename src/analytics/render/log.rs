@@ -0,0 +1,79 @@
+//! One-line-per-event rendering for `sidekick log`.
+//!
+//! Columns are local time, what happened, and the file or detail it
+//! concerns. Hook decisions also show the tool and why. With color on,
+//! allows are green and denies red.
+
+use serde::Serialize;
+
+use crate::analytics::event::{Decision, Event};
+
+/// `event` as one table row, without a trailing newline
+pub fn line(event: &Event, color: bool) -> String {
+    let at = event
+        .timestamp()
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M:%S");
+    let at = paint(color, "\x1b[2m", &at.to_string());
+
+    match event {
+        Event::HookDecision(e) => {
+            let decision = match e.decision {
+                Decision::Allow => paint(color, "\x1b[32m", "allow  "),
+                Decision::Deny => paint(color, "\x1b[1;31m", "deny   "),
+                Decision::Ask => paint(color, "\x1b[33m", "ask    "),
+            };
+            format!(
+                "{}  {}  {:<10} {}  {}",
+                at,
+                decision,
+                snake(e.tool),
+                e.file,
+                paint(color, "\x1b[2m", &format!("({})", snake(e.reason))),
+            )
+        }
+        Event::BufferRefresh(e) => format!("{}  refresh  {:<10} {}", at, snake(e.tool), e.file),
+        Event::NvimLaunch(e) => format!("{}  launch   {:<10} {}", at, e.pid, e.socket_path),
+        Event::StatsView(e) => format!("{}  stats    {:<10}", at, e.range)
+            .trim_end()
+            .to_string(),
+    }
+}
+
+/// How a unit enum is spelled in the log file
+fn snake(value: impl Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn paint(color: bool, code: &str, s: &str) -> String {
+    if color {
+        format!("{}{}\x1b[0m", code, s)
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DENY: &str = r#"{"type":"hook_decision","at":"2026-01-02T03:04:05Z","session_id":"s","cwd":"/p","tool":"multi_edit","file":"/p/src/main.rs","decision":"deny","reason":"buffer_dirty_and_current","instances_probed":1,"latency_ms":4}"#;
+
+    #[test]
+    fn renders_a_logged_decision() {
+        let event: Event = serde_json::from_str(DENY).unwrap();
+        let row = line(&event, false);
+        let time = event.timestamp().with_timezone(&chrono::Local);
+
+        assert!(row.starts_with(&time.format("%Y-%m-%d %H:%M:%S").to_string()));
+        assert!(row.contains("deny"));
+        assert!(row.contains("multi_edit"));
+        assert!(row.ends_with("/p/src/main.rs  (buffer_dirty_and_current)"));
+        assert!(!row.contains('\x1b'));
+
+        assert!(line(&event, true).contains("\x1b[1;31mdeny"));
+    }
+}
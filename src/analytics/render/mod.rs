@@ -6,7 +6,11 @@
 //! to a writer). Future implementations (PNG, SVG, kitty graphics, scripted
 //! reveals) can plug in behind the same trait — or, if they don't fit the
 //! "write bytes" model, sit alongside it without forcing breaking changes.
+//!
+//! `log` is the exception: it renders single events, not `Stats`, for
+//! `sidekick log`.
 
+pub mod log;
 pub mod terminal;
 
 use crate::analytics::aggregate::Stats;
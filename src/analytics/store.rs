@@ -17,12 +17,19 @@ use std::io::Write;
 use std::path::PathBuf;
 
 use crate::analytics::event::Event;
+use crate::utils;
 
 /// Resolve the events log path. Honors `SIDEKICK_EVENTS_PATH` for testability.
+/// Unit tests get a throwaway log of their own, so hooks they run never reach
+/// the user's real one.
 pub fn log_path() -> PathBuf {
     if let Ok(custom) = std::env::var("SIDEKICK_EVENTS_PATH") {
         return PathBuf::from(custom);
     }
+    if cfg!(test) {
+        return std::env::temp_dir()
+            .join(format!("sidekick-test-events-{}.jsonl", std::process::id()));
+    }
     let base = dirs::data_local_dir().unwrap_or_else(std::env::temp_dir);
     base.join("sidekick").join("events.jsonl")
 }
@@ -30,12 +37,16 @@ pub fn log_path() -> PathBuf {
 /// Append an event to the log. Never panics, never propagates errors.
 ///
 /// If writing fails (no disk, permission denied, etc.), the event is silently
-/// dropped. Analytics must not block the user's tool flow.
+/// dropped. Analytics must not block the user's tool flow. Nor is one logged
+/// once `utils::answer_overdue`: the host never saw that decision.
 pub fn append(event: &Event) {
     let _ = try_append(event);
 }
 
 fn try_append(event: &Event) -> anyhow::Result<()> {
+    if utils::answer_overdue() {
+        anyhow::bail!("couldn't log the event: the hook answer is overdue");
+    }
     let path = log_path();
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
@@ -49,6 +60,34 @@ fn try_append(event: &Event) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Events appended past byte `offset`, and the offset to read from next.
+/// A last line still being written is left for the next read.
+pub fn read_from(offset: u64) -> anyhow::Result<(Vec<Event>, u64)> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = match std::fs::File::open(log_path()) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(e) => return Err(e.into()),
+    };
+    // Truncated or replaced since: start over
+    let offset = if file.metadata()?.len() < offset {
+        0
+    } else {
+        offset
+    };
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    let complete = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let events = String::from_utf8_lossy(&bytes[..complete])
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Event>(line).ok())
+        .collect();
+    Ok((events, offset + complete as u64))
+}
+
 /// Read every event in the log. Malformed lines are silently skipped.
 ///
 /// Returns an empty vec if the log doesn't exist yet (cold start case).
@@ -69,3 +108,15 @@ pub fn read_all() -> anyhow::Result<Vec<Event>> {
     }
     Ok(events)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_tests_never_log_to_the_real_store() {
+        if std::env::var_os("SIDEKICK_EVENTS_PATH").is_none() {
+            assert!(log_path().starts_with(std::env::temp_dir()));
+        }
+    }
+}
@@ -0,0 +1,616 @@
+//! Runtime configuration for the hook handler.
+//!
+//! Settings come from three layers, highest precedence first:
+//!
+//! 1. `SIDEKICK_*` environment variables
+//! 2. The project policy, `.sidekick.toml`, found by walking up from the
+//!    hook's `cwd` to the repository root. Commit it so every contributor
+//!    gets the same behavior.
+//! 3. The user config, `<config dir>/sidekick/config.toml`
+//!
+//! A setting left unset everywhere keeps today's behavior. Configuration is
+//! loaded once per hook invocation and the resulting `Config` is threaded
+//! through the handler, so tests can build one directly instead of mutating
+//! the process environment. A file that doesn't parse is reported on stderr
+//! and skipped; it never fails the hook.
+//!
+//! # Settings
+//!
+//! - `disable_refresh` / `SIDEKICK_DISABLE_REFRESH=1` — skip buffer reloads
+//!   after the AI writes a file. PreToolUse protection is unaffected.
+//! - `warn_no_instance` / `SIDEKICK_WARN_NO_INSTANCE=1` — print a note on
+//!   stderr when a file tool runs but no editor was discovered, to debug setup.
+//! - `warn_missing_target` / `SIDEKICK_WARN_MISSING_TARGET=1` — add a
+//!   system message when the AI edits a file that doesn't exist on disk,
+//!   usually a mistyped path. Writes, which create files, are left alone.
+//! - `notify_limit` / `SIDEKICK_NOTIFY_LIMIT=<n>` — send at most `n` editor
+//!   notifications per minute per session. Edits are still denied past the
+//!   cap, just quietly.
+//! - `protect_dirs` / `SIDEKICK_PROTECT_DIRS=/src:/work` — only check
+//!   files under these directories (colon-separated, relative ones taken
+//!   from the hook's cwd); edits anywhere else are allowed unchecked.
+//!   Unset, every file is checked.
+//! - `guarded_tools` / `SIDEKICK_GUARDED_TOOLS=Edit,Write` — tools checked
+//!   before they run. Defaults to `Edit`, `Write` and `MultiEdit`.
+//! - `[tools.<Tool>]` / `SIDEKICK_TOOLS=Edit:ask,Write:ignore:refresh` — per
+//!   tool, what happens before it runs (`pre`: `block`, `ask` or `ignore`)
+//!   and after (`post`: `refresh` or `ignore`). A tool left out follows
+//!   `guarded_tools` and `disable_refresh`.
+//! - `unsaved` / `SIDEKICK_UNSAVED=any` — `current` (the default) blocks only
+//!   when the dirty buffer is the current one; `any` blocks whenever the file
+//!   has unsaved changes. `SIDEKICK_BLOCK_BACKGROUND=1` is shorthand for
+//!   `any`; `SIDEKICK_UNSAVED` wins when both are set.
+//! - `visible_is_current` / `SIDEKICK_VISIBLE_IS_CURRENT=1` — treat a buffer
+//!   shown in any window of the current tab as current, so a file open in a
+//!   split you aren't focused on is protected under the `current` policy.
+//! - `scan_all_instances` / `SIDEKICK_SCAN_ALL_INSTANCES=1` — ask every
+//!   Neovim instance for a file's status, rather than stopping at the first
+//!   that has it dirty and current, so each instance is accounted for.
+//! - `prompt_context` / `SIDEKICK_PROMPT_CONTEXT=1` — add the file and lines
+//!   on screen in the editor to every prompt, next to any visual selection.
+//!   Off by default since it queries the editor on each prompt.
+//! - `match_mode` / `SIDEKICK_MATCH_MODE=suffix` — how a Neovim buffer's
+//!   name is compared with the file the AI names: `strict` (the same
+//!   string), `canonical` (the default: the same file once symlinks and `..`
+//!   are resolved) or `suffix` (`canonical`, falling back to a relative name
+//!   that ends the other path, for `autochdir` setups).
+//! - `deny_message` / `SIDEKICK_DENY_MESSAGE` — the reason given to the AI
+//!   when an edit is blocked.
+//! - `honor_transcript` / `SIDEKICK_HONOR_TRANSCRIPT=1` — allow an edit
+//!   despite unsaved changes when one of the user's last few prompts asked to
+//!   overwrite that file by name.
+//! - `swapfile_fallback` / `SIDEKICK_SWAPFILE_FALLBACK=1` — when no editor
+//!   can be reached, ask before editing a file that has a Vim or Neovim swap
+//!   file, the trace of a session that may have died with unsaved work.
+//! - `fail_closed` / `SIDEKICK_FAIL_CLOSED=1` — when an editor is running
+//!   but can't be asked whether the file has unsaved changes, ask before
+//!   editing it. By default such an edit is allowed, so a wedged editor
+//!   never stalls the AI.
+//! - `soft_block` / `SIDEKICK_SOFT_BLOCK=1` — deny an edit to a dirty file
+//!   once, but let the exact same edit through if the AI retries it within
+//!   a minute: the user has been warned.
+//! - `read_live_content` / `SIDEKICK_READ_LIVE_CONTENT=1` — when the AI reads
+//!   a file with unsaved changes, also hand it the editor's text.
+//! - `notify_level` / `SIDEKICK_NOTIFY_LEVEL=info` — severity of the "edit
+//!   blocked" message: `info`, `warn` (the default) or `error`.
+//! - `notify_prefix` / `SIDEKICK_NOTIFY_PREFIX` — put in front of every
+//!   editor notification so it's clear where it came from. Defaults to
+//!   `[sidekick] `; an emoji works too, and an empty value turns it off.
+//! - `max_msg_len` / `SIDEKICK_MAX_MSG_LEN=<n>` — cut editor notifications
+//!   to `n` characters, ending in `…`, so a long path can't overflow the
+//!   command line. Defaults to 200; `0` never cuts.
+//! - `rich_notify` / `SIDEKICK_RICH_NOTIFY=1` — announce a block in a small
+//!   floating window naming the file, where the editor supports it.
+//! - `guard_bash_deletes` / `SIDEKICK_GUARD_BASH_DELETES=1` — deny a plain
+//!   `rm`, `mv` or `git rm` of a file the user is editing. Commands with
+//!   globs, variables or quoting are left alone rather than guessed at.
+//!   `[tools.Bash]` can make it ask instead, and `protect_dirs` and
+//!   `trust_bypass_mode` apply as for edits. Claude Code only sends Bash
+//!   calls once `Bash` is in the `PreToolUse` matcher.
+//! - `trust_bypass_mode` / `SIDEKICK_TRUST_BYPASS_MODE=1` — skip the editor
+//!   check entirely when Claude Code runs with `bypassPermissions`, taking
+//!   that as the user accepting overwrites too.
+//! - `max_instances` / `SIDEKICK_MAX_INSTANCES=<n>` — consider at most `n`
+//!   editor instances per hook, for hosts with many stale sockets.
+//! - `dead_socket_ttl_secs` / `SIDEKICK_DEAD_SOCKET_TTL_SECS=<n>` — skip a
+//!   Neovim socket for `n` seconds after it refused a connection, so bursts
+//!   of hooks don't each wait on the same stale socket.
+//! - `refresh_checktime_only` / `SIDEKICK_REFRESH_CHECKTIME_ONLY=1` — after
+//!   the AI writes a file, only `:checktime` its buffer instead of a full
+//!   `:edit`, leaving the reload to `autoread`.
+//! - `refresh_exclude_filetypes` / `SIDEKICK_REFRESH_EXCLUDE_FILETYPES=log,json`
+//!   — filetypes that get `:checktime` only, for buffers too big to reload
+//!   on every write.
+//!
+//! ```toml
+//! # .sidekick.toml
+//! guarded_tools = ["Edit", "MultiEdit"]
+//! unsaved = "any"
+//! deny_message = "Someone is editing this file. Wait for them to save."
+//!
+//! [tools.Write]
+//! pre = "ask"
+//! post = "ignore"
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::action::NotifyLevel;
+use crate::action::neovim::{MatchMode, RefreshPolicy};
+
+/// Name of the project policy file
+pub const PROJECT_FILE: &str = ".sidekick.toml";
+
+/// Longest editor notification, in characters, unless configured otherwise
+pub const DEFAULT_MAX_MSG_LEN: usize = 200;
+
+/// When unsaved changes block an edit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnsavedPolicy {
+    /// Only when the dirty buffer is current in the same editor instance
+    #[default]
+    Current,
+    /// Whenever the file has unsaved changes, visible or not
+    Any,
+}
+
+/// What PreToolUse does with a tool call on a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreAction {
+    /// Deny it while the file has unsaved changes
+    Block,
+    /// Ask the user to confirm it while the file has unsaved changes
+    Ask,
+    /// Let it through unchecked
+    Ignore,
+}
+
+/// What PostToolUse does after a tool wrote a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostAction {
+    /// Reload the file's buffers
+    Refresh,
+    /// Leave the buffers alone
+    Ignore,
+}
+
+/// Per-tool behavior; an unset half falls back to `guarded_tools` or
+/// `disable_refresh`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolActions {
+    pub pre: Option<PreAction>,
+    pub post: Option<PostAction>,
+}
+
+/// How much the hook says on stderr, set by `--quiet` / `--verbose`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Nothing, not even warnings
+    Quiet,
+    /// Warnings only
+    #[default]
+    Normal,
+    /// Warnings plus what the hook found and decided
+    Verbose,
+}
+
+impl Verbosity {
+    pub fn shows_warnings(self) -> bool {
+        self != Verbosity::Quiet
+    }
+
+    /// Report a recoverable problem on stderr
+    pub fn warn(self, message: impl std::fmt::Display) {
+        if self.shows_warnings() {
+            eprintln!("Warning: {}", message);
+        }
+    }
+
+    /// Report detail that only `--verbose` asked for
+    pub fn note(self, message: impl std::fmt::Display) {
+        if self == Verbosity::Verbose {
+            eprintln!("sidekick: {}", message);
+        }
+    }
+}
+
+/// Hook handler configuration
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Make PostToolUse a no-op: leave reloading to the editor (`autoread`)
+    pub disable_refresh: bool,
+    /// Say so on stderr when a file tool finds no editor to protect it
+    pub warn_no_instance: bool,
+    /// Flag edits of files that don't exist on disk
+    pub warn_missing_target: bool,
+    /// Per-session cap on editor notifications per minute; `None` is unlimited
+    pub notify_limit: Option<usize>,
+    /// Claude Code tool names checked before they run
+    pub guarded_tools: Vec<String>,
+    /// Directories whose files are checked; empty checks every file
+    pub protect_dirs: Vec<PathBuf>,
+    /// Per-tool overrides, keyed by Claude Code tool name
+    pub tools: HashMap<String, ToolActions>,
+    /// When unsaved changes block an edit
+    pub unsaved: UnsavedPolicy,
+    /// Count a buffer visible in any window of the current tab as current
+    pub visible_is_current: bool,
+    /// Query every Neovim instance for a status, with no early exit
+    pub scan_all_instances: bool,
+    /// How buffer names are compared with file paths
+    pub match_mode: MatchMode,
+    /// Tell the AI which file and lines the user has on screen, per prompt
+    pub prompt_context: bool,
+    /// Reason given to the AI on a block; `None` uses the built-in one
+    pub deny_message: Option<String>,
+    /// Let an explicit "overwrite <file>" in the transcript lift a block
+    pub honor_transcript: bool,
+    /// Let an unchanged retry of a denied edit through shortly after
+    pub soft_block: bool,
+    /// With no editor reachable, ask before editing files with a swap file
+    pub swapfile_fallback: bool,
+    /// Ask, rather than allow, when a running editor can't be queried
+    pub fail_closed: bool,
+    /// Attach the editor's unsaved text to reads of dirty files
+    pub read_live_content: bool,
+    /// Severity of the "edit blocked" message
+    pub notify_level: NotifyLevel,
+    /// Branding put in front of every editor notification
+    pub notify_prefix: String,
+    /// Longest editor notification in characters; `None` is unlimited
+    pub max_msg_len: Option<usize>,
+    /// Announce blocks in a floating window instead of a one-line message
+    pub rich_notify: bool,
+    /// Allow everything unchecked under Claude Code's `bypassPermissions`
+    pub trust_bypass_mode: bool,
+    /// Deny simple `rm`/`mv`/`git rm` of files being edited
+    pub guard_bash_deletes: bool,
+    /// Most editor instances discovery considers; `None` is unlimited
+    pub max_instances: Option<usize>,
+    /// How long discovery skips a socket that refused a connection; `None`
+    /// always probes
+    pub dead_socket_ttl: Option<Duration>,
+    /// Refresh with `:checktime` alone, never a full reload
+    pub refresh_checktime_only: bool,
+    /// Filetypes refreshed with `:checktime` alone
+    pub refresh_exclude_filetypes: Vec<String>,
+    /// Stderr noise level; comes from the command line, not config files
+    pub verbosity: Verbosity,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Layer::default().resolve()
+    }
+}
+
+impl Config {
+    /// Load configuration from the environment, the project policy above
+    /// `project_dir`, and the user config
+    pub fn load(project_dir: &Path, verbosity: Verbosity) -> Self {
+        let project = find_project_file(project_dir).and_then(|p| std::fs::read_to_string(p).ok());
+        let user = user_config_path().and_then(|p| std::fs::read_to_string(p).ok());
+
+        Self::from_sources(
+            |name| std::env::var(name).ok(),
+            project.as_deref(),
+            user.as_deref(),
+            verbosity,
+        )
+    }
+
+    /// Layer a variable lookup over project and user config file contents.
+    /// A file that doesn't parse is skipped, with a warning unless quiet.
+    pub fn from_sources(
+        var: impl Fn(&str) -> Option<String>,
+        project: Option<&str>,
+        user: Option<&str>,
+        verbosity: Verbosity,
+    ) -> Self {
+        let mut config = Layer::from_vars(var)
+            .over(parse_layer(project, PROJECT_FILE, verbosity))
+            .over(parse_layer(user, "user config", verbosity))
+            .resolve();
+        config.verbosity = verbosity;
+        config
+    }
+
+    /// How Neovim reloads a buffer after the AI writes its file
+    pub fn refresh_policy(&self) -> RefreshPolicy {
+        RefreshPolicy {
+            checktime_only: self.refresh_checktime_only,
+            exclude_filetypes: self.refresh_exclude_filetypes.clone(),
+        }
+    }
+
+    /// Whether a Claude Code tool is checked before it runs. `Bash` is with
+    /// `guard_bash_deletes` on.
+    pub fn guards(&self, tool_name: &str) -> bool {
+        (self.guard_bash_deletes && tool_name.eq_ignore_ascii_case("Bash"))
+            || self
+                .guarded_tools
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(tool_name))
+    }
+
+    /// What PreToolUse does for a Claude Code tool
+    pub fn pre_action(&self, tool_name: &str) -> PreAction {
+        self.tool_actions(tool_name)
+            .and_then(|a| a.pre)
+            .unwrap_or(if self.guards(tool_name) {
+                PreAction::Block
+            } else {
+                PreAction::Ignore
+            })
+    }
+
+    /// What PostToolUse does for a Claude Code tool
+    pub fn post_action(&self, tool_name: &str) -> PostAction {
+        self.tool_actions(tool_name)
+            .and_then(|a| a.post)
+            .unwrap_or(if self.disable_refresh {
+                PostAction::Ignore
+            } else {
+                PostAction::Refresh
+            })
+    }
+
+    fn tool_actions(&self, tool_name: &str) -> Option<&ToolActions> {
+        self.tools
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(tool_name))
+            .map(|(_, actions)| actions)
+    }
+}
+
+/// One source of settings; `None` defers to the layer below
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Layer {
+    disable_refresh: Option<bool>,
+    warn_no_instance: Option<bool>,
+    warn_missing_target: Option<bool>,
+    notify_limit: Option<usize>,
+    guarded_tools: Option<Vec<String>>,
+    protect_dirs: Option<Vec<PathBuf>>,
+    tools: Option<HashMap<String, ToolActions>>,
+    unsaved: Option<UnsavedPolicy>,
+    visible_is_current: Option<bool>,
+    scan_all_instances: Option<bool>,
+    match_mode: Option<MatchMode>,
+    prompt_context: Option<bool>,
+    deny_message: Option<String>,
+    honor_transcript: Option<bool>,
+    soft_block: Option<bool>,
+    swapfile_fallback: Option<bool>,
+    fail_closed: Option<bool>,
+    rich_notify: Option<bool>,
+    read_live_content: Option<bool>,
+    notify_level: Option<NotifyLevel>,
+    notify_prefix: Option<String>,
+    max_msg_len: Option<usize>,
+    trust_bypass_mode: Option<bool>,
+    guard_bash_deletes: Option<bool>,
+    max_instances: Option<usize>,
+    dead_socket_ttl_secs: Option<u64>,
+    refresh_checktime_only: Option<bool>,
+    refresh_exclude_filetypes: Option<Vec<String>>,
+}
+
+impl Layer {
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        Self {
+            disable_refresh: flag(var("SIDEKICK_DISABLE_REFRESH")),
+            warn_no_instance: flag(var("SIDEKICK_WARN_NO_INSTANCE")),
+            warn_missing_target: flag(var("SIDEKICK_WARN_MISSING_TARGET")),
+            notify_limit: var("SIDEKICK_NOTIFY_LIMIT").and_then(|v| v.trim().parse().ok()),
+            guarded_tools: var("SIDEKICK_GUARDED_TOOLS").map(|v| list(&v)),
+            protect_dirs: var("SIDEKICK_PROTECT_DIRS").map(|v| {
+                std::env::split_paths(&v)
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .collect()
+            }),
+            tools: var("SIDEKICK_TOOLS").map(|v| tool_actions(&v)),
+            unsaved: var("SIDEKICK_UNSAVED")
+                .and_then(|v| match v.trim().to_ascii_lowercase().as_str() {
+                    "current" => Some(UnsavedPolicy::Current),
+                    "any" => Some(UnsavedPolicy::Any),
+                    _ => None,
+                })
+                .or_else(|| {
+                    flag(var("SIDEKICK_BLOCK_BACKGROUND")).map(|block| {
+                        if block {
+                            UnsavedPolicy::Any
+                        } else {
+                            UnsavedPolicy::Current
+                        }
+                    })
+                }),
+            visible_is_current: flag(var("SIDEKICK_VISIBLE_IS_CURRENT")),
+            scan_all_instances: flag(var("SIDEKICK_SCAN_ALL_INSTANCES")),
+            match_mode: var("SIDEKICK_MATCH_MODE").and_then(|v| {
+                match v.trim().to_ascii_lowercase().as_str() {
+                    "strict" => Some(MatchMode::Strict),
+                    "canonical" => Some(MatchMode::Canonical),
+                    "suffix" => Some(MatchMode::Suffix),
+                    _ => None,
+                }
+            }),
+            prompt_context: flag(var("SIDEKICK_PROMPT_CONTEXT")),
+            deny_message: var("SIDEKICK_DENY_MESSAGE").filter(|m| !m.trim().is_empty()),
+            honor_transcript: flag(var("SIDEKICK_HONOR_TRANSCRIPT")),
+            soft_block: flag(var("SIDEKICK_SOFT_BLOCK")),
+            swapfile_fallback: flag(var("SIDEKICK_SWAPFILE_FALLBACK")),
+            fail_closed: flag(var("SIDEKICK_FAIL_CLOSED")),
+            rich_notify: flag(var("SIDEKICK_RICH_NOTIFY")),
+            read_live_content: flag(var("SIDEKICK_READ_LIVE_CONTENT")),
+            notify_level: var("SIDEKICK_NOTIFY_LEVEL").and_then(|v| {
+                match v.trim().to_ascii_lowercase().as_str() {
+                    "info" => Some(NotifyLevel::Info),
+                    "warn" | "warning" => Some(NotifyLevel::Warn),
+                    "error" => Some(NotifyLevel::Error),
+                    _ => None,
+                }
+            }),
+            notify_prefix: var("SIDEKICK_NOTIFY_PREFIX"),
+            max_msg_len: var("SIDEKICK_MAX_MSG_LEN").and_then(|v| v.trim().parse().ok()),
+            trust_bypass_mode: flag(var("SIDEKICK_TRUST_BYPASS_MODE")),
+            guard_bash_deletes: flag(var("SIDEKICK_GUARD_BASH_DELETES")),
+            max_instances: var("SIDEKICK_MAX_INSTANCES").and_then(|v| v.trim().parse().ok()),
+            dead_socket_ttl_secs: var("SIDEKICK_DEAD_SOCKET_TTL_SECS")
+                .and_then(|v| v.trim().parse().ok()),
+            refresh_checktime_only: flag(var("SIDEKICK_REFRESH_CHECKTIME_ONLY")),
+            refresh_exclude_filetypes: var("SIDEKICK_REFRESH_EXCLUDE_FILETYPES").map(|v| list(&v)),
+        }
+    }
+
+    /// Fill this layer's gaps from `lower`
+    fn over(self, lower: Layer) -> Layer {
+        Layer {
+            disable_refresh: self.disable_refresh.or(lower.disable_refresh),
+            warn_no_instance: self.warn_no_instance.or(lower.warn_no_instance),
+            warn_missing_target: self.warn_missing_target.or(lower.warn_missing_target),
+            notify_limit: self.notify_limit.or(lower.notify_limit),
+            guarded_tools: self.guarded_tools.or(lower.guarded_tools),
+            protect_dirs: self.protect_dirs.or(lower.protect_dirs),
+            tools: self.tools.or(lower.tools),
+            unsaved: self.unsaved.or(lower.unsaved),
+            visible_is_current: self.visible_is_current.or(lower.visible_is_current),
+            scan_all_instances: self.scan_all_instances.or(lower.scan_all_instances),
+            match_mode: self.match_mode.or(lower.match_mode),
+            prompt_context: self.prompt_context.or(lower.prompt_context),
+            deny_message: self.deny_message.or(lower.deny_message),
+            honor_transcript: self.honor_transcript.or(lower.honor_transcript),
+            soft_block: self.soft_block.or(lower.soft_block),
+            swapfile_fallback: self.swapfile_fallback.or(lower.swapfile_fallback),
+            fail_closed: self.fail_closed.or(lower.fail_closed),
+            rich_notify: self.rich_notify.or(lower.rich_notify),
+            read_live_content: self.read_live_content.or(lower.read_live_content),
+            notify_level: self.notify_level.or(lower.notify_level),
+            notify_prefix: self.notify_prefix.or(lower.notify_prefix),
+            max_msg_len: self.max_msg_len.or(lower.max_msg_len),
+            trust_bypass_mode: self.trust_bypass_mode.or(lower.trust_bypass_mode),
+            guard_bash_deletes: self.guard_bash_deletes.or(lower.guard_bash_deletes),
+            max_instances: self.max_instances.or(lower.max_instances),
+            dead_socket_ttl_secs: self.dead_socket_ttl_secs.or(lower.dead_socket_ttl_secs),
+            refresh_checktime_only: self.refresh_checktime_only.or(lower.refresh_checktime_only),
+            refresh_exclude_filetypes: self
+                .refresh_exclude_filetypes
+                .or(lower.refresh_exclude_filetypes),
+        }
+    }
+
+    /// Apply defaults for whatever no layer set
+    fn resolve(self) -> Config {
+        Config {
+            disable_refresh: self.disable_refresh.unwrap_or(false),
+            warn_no_instance: self.warn_no_instance.unwrap_or(false),
+            warn_missing_target: self.warn_missing_target.unwrap_or(false),
+            notify_limit: self.notify_limit,
+            protect_dirs: self.protect_dirs.unwrap_or_default(),
+            guarded_tools: self.guarded_tools.unwrap_or_else(|| {
+                ["Edit", "Write", "MultiEdit"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            }),
+            tools: self.tools.unwrap_or_default(),
+            unsaved: self.unsaved.unwrap_or_default(),
+            visible_is_current: self.visible_is_current.unwrap_or(false),
+            scan_all_instances: self.scan_all_instances.unwrap_or(false),
+            match_mode: self.match_mode.unwrap_or_default(),
+            prompt_context: self.prompt_context.unwrap_or(false),
+            deny_message: self.deny_message,
+            honor_transcript: self.honor_transcript.unwrap_or(false),
+            soft_block: self.soft_block.unwrap_or(false),
+            swapfile_fallback: self.swapfile_fallback.unwrap_or(false),
+            fail_closed: self.fail_closed.unwrap_or(false),
+            rich_notify: self.rich_notify.unwrap_or(false),
+            read_live_content: self.read_live_content.unwrap_or(false),
+            notify_level: self.notify_level.unwrap_or_default(),
+            notify_prefix: self
+                .notify_prefix
+                .unwrap_or_else(|| "[sidekick] ".to_string()),
+            max_msg_len: match self.max_msg_len {
+                Some(0) => None,
+                len => Some(len.unwrap_or(DEFAULT_MAX_MSG_LEN)),
+            },
+            trust_bypass_mode: self.trust_bypass_mode.unwrap_or(false),
+            guard_bash_deletes: self.guard_bash_deletes.unwrap_or(false),
+            max_instances: self.max_instances,
+            dead_socket_ttl: self.dead_socket_ttl_secs.map(Duration::from_secs),
+            refresh_checktime_only: self.refresh_checktime_only.unwrap_or(false),
+            refresh_exclude_filetypes: self.refresh_exclude_filetypes.unwrap_or_default(),
+            verbosity: Verbosity::default(),
+        }
+    }
+}
+
+/// Boolean flags are on for `1`, `true`, `yes` and `on`, case-insensitively.
+/// Any other value is off; an unset variable defers to the layer below.
+pub(crate) fn flag(value: Option<String>) -> Option<bool> {
+    value.map(|v| {
+        matches!(
+            v.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        )
+    })
+}
+
+/// Comma-separated values, trimmed, empty entries dropped
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// `Tool:pre[:post]` entries, comma-separated; `Write::ignore` sets only
+/// `post`. Entries that don't parse are dropped.
+fn tool_actions(value: &str) -> HashMap<String, ToolActions> {
+    list(value)
+        .iter()
+        .filter_map(|entry| {
+            let (name, actions) = entry.split_once(':')?;
+            let mut parts = actions.split(':').map(|p| p.trim().to_ascii_lowercase());
+            let pre = match parts.next().as_deref() {
+                None | Some("") => None,
+                Some("block") => Some(PreAction::Block),
+                Some("ask") => Some(PreAction::Ask),
+                Some("ignore") => Some(PreAction::Ignore),
+                Some(_) => return None,
+            };
+            let post = match parts.next().as_deref() {
+                None | Some("") => None,
+                Some("refresh") => Some(PostAction::Refresh),
+                Some("ignore") => Some(PostAction::Ignore),
+                Some(_) => return None,
+            };
+            Some((name.trim().to_string(), ToolActions { pre, post }))
+        })
+        .collect()
+}
+
+/// Nearest `.sidekick.toml` from `dir` upward, stopping at the repository
+/// root (the first directory holding `.git`)
+fn find_project_file(dir: &Path) -> Option<PathBuf> {
+    for ancestor in dir.ancestors() {
+        let candidate = ancestor.join(PROJECT_FILE);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        if ancestor.join(".git").exists() {
+            break;
+        }
+    }
+    None
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("sidekick").join("config.toml"))
+}
+
+/// Parse one config file. Absent is the same as empty; unparseable is
+/// reported and skipped so a bad policy never breaks the hook.
+fn parse_layer(content: Option<&str>, name: &str, verbosity: Verbosity) -> Layer {
+    let Some(content) = content else {
+        return Layer::default();
+    };
+    match toml::from_str(content) {
+        Ok(layer) => layer,
+        Err(e) => {
+            verbosity.warn(format_args!("ignoring {}: {}", name, e));
+            Layer::default()
+        }
+    }
+}
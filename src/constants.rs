@@ -4,3 +4,100 @@ use std::time::Duration;
 
 /// RPC connection timeout for Neovim instances
 pub const NEOVIM_RPC_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Pauses between attempts on a Neovim socket that refuses connections. A
+/// freshly launched nvim creates its socket a moment before it listens.
+pub const NEOVIM_CONNECT_BACKOFF: [Duration; 3] = [
+    Duration::from_millis(20),
+    Duration::from_millis(40),
+    Duration::from_millis(80),
+];
+
+/// How long a socket stays in the dead-socket cache at most, whatever the
+/// configured TTL, so the file doesn't grow
+pub const DEAD_SOCKET_CACHE_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Hex digits of the directory hash kept in a readable socket name, enough
+/// that two projects never share one in practice
+pub const READABLE_SOCKET_HASH_LEN: usize = 16;
+
+/// Longest project-name prefix on a readable socket name, keeping the path
+/// well under the OS limit for socket paths
+pub const READABLE_SOCKET_PREFIX_MAX: usize = 24;
+
+/// How long to wait for a Kakoune session to answer a query
+pub const KAKOUNE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Version of the endpoints editor plugins serve, the JetBrains plugin's
+/// `/api/sidekick` today. Bumped when a change breaks older plugins.
+pub const PLUGIN_PROTOCOL_VERSION: u32 = 1;
+
+/// How long to wait for a JetBrains IDE's built-in server to answer
+pub const JETBRAINS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait on an editor: `SIDEKICK_RPC_TIMEOUT_MS` when set, for
+/// every backend alike, else the backend's `default`
+pub fn rpc_timeout(default: Duration) -> Duration {
+    timeout_override(std::env::var("SIDEKICK_RPC_TIMEOUT_MS").ok()).unwrap_or(default)
+}
+
+/// Budget for answering one hook, editors and all, unless
+/// `SIDEKICK_HOOK_TIMEOUT_MS` says otherwise. Past it the hook allows.
+pub const HOOK_DEADLINE: Duration = Duration::from_secs(3);
+
+/// How long a hook may take to answer
+pub fn hook_deadline() -> Duration {
+    timeout_override(std::env::var("SIDEKICK_HOOK_TIMEOUT_MS").ok()).unwrap_or(HOOK_DEADLINE)
+}
+
+/// A positive number of milliseconds; anything else is no override
+fn timeout_override(value: Option<String>) -> Option<Duration> {
+    let ms: u64 = value?.trim().parse().ok()?;
+    (ms > 0).then(|| Duration::from_millis(ms))
+}
+
+/// How long a rich notification's floating window stays open
+pub const RICH_NOTIFY_DURATION: Duration = Duration::from_secs(4);
+
+/// Window over which `SIDEKICK_NOTIFY_LIMIT` counts editor notifications
+pub const NOTIFY_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// How long after a denied edit an unchanged retry goes through under
+/// `SIDEKICK_SOFT_BLOCK`
+pub const SOFT_BLOCK_GRACE: Duration = Duration::from_secs(60);
+
+/// How much of a session transcript is read at a time, backwards from the end
+pub const TRANSCRIPT_TAIL_BYTES: u64 = 64 * 1024;
+
+/// How many transcript lines, counted from the end, are searched for recent
+/// intents. Tool calls and results sit between user turns, so this is well
+/// over `TRANSCRIPT_RECENT_TURNS`.
+pub const TRANSCRIPT_TAIL_LINES: usize = 200;
+
+/// How many of the latest user turns can carry an overwrite request
+pub const TRANSCRIPT_RECENT_TURNS: usize = 3;
+
+/// Largest visual selection whose text is injected into a prompt; past it
+/// only the range is
+pub const SELECTION_MAX_BYTES: usize = 256 * 1024;
+
+/// Largest live buffer handed to the AI on a Read of a dirty file, and
+/// largest file or buffer diffed to say where unsaved changes are
+pub const READ_LIVE_CONTENT_MAX_BYTES: usize = 64 * 1024;
+
+#[cfg(test)]
+mod tests {
+    use super::timeout_override;
+    use std::time::Duration;
+
+    #[test]
+    fn rpc_timeout_override_is_read_in_milliseconds() {
+        assert_eq!(
+            timeout_override(Some(" 500 ".into())),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(timeout_override(Some("0".into())), None);
+        assert_eq!(timeout_override(Some("2s".into())), None);
+        assert_eq!(timeout_override(None), None);
+    }
+}
@@ -742,10 +742,12 @@ fn check_last_hook() -> Check {
                 ToolKind::Edit => "Edit",
                 ToolKind::Write => "Write",
                 ToolKind::MultiEdit => "MultiEdit",
+                ToolKind::Bash => "Bash",
             };
             let decision = match d.decision {
                 Decision::Allow => "allowed",
                 Decision::Deny => "blocked",
+                Decision::Ask => "asked",
             };
             let file = Path::new(&d.file)
                 .file_name()
@@ -131,7 +131,7 @@ pub(crate) fn claude_fix() -> Option<Fix> {
     }
     let path = dirs::home_dir()?.join(".claude").join("settings.json");
     let before = std::fs::read_to_string(&path).ok();
-    let after = claude_settings_after(before.as_deref()).ok()?;
+    let after = claude_settings_after(before.as_deref(), "sidekick hook").ok()?;
     Some(Fix {
         title: "Register the Claude Code hooks".into(),
         path,
@@ -167,9 +167,11 @@ pub(crate) fn alias_fix() -> Option<Fix> {
     })
 }
 
-/// Merge sidekick's three hooks into a Claude Code `settings.json`, leaving
-/// every other key — and the user's key order — untouched.
-fn claude_settings_after(before: Option<&str>) -> Result<String> {
+/// Merge sidekick's four hooks, running `command`, into a Claude Code
+/// `settings.json`, leaving every other key — and the user's key order —
+/// untouched. An event that already runs sidekick isn't given a second entry,
+/// but one running it from another path is pointed at `command`'s binary.
+pub(crate) fn claude_settings_after(before: Option<&str>, command: &str) -> Result<String> {
     let mut root: serde_json::Value = match before {
         Some(s) if !s.trim().is_empty() => {
             serde_json::from_str(s).context("~/.claude/settings.json isn't valid JSON")?
@@ -189,15 +191,19 @@ fn claude_settings_after(before: Option<&str>) -> Result<String> {
             ("PreToolUse", "Edit|Write|MultiEdit"),
             ("PostToolUse", "Edit|Write|MultiEdit"),
             ("UserPromptSubmit", ""),
+            ("Stop", ""),
         ] {
             let arr = hooks
                 .entry(event)
                 .or_insert_with(|| serde_json::json!([]))
                 .as_array_mut()
                 .with_context(|| format!("`hooks.{event}` in settings.json isn't an array"))?;
+            if repoint_sidekick(arr, command) {
+                continue;
+            }
             arr.push(serde_json::json!({
                 "matcher": matcher,
-                "hooks": [{ "type": "command", "command": "sidekick hook" }],
+                "hooks": [{ "type": "command", "command": command }],
             }));
         }
     }
@@ -206,6 +212,55 @@ fn claude_settings_after(before: Option<&str>) -> Result<String> {
     Ok(s)
 }
 
+/// Point every `sidekick hook` command in a `hooks.<event>` list at the
+/// binary `command` runs, keeping each one's arguments, say after the binary
+/// moved. Returns whether the list runs sidekick at all.
+fn repoint_sidekick(entries: &mut [serde_json::Value], command: &str) -> bool {
+    let (program, _) = split_program(command);
+    // `command` as written up to its arguments, quotes and all
+    let exe = command
+        .trim()
+        .strip_suffix("hook")
+        .unwrap_or(command)
+        .trim_end();
+    let mut runs = false;
+    for hook in entries
+        .iter_mut()
+        .filter_map(|entry| entry["hooks"].as_array_mut())
+        .flatten()
+    {
+        let Some(existing) = hook["command"]
+            .as_str()
+            .filter(|c| is_sidekick_hook_command(c))
+        else {
+            continue;
+        };
+        runs = true;
+        let (old_program, args) = split_program(existing);
+        if old_program != program {
+            hook["command"] = format!("{} {}", exe, args).into();
+        }
+    }
+    runs
+}
+
+/// Whether a hook command is `sidekick hook`, by any path to the binary
+pub(crate) fn is_sidekick_hook_command(command: &str) -> bool {
+    let (program, rest) = split_program(command);
+    std::path::Path::new(program).file_name() == Some("sidekick".as_ref())
+        && (rest == "hook" || rest.starts_with("hook "))
+}
+
+/// A command's program, unquoted, and the arguments after it
+fn split_program(command: &str) -> (&str, &str) {
+    let command = command.trim();
+    let (program, rest) = match command.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+        None => command.split_once(' ').unwrap_or((command, "")),
+    };
+    (program, rest.trim_start())
+}
+
 /// The rc file the user's login shell sources, mirroring `scripts/install.sh`.
 fn shell_rc_path() -> Option<PathBuf> {
     let shell = std::env::var("SHELL").ok()?;
@@ -511,14 +566,14 @@ fn truncate_text(s: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::claude_settings_after;
+    use super::{claude_settings_after, is_sidekick_hook_command};
 
     #[test]
-    fn merges_three_hooks_into_empty_settings() {
-        let out = claude_settings_after(None).unwrap();
+    fn merges_four_hooks_into_empty_settings() {
+        let out = claude_settings_after(None, "sidekick hook").unwrap();
         let v: serde_json::Value = serde_json::from_str(&out).unwrap();
         let hooks = &v["hooks"];
-        for event in ["PreToolUse", "PostToolUse", "UserPromptSubmit"] {
+        for event in ["PreToolUse", "PostToolUse", "UserPromptSubmit", "Stop"] {
             let arr = hooks[event].as_array().unwrap();
             assert_eq!(arr.len(), 1);
             assert_eq!(arr[0]["hooks"][0]["command"], "sidekick hook");
@@ -530,7 +585,7 @@ mod tests {
     #[test]
     fn keeps_existing_keys_order_and_hooks() {
         let before = r#"{"model":"opus","hooks":{"PreToolUse":[{"matcher":"Bash","hooks":[]}]}}"#;
-        let out = claude_settings_after(Some(before)).unwrap();
+        let out = claude_settings_after(Some(before), "sidekick hook").unwrap();
         // preserve_order keeps `model` ahead of `hooks` rather than sorting.
         assert!(out.find("\"model\"").unwrap() < out.find("\"hooks\"").unwrap());
 
@@ -542,8 +597,21 @@ mod tests {
         assert_eq!(pre[1]["hooks"][0]["command"], "sidekick hook");
     }
 
+    #[test]
+    fn recognizes_sidekick_hook_by_any_path() {
+        assert!(is_sidekick_hook_command("sidekick hook"));
+        assert!(is_sidekick_hook_command(
+            "/usr/local/bin/sidekick hook --cwd x"
+        ));
+        assert!(is_sidekick_hook_command(
+            "\"/Users/me/my bin/sidekick\" hook"
+        ));
+        assert!(!is_sidekick_hook_command("sidekick stats"));
+        assert!(!is_sidekick_hook_command("/bin/not-sidekick hook"));
+    }
+
     #[test]
     fn rejects_invalid_json() {
-        assert!(claude_settings_after(Some("{ not json")).is_err());
+        assert!(claude_settings_after(Some("{ not json"), "sidekick hook").is_err());
     }
 }
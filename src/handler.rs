@@ -18,103 +18,661 @@
 //!
 //! 3. UserPromptSubmit: Inject visual selection as additional context
 //!    - If Neovim has a visual selection → inject as additionalContext
+//!    - With `prompt_context`, also the file and lines on screen
 //!    - Otherwise → no-op
 //!
 //! # Example
 //!
 //! ```no_run
-//! use sidekick::handler;
+//! use sidekick::handler::{self, HookOptions};
 //!
 //! // Called by Claude Code (or the opencode/pi bridges) via stdin/stdout
-//! handler::handle_hook().expect("Failed to process hook");
+//! handler::handle_hook(&HookOptions::default()).expect("Failed to process hook");
 //! ```
 
 use std::io::{self, Read, Write};
-use std::time::Instant;
+use std::os::fd::{AsFd, AsRawFd};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
 
 use chrono::Utc;
 
-use crate::action::{Action, neovim::NeovimAction};
+use crate::action::{self, Action, BufferStatus, DirtySummary, EditorContext, EditorView};
 use crate::analytics::{
     self,
     event::{BufferRefresh, Decision, DecisionReason, Event, HookDecision, ToolKind},
 };
-use crate::hook::{self, Hook, HookEvent, HookOutput, PermissionDecision, Tool, ToolHook};
+use crate::config::{Config, PostAction, PreAction, Verbosity};
+use crate::constants::{
+    self, NOTIFY_RATE_WINDOW, READ_LIVE_CONTENT_MAX_BYTES, SELECTION_MAX_BYTES, SOFT_BLOCK_GRACE,
+};
+use crate::hook::{
+    self, BashToolInput, DenyDetails, Hook, HookEvent, HookOutput, OutputFormat,
+    RETRY_AFTER_SAVE_MARKER, Tool, ToolHook,
+};
+use crate::session::SessionState;
+use crate::transcript;
 use crate::utils;
 
-pub fn handle_hook() -> anyhow::Result<()> {
-    // Read hook input from stdin
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
+/// Invocation options for `sidekick hook`
+#[derive(Debug, Clone, Default)]
+pub struct HookOptions {
+    /// How the response is written to stdout
+    pub format: OutputFormat,
+    /// Project directory used for socket discovery instead of the process cwd
+    pub cwd: Option<PathBuf>,
+    /// How much to say on stderr
+    pub verbosity: Verbosity,
+    /// Read newline-delimited hooks and answer each on its own line
+    pub ndjson: bool,
+    /// Exit with `DENY_EXIT_CODE` when a response denies, not always 0
+    pub exit_code: bool,
+    /// Print each hook as parsed to stderr, see `parsed_json`
+    pub echo_parsed: bool,
+    /// Allow without reading if no input arrives on stdin within this long
+    pub stdin_timeout: Option<Duration>,
+}
+
+/// Exit code of `sidekick hook --exit-code` when a tool call was denied.
+/// Matches the code Claude Code itself reads as "block".
+pub const DENY_EXIT_CODE: i32 = 2;
+
+/// Answer the hook on stdin. Returns the code to exit with: always 0 unless
+/// `exit_code` is set, then `DENY_EXIT_CODE` on a deny.
+pub fn handle_hook(options: &HookOptions) -> anyhow::Result<i32> {
+    handle_hook_fd(io::stdin(), io::stdout().lock(), options)
+}
+
+/// `handle_hook_io`, first waiting at most `stdin_timeout` for `input` to
+/// have something to read. A pipe left open with nothing on it is answered
+/// as if it were empty, with an allow, rather than blocking the turn.
+fn handle_hook_fd(
+    input: impl Read + AsFd,
+    mut output: impl Write,
+    options: &HookOptions,
+) -> anyhow::Result<i32> {
+    if let Some(timeout) = options.stdin_timeout
+        && !wait_readable(&input, timeout)?
+    {
+        options.verbosity.warn(format_args!(
+            "no input within {}ms, allowing the tool call",
+            timeout.as_millis()
+        ));
+        let response = HookOutput::new();
+        let format = if options.ndjson {
+            OutputFormat::Json
+        } else {
+            options.format
+        };
+        output.write_all(response.render(format)?.as_bytes())?;
+        return Ok(exit_code(&response, options));
+    }
+    handle_hook_io(input, output, options)
+}
+
+/// Whether `fd` becomes readable (data or EOF) within `timeout`
+fn wait_readable(fd: impl AsFd, timeout: Duration) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd: fd.as_fd().as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let ms = left.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        // SAFETY: `pollfd` is a valid, initialized array of one for the call
+        match unsafe { libc::poll(&mut pollfd, 1, ms) } {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            -1 => return Err(io::Error::last_os_error()),
+            ready => return Ok(ready > 0),
+        }
+    }
+}
+
+/// Answer the hook(s) on `input`, writing each response to `output`. With
+/// `ndjson` every non-empty line is its own hook and gets one line back;
+/// otherwise the whole input is a single hook. Returns the exit code, as
+/// `handle_hook`; with `ndjson` any one deny counts.
+pub fn handle_hook_io(
+    mut input: impl Read,
+    mut output: impl Write,
+    options: &HookOptions,
+) -> anyhow::Result<i32> {
+    // Invalid UTF-8 (a mangled file path, say) becomes U+FFFD rather than
+    // failing the read, so the hook still gets a parse attempt
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let raw = String::from_utf8_lossy(&bytes);
 
-    // Parse the hook
-    let hook = hook::parse_hook(&input)?;
+    if !options.ndjson {
+        let response = respond_in_time(&raw, options)?;
+        output.write_all(response.render(options.format)?.as_bytes())?;
+        return Ok(exit_code(&response, options));
+    }
+
+    let mut code = 0;
+    for line in raw.lines().filter(|l| !l.trim().is_empty()) {
+        // One response per line, so always compact
+        let response = respond_in_time(line, options)?;
+        writeln!(output, "{}", response.render(OutputFormat::Json)?)?;
+        code = code.max(exit_code(&response, options));
+    }
+    Ok(code)
+}
 
-    // Resolve nvim instances once so we know how many we probed.
-    let socket_paths = utils::find_matching_sockets().unwrap_or_default();
-    let instances_probed = socket_paths.len();
-    let nvim_action = if socket_paths.is_empty() {
-        None
+/// What `sidekick hook` exits with after writing `response`
+fn exit_code(response: &HookOutput, options: &HookOptions) -> i32 {
+    if options.exit_code && response.denies() {
+        DENY_EXIT_CODE
     } else {
-        Some(NeovimAction::new(socket_paths))
+        0
+    }
+}
+
+/// `respond`, but allowing the tool call if that takes longer than the
+/// hook's budget: a late answer is worse than none, as the host kills the
+/// hook and may report it as failed
+fn respond_in_time(input: &str, options: &HookOptions) -> anyhow::Result<HookOutput> {
+    let (input, worker_options) = (input.to_string(), options.clone());
+    let deadline = constants::hook_deadline();
+    let due = Instant::now() + deadline;
+    let answer = with_deadline(deadline, move || {
+        // Past this the worker stops writing state, logging or messaging
+        utils::set_answer_due(due);
+        respond(&input, &worker_options)
+    });
+    match answer {
+        Ok(response) => response,
+        Err(RecvTimeoutError::Timeout) => {
+            options.verbosity.warn(format_args!(
+                "no answer within {}ms, allowing the tool call",
+                deadline.as_millis()
+            ));
+            Ok(HookOutput::new())
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            options
+                .verbosity
+                .warn("the hook panicked, allowing the tool call");
+            Ok(HookOutput::new())
+        }
+    }
+}
+
+/// Run `f` on its own thread and wait at most `deadline` for its result.
+/// On timeout the thread is left to finish or die with the process; if it
+/// panics, the wait ends early as `Disconnected`.
+fn with_deadline<T: Send + 'static>(
+    deadline: Duration,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, RecvTimeoutError> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    rx.recv_timeout(deadline)
+}
+
+/// Parse one hook, discover its editors and decide the response
+fn respond(input: &str, options: &HookOptions) -> anyhow::Result<HookOutput> {
+    let hook = hook::parse_hook(input)?;
+    if options.echo_parsed {
+        eprintln!("{}", parsed_json(&hook));
+    }
+
+    // Resolve editor instances once so we know how many we probed.
+    // An unreadable cwd resolves to no editors, like any failed discovery.
+    let cwd = options
+        .cwd
+        .clone()
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default();
+
+    // Project policy lives with the project the AI is working in
+    let project_dir = match &hook {
+        Hook::Tool(h) if !h.cwd.is_empty() => PathBuf::from(&h.cwd),
+        _ => cwd.clone(),
     };
+    let config = Config::load(&project_dir, options.verbosity);
+
+    let (editors, instances_probed) = action::discover(&cwd, &config);
+    config.verbosity.note(format_args!(
+        "{} editor instance(s) found for {}",
+        instances_probed,
+        cwd.display()
+    ));
 
-    // Handle based on hook type
-    let output = match hook {
+    Ok(dispatch(
+        hook,
+        editors.as_deref(),
+        instances_probed,
+        &config,
+    ))
+}
+
+/// Respond to a parsed hook given the editors discovered for it. Everything
+/// after stdin and discovery, so it can be driven with stand-in editors.
+pub fn dispatch(
+    hook: Hook,
+    nvim_action: Option<&dyn Action>,
+    instances_probed: usize,
+    config: &Config,
+) -> HookOutput {
+    if let Some(warning) = no_instance_warning(&hook, instances_probed, config) {
+        eprintln!("{}", warning);
+    }
+
+    match hook {
         Hook::Tool(h) => match h.hook_event_name {
-            HookEvent::PreToolUse => {
-                handle_pre_tool_use(&h, nvim_action.as_ref(), instances_probed)
-            }
-            HookEvent::PostToolUse => handle_post_tool_use(&h, nvim_action.as_ref()),
+            HookEvent::PreToolUse => handle_pre_tool_use(&h, nvim_action, instances_probed, config),
+            HookEvent::PostToolUse => handle_post_tool_use(&h, nvim_action, config),
         },
-        Hook::UserPrompt => handle_user_prompt_submit(nvim_action.as_ref()),
-    };
+        Hook::UserPrompt => handle_user_prompt_submit(nvim_action, config),
+        Hook::Stop { session_id } => handle_stop(&session_id, nvim_action),
+    }
+}
 
-    // Return hook output
-    io::stdout().write_all(output.to_json()?.as_bytes())?;
+/// Handle Stop hook - the turn is over, so clear the editing indicator
+/// PreToolUse set
+fn handle_stop(session_id: &str, nvim_action: Option<&dyn Action>) -> HookOutput {
+    if let Some(action) = nvim_action {
+        let _ = action.set_status(false, None);
+    }
+    let mut state = SessionState::load(session_id);
+    if state.status.take().is_some() {
+        state.save(session_id);
+    }
+    HookOutput::new()
+}
 
-    Ok(())
+/// How sidekick read a hook, for `sidekick hook --echo-parsed`: the hook
+/// re-serialized with its variant under `hook`, and a file tool's path
+/// resolved against the hook's cwd under `resolved_path`
+fn parsed_json(hook: &Hook) -> serde_json::Value {
+    let mut value = serde_json::to_value(hook).unwrap_or_default();
+    if let Hook::Tool(h) = hook
+        && let Tool::Read(f) | Tool::Write(f) | Tool::Edit(f) | Tool::MultiEdit(f) = &h.tool
+        && let Some(file_path) = &f.file_path
+    {
+        let resolved = utils::normalize_path(&Path::new(&h.cwd).join(file_path));
+        value["resolved_path"] = resolved.to_string_lossy().into();
+    }
+    value
+}
+
+/// Setup aid: with `warn_no_instance` on, explain why a file tool went
+/// unguarded when discovery came back empty. Silent otherwise.
+fn no_instance_warning(hook: &Hook, instances_probed: usize, config: &Config) -> Option<String> {
+    if !config.warn_no_instance || !config.verbosity.shows_warnings() || instances_probed > 0 {
+        return None;
+    }
+    let Hook::Tool(h) = hook else {
+        return None;
+    };
+    let (_, file_path) = tool_to_mutation(&h.tool)?;
+
+    Some(format!(
+        "sidekick: no editor found for this directory, {} is unprotected \
+         (launch it with `sidekick neovim` or `sidekick kakoune`)",
+        file_path
+    ))
 }
 
 /// Handle PreToolUse hook - check if file has unsaved changes
 fn handle_pre_tool_use(
     h: &ToolHook,
-    nvim_action: Option<&NeovimAction>,
+    nvim_action: Option<&dyn Action>,
     instances_probed: usize,
+    config: &Config,
 ) -> HookOutput {
+    if let Tool::Read(input) = &h.tool {
+        return match input.file_path.as_deref() {
+            Some(file_path) => warn_on_stale_read(nvim_action, file_path, config),
+            None => HookOutput::new(),
+        };
+    }
+
+    if let Tool::Bash(input) = &h.tool {
+        return match nvim_action {
+            Some(action) => guard_bash_deletes(h, action, input, instances_probed, config),
+            None => HookOutput::new(),
+        };
+    }
+
     let Some((tool_kind, file_path)) = tool_to_mutation(&h.tool) else {
         return HookOutput::new();
     };
 
+    let pre = config.pre_action(tool_name(tool_kind));
+    if pre == PreAction::Ignore {
+        return HookOutput::new();
+    }
+
+    if config.trust_bypass_mode && h.bypasses_permissions() {
+        config.verbosity.note(format_args!(
+            "bypassPermissions, not checking {}",
+            file_path
+        ));
+        return HookOutput::new();
+    }
+
     let started = Instant::now();
-    let (output, reason) = check_buffer_modifications(nvim_action, file_path);
-    let decision = match reason {
-        DecisionReason::BufferDirtyAndCurrent => Decision::Deny,
-        _ => Decision::Allow,
+    let missing = config.warn_missing_target && edits_missing_file(h, tool_kind, file_path);
+    let (output, reason) = check_buffer_modifications(h, nvim_action, file_path, config);
+    let output = match output.system_message {
+        None if missing => output.with_system_message(format!(
+            "sidekick: {} doesn't exist on disk; check the path",
+            file_path
+        )),
+        _ => output,
+    };
+    let output = if pre == PreAction::Ask {
+        output.ask_instead()
+    } else {
+        output
     };
+    let decision = decision_of(&output);
+    config
+        .verbosity
+        .note(format_args!("{:?} {} ({:?})", decision, file_path, reason));
+
+    record_decision(
+        h,
+        tool_kind,
+        file_path,
+        (decision, reason),
+        instances_probed,
+        started,
+    );
+
+    // Shown while the turn edits; the Stop hook clears it. Only sent when
+    // the file shown changes, not on every edit.
+    if let Some(action) = nvim_action
+        && !output.denies()
+        && !utils::answer_overdue()
+    {
+        let mut state = SessionState::load(&h.session_id);
+        if state.status.as_deref() != Some(file_path)
+            && action.set_status(true, Some(file_path.to_string())).is_ok()
+        {
+            state.status = Some(file_path.to_string());
+            state.save(&h.session_id);
+        }
+    }
+
+    output
+}
 
+/// Append a PreToolUse decision to the analytics store
+fn record_decision(
+    h: &ToolHook,
+    tool: ToolKind,
+    file_path: &str,
+    (decision, reason): (Decision, DecisionReason),
+    instances_probed: usize,
+    started: Instant,
+) {
     analytics::store::append(&Event::HookDecision(HookDecision {
         at: Utc::now(),
         session_id: h.session_id.clone(),
         cwd: h.cwd.clone(),
-        tool: tool_kind,
+        tool,
         file: file_path.to_string(),
         decision,
         reason,
         instances_probed,
         latency_ms: started.elapsed().as_millis() as u64,
     }));
+}
 
-    output
+/// With `swapfile_fallback`, when no editor could say whether the file has
+/// unsaved changes: ask the user if a swap file says it might
+fn swapfile_fallback(
+    h: &ToolHook,
+    file_path: &str,
+    config: &Config,
+) -> Option<(HookOutput, DecisionReason)> {
+    if !config.swapfile_fallback || !utils::has_swapfile(&Path::new(&h.cwd).join(file_path)) {
+        return None;
+    }
+    let output = HookOutput::deny(
+        "No editor could be reached, but this file has a Vim swap file, so an editor \
+         may hold unsaved changes to it. Check with the user before overwriting it",
+    )
+    .ask_instead();
+    Some((output, DecisionReason::SwapFile))
+}
+
+/// What a response did to the tool call, as analytics records it
+fn decision_of(output: &HookOutput) -> Decision {
+    if output.denies() {
+        Decision::Deny
+    } else if output.asks() {
+        Decision::Ask
+    } else {
+        Decision::Allow
+    }
+}
+
+/// Why unsaved changes blocked the call: the current buffer's, or only
+/// another buffer's under the `any` unsaved policy
+fn dirty_reason(statuses: &[BufferStatus]) -> DecisionReason {
+    if statuses
+        .iter()
+        .any(|s| s.is_current && s.has_unsaved_changes)
+    {
+        DecisionReason::BufferDirtyAndCurrent
+    } else {
+        DecisionReason::BufferDirty
+    }
+}
+
+/// With `fail_closed`, when a running editor couldn't say whether the file
+/// has unsaved changes: ask the user rather than allow
+fn fail_closed(config: &Config) -> Option<(HookOutput, DecisionReason)> {
+    config.fail_closed.then(|| {
+        let output = HookOutput::deny(
+            "An editor is open on this project but couldn't be asked whether this file \
+             has unsaved changes. Check with the user before changing it",
+        )
+        .ask_instead();
+        (output, DecisionReason::StatusCheckFailed)
+    })
+}
+
+/// Whether `file_path` lies under one of `protect_dirs`, or any path when
+/// none are set. Relative paths are taken from the hook's cwd.
+fn is_protected(h: &ToolHook, file_path: &str, config: &Config) -> bool {
+    if config.protect_dirs.is_empty() {
+        return true;
+    }
+    let cwd = Path::new(&h.cwd);
+    let file = utils::normalize_path(&cwd.join(file_path));
+    config
+        .protect_dirs
+        .iter()
+        .any(|dir| file.starts_with(utils::normalize_path(&cwd.join(dir))))
+}
+
+/// Whether the tool edits a file that isn't on disk. A write creates one.
+fn edits_missing_file(h: &ToolHook, tool_kind: ToolKind, file_path: &str) -> bool {
+    tool_kind != ToolKind::Write && !Path::new(&h.cwd).join(file_path).exists()
+}
+
+/// Deny a Bash command that would delete or move a file the user is
+/// editing: their unsaved changes would have nothing left to be saved to.
+/// Follows the `Bash` tool policy, `protect_dirs` and `trust_bypass_mode`
+/// as edits do, and records a decision for each target it checks.
+fn guard_bash_deletes(
+    h: &ToolHook,
+    action: &dyn Action,
+    input: &BashToolInput,
+    instances_probed: usize,
+    config: &Config,
+) -> HookOutput {
+    let pre = config.pre_action(tool_name(ToolKind::Bash));
+    if pre == PreAction::Ignore {
+        return HookOutput::new();
+    }
+    if config.trust_bypass_mode && h.bypasses_permissions() {
+        config
+            .verbosity
+            .note(format_args!("bypassPermissions, not checking bash deletes"));
+        return HookOutput::new();
+    }
+
+    for target in input.delete_targets() {
+        let started = Instant::now();
+        let path = Path::new(&h.cwd).join(&target);
+        let file_path = path.to_string_lossy();
+        let record = |outcome| {
+            record_decision(
+                h,
+                ToolKind::Bash,
+                &file_path,
+                outcome,
+                instances_probed,
+                started,
+            )
+        };
+        if !is_protected(h, &file_path, config) {
+            record((Decision::Allow, DecisionReason::Unprotected));
+            continue;
+        }
+        // Most targets aren't open at all; that's the cheap question
+        if !action.is_open(&file_path).unwrap_or(true) {
+            record((Decision::Allow, DecisionReason::BufferAvailable));
+            continue;
+        }
+        let Ok(statuses) = action.buffer_status(&file_path) else {
+            if let Some((output, reason)) = fail_closed(config) {
+                record((decision_of(&output), reason));
+                return output;
+            }
+            record((Decision::Allow, DecisionReason::StatusCheckFailed));
+            continue;
+        };
+        if action::merge_statuses(&statuses, config.unsaved) == Decision::Allow {
+            record((Decision::Allow, DecisionReason::BufferAvailable));
+            continue;
+        }
+
+        let reason = format!(
+            "The command would delete or move {}, which has unsaved changes in the \
+             editor. Try again once the user saves it",
+            target
+        );
+        let reason = config.deny_message.as_deref().unwrap_or(&reason);
+        config
+            .verbosity
+            .note(format_args!("Deny bash delete of {}", file_path));
+        let output = deny(
+            h,
+            action,
+            &statuses,
+            DenyDetails::new(file_path.clone()),
+            config,
+            "a command would delete it",
+            reason,
+        );
+        let output = if pre == PreAction::Ask {
+            output.ask_instead()
+        } else {
+            output
+        };
+        record((decision_of(&output), dirty_reason(&statuses)));
+        return output;
+    }
+    HookOutput::new()
+}
+
+/// Reads are never blocked, but one of a file with unsaved changes returns
+/// disk content the user no longer sees. Say so, so the AI can allow for it,
+/// and with `read_live_content` hand over what the editor shows.
+fn warn_on_stale_read(
+    nvim_action: Option<&dyn Action>,
+    file_path: &str,
+    config: &Config,
+) -> HookOutput {
+    let Some(action) = nvim_action else {
+        return HookOutput::new();
+    };
+    let dirty = action
+        .buffer_status(file_path)
+        .is_ok_and(|statuses| statuses.iter().any(|s| s.has_unsaved_changes));
+
+    if !dirty {
+        return HookOutput::new();
+    }
+
+    let output = HookOutput::new().with_system_message(format!(
+        "sidekick: {} has unsaved changes in the editor, so this read shows the \
+         saved version, not what the user sees",
+        file_path
+    ));
+
+    if !config.read_live_content {
+        return output;
+    }
+
+    // The dirty instance is the one whose text differs from disk
+    let on_disk = std::fs::read_to_string(file_path).ok();
+    let live = action.buffer_contents(file_path).ok().and_then(|contents| {
+        contents
+            .into_iter()
+            .find(|c| Some(c.trim_end()) != on_disk.as_deref().map(str::trim_end))
+    });
+
+    match live {
+        Some(live) => output.with_tool_context(live_content_context(file_path, &live)),
+        None => output,
+    }
+}
+
+/// The editor's text for `file_path` as a fenced block, capped at
+/// `READ_LIVE_CONTENT_MAX_BYTES`
+fn live_content_context(file_path: &str, live: &str) -> String {
+    let mut end = live.len().min(READ_LIVE_CONTENT_MAX_BYTES);
+    while !live.is_char_boundary(end) {
+        end -= 1;
+    }
+    let truncated = if end < live.len() {
+        format!("\n[truncated after {} bytes]", end)
+    } else {
+        String::new()
+    };
+
+    format!(
+        "[Unsaved editor content of {} — rely on this over the read result]\n```\n{}\n```{}",
+        file_path,
+        &live[..end],
+        truncated
+    )
 }
 
 /// Handle PostToolUse hook - refresh buffers after modifications
-fn handle_post_tool_use(h: &ToolHook, nvim_action: Option<&NeovimAction>) -> HookOutput {
+fn handle_post_tool_use(
+    h: &ToolHook,
+    nvim_action: Option<&dyn Action>,
+    config: &Config,
+) -> HookOutput {
     let Some((tool_kind, file_path)) = tool_to_mutation(&h.tool) else {
         return HookOutput::new();
     };
 
-    let output = refresh_buffer(nvim_action, file_path);
+    let mut state = SessionState::load(&h.session_id);
+    if state.modified.insert(file_path.to_string()) {
+        state.save(&h.session_id);
+    }
+
+    // Refresh off for this tool: the editor reloads on its own (e.g. `autoread`)
+    if config.post_action(tool_name(tool_kind)) == PostAction::Ignore {
+        return HookOutput::new();
+    }
+
+    let output = refresh_buffer(nvim_action, file_path, config);
 
     // Only count refreshes when nvim was reachable — otherwise nothing happened
     // and recording the event would inflate the activity charts.
@@ -131,71 +689,484 @@ fn handle_post_tool_use(h: &ToolHook, nvim_action: Option<&NeovimAction>) -> Hoo
     output
 }
 
-/// Handle UserPromptSubmit hook - inject visual selections as context
-fn handle_user_prompt_submit(nvim_action: Option<&NeovimAction>) -> HookOutput {
+/// Handle UserPromptSubmit hook - inject visual selections as context, and
+/// with `prompt_context` what the user is looking at where nothing in that
+/// file is selected
+fn handle_user_prompt_submit(nvim_action: Option<&dyn Action>, config: &Config) -> HookOutput {
     let Some(action) = nvim_action else {
         return HookOutput::new();
     };
 
-    let Ok(selections) = action.get_visual_selections() else {
-        return HookOutput::new();
+    let selections = action::dedup_selections(
+        action
+            .get_visual_selections(Some(SELECTION_MAX_BYTES))
+            .unwrap_or_default(),
+    );
+    let views = if config.prompt_context {
+        action.current_views().unwrap_or_default()
+    } else {
+        Vec::new()
     };
 
-    if selections.is_empty() {
-        return HookOutput::new();
-    }
-
     let context = selections
         .iter()
-        .map(|ctx| {
-            format!(
-                "[Selected from {}:{}-{}]\n```\n{}\n```",
-                ctx.file_path, ctx.start_line, ctx.end_line, ctx.content
-            )
-        })
+        .map(format_selection)
+        .chain(
+            views
+                .iter()
+                .filter(|v| !selections.iter().any(|s| s.file_path == v.file_path))
+                .map(format_view),
+        )
         .collect::<Vec<_>>()
         .join("\n\n");
 
+    if context.is_empty() {
+        return HookOutput::new();
+    }
     HookOutput::new().with_additional_context(context)
 }
 
+fn format_view(view: &EditorView) -> String {
+    format!(
+        "[User is looking at {}:{}-{}]",
+        view.file_path, view.first_line, view.last_line
+    )
+}
+
+/// Render one selection as a fenced block, tagged with its language and the
+/// git revision it was taken at when the editor knows them.
+fn format_selection(ctx: &EditorContext) -> String {
+    let revision = match (&ctx.git_branch, &ctx.git_sha) {
+        (Some(branch), Some(sha)) => format!(" @ {}:{}", branch, short_sha(sha)),
+        (Some(branch), None) => format!(" @ {}", branch),
+        (None, Some(sha)) => format!(" @ {}", short_sha(sha)),
+        (None, None) => String::new(),
+    };
+
+    let header = format!(
+        "[Selected from {}:{}-{}{}]",
+        ctx.file_path, ctx.start_line, ctx.end_line, revision
+    );
+    match &ctx.content {
+        Some(content) => format!(
+            "{}\n```{}\n{}\n```",
+            header,
+            ctx.language.as_deref().unwrap_or(""),
+            content
+        ),
+        None if ctx.truncated => format!(
+            "{}\n(selection too large to include; read those lines instead)",
+            header
+        ),
+        None => header,
+    }
+}
+
+fn short_sha(sha: &str) -> &str {
+    &sha[..sha.len().min(12)]
+}
+
 /// Check if buffer has unsaved modifications and block if necessary.
 /// Returns the hook response alongside a `DecisionReason` for analytics.
 fn check_buffer_modifications(
-    nvim_action: Option<&NeovimAction>,
+    h: &ToolHook,
+    nvim_action: Option<&dyn Action>,
     file_path: &str,
+    config: &Config,
 ) -> (HookOutput, DecisionReason) {
+    if !is_protected(h, file_path, config) {
+        return (HookOutput::new(), DecisionReason::Unprotected);
+    }
+
     let Some(action) = nvim_action else {
-        return (HookOutput::new(), DecisionReason::NoNvimRunning);
+        return swapfile_fallback(h, file_path, config)
+            .unwrap_or((HookOutput::new(), DecisionReason::NoNvimRunning));
     };
 
-    let Ok(status) = action.buffer_status(file_path) else {
-        return (HookOutput::new(), DecisionReason::StatusCheckFailed);
+    let Ok(statuses) = action.buffer_status(file_path) else {
+        return swapfile_fallback(h, file_path, config)
+            .or_else(|| fail_closed(config))
+            .unwrap_or((HookOutput::new(), DecisionReason::StatusCheckFailed));
     };
 
-    if status.has_unsaved_changes && status.is_current {
-        if let Err(e) = action.send_message("Edit blocked — file has unsaved changes") {
-            eprintln!("Warning: {}", e);
+    // The user approved this edit from the editor after it was blocked
+    let mut state = SessionState::load(&h.session_id);
+    if state.take_approved(file_path) {
+        state.save(&h.session_id);
+        let output = HookOutput::new().with_system_message(format!(
+            "sidekick: the user approved overwriting {}",
+            file_path
+        ));
+        return (output, DecisionReason::UserOverride);
+    }
+
+    if action::merge_statuses(&statuses, config.unsaved) == Decision::Deny {
+        if config.honor_transcript && overwrite_requested(&h.transcript_path, file_path) {
+            return (HookOutput::new(), DecisionReason::UserOverride);
+        }
+
+        // Soft blocking: the user saw this exact edit blocked moments ago
+        // and left it, so let the retry through
+        let edit = config.soft_block.then(|| edit_hash(&h.tool));
+        if let Some(edit) = &edit
+            && state.take_soft_retry(file_path, edit, Utc::now(), SOFT_BLOCK_GRACE)
+        {
+            state.take_denied(file_path);
+            state.save(&h.session_id);
+            let output = HookOutput::new().with_system_message(format!(
+                "sidekick: {} still has unsaved changes, but the user was warned; \
+                 letting the retried edit through",
+                file_path
+            ));
+            return (output, DecisionReason::SoftBlockRetry);
         }
 
-        let output = HookOutput::new().with_permission_decision(
-            PermissionDecision::Deny,
-            Some("The file is being edited by the user, try again later".to_string()),
+        // An edit landing on the lines the user has selected is the
+        // sharpest conflict, so say exactly that. One fetch of the live
+        // text answers that and where the unsaved changes are.
+        let contents = action.buffer_contents(file_path).unwrap_or_default();
+        let details = DenyDetails::new(file_path);
+        let overlap = edit_overlaps_selection(&h.tool, action, file_path, &contents);
+        let (details, detail, reason) = match overlap {
+            Some((start, end)) => (
+                details.with_lines(start, end),
+                format!(
+                    "the edit overlaps lines {}-{} you're working on",
+                    start, end
+                ),
+                format!(
+                    "The user is working on lines {}-{} of this file right now and this \
+                     edit overlaps them. Leave that region alone, or try again once they save it",
+                    start, end
+                ),
+            ),
+            None => match DirtySummary::of(file_path, &contents) {
+                Some(summary) => (
+                    details.with_lines(
+                        summary.first_line,
+                        (summary.first_line + summary.changed_lines as u32)
+                            .saturating_sub(1)
+                            .max(summary.first_line),
+                    ),
+                    format!("file has {}", summary),
+                    EDITING_REASON.to_string(),
+                ),
+                None => (
+                    details,
+                    "file has unsaved changes".to_string(),
+                    EDITING_REASON.to_string(),
+                ),
+            },
+        };
+        let reason = config.deny_message.as_deref().unwrap_or(&reason);
+        let output = deny(h, action, &statuses, details, config, &detail, reason);
+        if let Some(edit) = edit {
+            let mut state = SessionState::load(&h.session_id);
+            state.record_soft_denied(file_path, &edit, Utc::now());
+            state.save(&h.session_id);
+        }
+        (output, dirty_reason(&statuses))
+    } else if edits_stale_content(&h.tool, action, &statuses, file_path) {
+        let output = deny(
+            h,
+            action,
+            &statuses,
+            DenyDetails::new(file_path),
+            config,
+            "it doesn't match your unsaved changes",
+            "The file has unsaved changes in the editor and old_string no longer \
+             occurs in them, so this edit was made against stale content. Try again \
+             once the user saves it, re-reading the file first",
         );
-        (output, DecisionReason::BufferDirtyAndCurrent)
+        (output, DecisionReason::StaleContent)
+    } else {
+        // A retry of an edit we blocked earlier: the user has saved since.
+        let mut state = SessionState::load(&h.session_id);
+        let output = if state.take_denied(file_path) {
+            state.save(&h.session_id);
+            HookOutput::new().with_system_message(format!(
+                "sidekick: {} was saved, retrying the blocked edit",
+                file_path
+            ))
+        } else {
+            HookOutput::new()
+        };
+        (output, DecisionReason::BufferAvailable)
+    }
+}
+
+/// Why the AI is told an edit was blocked, when the user is simply editing
+/// the file
+const EDITING_REASON: &str =
+    "The file is being edited by the user, try again later once they save it";
+
+/// Block the edit: tell the editor why (`detail`, within the notification
+/// cap), remember the file so the retry after a save is recognized, and give
+/// the AI `reason` with `details`, naming the editor holding the changes
+fn deny(
+    h: &ToolHook,
+    action: &dyn Action,
+    statuses: &[BufferStatus],
+    mut details: DenyDetails,
+    config: &Config,
+    detail: &str,
+    reason: &str,
+) -> HookOutput {
+    let file_path: &str = &details.file;
+    notify_block(h, action, statuses, file_path, detail, config);
+
+    let mut state = SessionState::load(&h.session_id);
+    state.record_denied(file_path, Utc::now());
+    state.save(&h.session_id);
+
+    details.editor = statuses
+        .iter()
+        .find(|s| s.has_unsaved_changes)
+        .and_then(|s| s.instance.clone());
+    HookOutput::deny(details.append_to(&format!("{} {}", reason, RETRY_AFTER_SAVE_MARKER)))
+}
+
+/// Tell the user an edit of `file_path` was blocked, in the editor or, when
+/// none has focus, on stderr. Nothing goes out past the answer's deadline
+/// or the notification cap, and only then is the editor asked about focus.
+fn notify_block(
+    h: &ToolHook,
+    action: &dyn Action,
+    statuses: &[BufferStatus],
+    file_path: &str,
+    detail: &str,
+    config: &Config,
+) {
+    if utils::answer_overdue() || !notification_allowed(&h.session_id, config) {
+        return;
+    }
+    let prefix = &config.notify_prefix;
+    let notifier = Notifier {
+        action,
+        max: config.max_msg_len,
+    };
+    // Nobody is looking at the editor; the terminal running the AI is the
+    // likelier place to be seen
+    let sent = if !action.is_focused().unwrap_or(true) {
+        notifier.eprint(&with_prefix(
+            prefix,
+            &format!("Edit blocked — {}: {}", file_path, detail),
+        ));
+        Ok(())
+    } else if config.rich_notify {
+        notifier.notify_rich(
+            &with_prefix(prefix, "Edit blocked"),
+            &format!(
+                "{}: {}\nSave it (:w) and the AI can retry.",
+                file_path, detail
+            ),
+        )
     } else {
-        (HookOutput::new(), DecisionReason::BufferAvailable)
+        send_to_holders(
+            &notifier,
+            statuses,
+            &with_prefix(prefix, &format!("Edit blocked — {}", detail)),
+            config.notify_level,
+        )
+    };
+    if let Err(e) = sent {
+        config.verbosity.warn(e);
+    }
+}
+
+/// Identifies an edit by its tool and input, so a retry can be told apart
+/// from a different edit to the same file
+fn edit_hash(tool: &Tool) -> String {
+    let input = serde_json::to_vec(tool).unwrap_or_default();
+    blake3::hash(&input).to_hex().to_string()
+}
+
+/// Send `message` only to the instances holding unsaved changes, so other
+/// windows on the same project stay quiet. Falls back to every instance when
+/// one of them can't be addressed or none took it.
+fn send_to_holders(
+    notifier: &Notifier,
+    statuses: &[BufferStatus],
+    message: &str,
+    level: action::NotifyLevel,
+) -> anyhow::Result<()> {
+    let holders: Option<Vec<&str>> = statuses
+        .iter()
+        .filter(|s| s.has_unsaved_changes)
+        .map(|s| s.instance.as_deref())
+        .collect();
+    if let Some(holders) = holders {
+        let delivered = holders
+            .iter()
+            .filter(|instance| notifier.send_message_to(instance, message, level).is_ok())
+            .count();
+        if delivered > 0 {
+            return Ok(());
+        }
+    }
+    notifier.send_message(message, level)
+}
+
+/// Where block notices go out, to the editor or stderr. Every one is cut to
+/// `max` characters here, so no path can skip it.
+struct Notifier<'a> {
+    action: &'a dyn Action,
+    max: Option<usize>,
+}
+
+impl Notifier<'_> {
+    fn send_message(&self, message: &str, level: action::NotifyLevel) -> anyhow::Result<()> {
+        self.action
+            .send_message(&truncate_message(message, self.max), level)
+    }
+
+    fn send_message_to(
+        &self,
+        instance: &str,
+        message: &str,
+        level: action::NotifyLevel,
+    ) -> anyhow::Result<()> {
+        self.action
+            .send_message_to(instance, &truncate_message(message, self.max), level)
+    }
+
+    fn notify_rich(&self, title: &str, body: &str) -> anyhow::Result<()> {
+        self.action.notify_rich(
+            &truncate_message(title, self.max),
+            &truncate_message(body, self.max),
+        )
+    }
+
+    fn eprint(&self, message: &str) {
+        eprintln!("{}", truncate_message(message, self.max));
+    }
+}
+
+/// `message` cut to `max` characters, the last of them `…`
+fn truncate_message(message: &str, max: Option<usize>) -> String {
+    match max {
+        Some(max) if message.chars().count() > max => {
+            let mut cut: String = message.chars().take(max.saturating_sub(1)).collect();
+            cut.push('…');
+            cut
+        }
+        _ => message.to_string(),
+    }
+}
+
+/// `message` branded with `prefix`, unless it already is
+fn with_prefix(prefix: &str, message: &str) -> String {
+    if message.starts_with(prefix) {
+        message.to_string()
+    } else {
+        format!("{}{}", prefix, message)
+    }
+}
+
+/// For an Edit of a file with unsaved changes, whether `old_string` is gone
+/// from the text the user sees. Applied to disk, the edit would land on
+/// content that no longer matches the buffer.
+fn edits_stale_content(
+    tool: &Tool,
+    action: &dyn Action,
+    statuses: &[BufferStatus],
+    file_path: &str,
+) -> bool {
+    let Tool::Edit(input) = tool else {
+        return false;
+    };
+    let Some(old_string) = input.old_string.as_deref().filter(|s| !s.is_empty()) else {
+        return false;
+    };
+    if !statuses.iter().any(|s| s.has_unsaved_changes) {
+        return false;
+    }
+
+    action
+        .buffer_contents(file_path)
+        .is_ok_and(|contents| contents.iter().any(|c| !c.contains(old_string)))
+}
+
+/// For an Edit, the user's selection in `file_path` that the lines matching
+/// `old_string` in its live `contents` overlap, as a 1-based inclusive range
+fn edit_overlaps_selection(
+    tool: &Tool,
+    action: &dyn Action,
+    file_path: &str,
+    contents: &[String],
+) -> Option<(u32, u32)> {
+    let Tool::Edit(input) = tool else {
+        return None;
+    };
+    let old_string = input.old_string.as_deref().filter(|s| !s.is_empty())?;
+    let selections: Vec<EditorContext> = action
+        .get_visual_selections(Some(0))
+        .ok()?
+        .into_iter()
+        .filter(|s| same_file(&s.file_path, file_path))
+        .collect();
+    if selections.is_empty() {
+        return None;
+    }
+
+    let edits: Vec<(u32, u32)> = contents
+        .iter()
+        .filter_map(|content| {
+            let at = content.find(old_string)?;
+            let first = content[..at].matches('\n').count() as u32 + 1;
+            let last = first + old_string.trim_end_matches('\n').matches('\n').count() as u32;
+            Some((first, last))
+        })
+        .collect();
+
+    selections.iter().find_map(|s| {
+        edits
+            .iter()
+            .any(|&(first, last)| first <= s.end_line && s.start_line <= last)
+            .then_some((s.start_line, s.end_line))
+    })
+}
+
+/// Whether two paths name the same file, once resolved where they exist
+fn same_file(a: &str, b: &str) -> bool {
+    utils::normalize_path(Path::new(a)) == utils::normalize_path(Path::new(b))
+}
+
+/// Whether one of the user's latest prompts asked to overwrite this file.
+/// A missing or unreadable transcript counts as no.
+fn overwrite_requested(transcript_path: &str, file_path: &str) -> bool {
+    if transcript_path.is_empty() {
+        return false;
     }
+    transcript::recent_intents(Path::new(transcript_path))
+        .is_ok_and(|intents| intents.iter().any(|i| i.permits_overwrite(file_path)))
+}
+
+/// Apply the per-session notification cap, if one is configured. Past the
+/// cap the edit is still denied; only the editor notification is dropped.
+fn notification_allowed(session_id: &str, config: &Config) -> bool {
+    let Some(limit) = config.notify_limit else {
+        return true;
+    };
+
+    let mut state = SessionState::load(session_id);
+    let allowed = state.try_record_notification(Utc::now(), limit, NOTIFY_RATE_WINDOW);
+    state.save(session_id);
+    allowed
 }
 
 /// Refresh buffer after file modification
-fn refresh_buffer(nvim_action: Option<&NeovimAction>, file_path: &str) -> HookOutput {
+fn refresh_buffer(
+    nvim_action: Option<&dyn Action>,
+    file_path: &str,
+    config: &Config,
+) -> HookOutput {
     let Some(action) = nvim_action else {
         return HookOutput::new();
     };
 
     if let Err(e) = action.refresh_buffer(file_path) {
-        eprintln!("Warning: {}", e);
+        config.verbosity.warn(e);
     }
 
     HookOutput::new()
@@ -203,9 +1174,1240 @@ fn refresh_buffer(nvim_action: Option<&NeovimAction>, file_path: &str) -> HookOu
 
 fn tool_to_mutation(tool: &Tool) -> Option<(ToolKind, &str)> {
     match tool {
-        Tool::Edit(f) => Some((ToolKind::Edit, f.file_path.as_str())),
-        Tool::Write(f) => Some((ToolKind::Write, f.file_path.as_str())),
-        Tool::MultiEdit(f) => Some((ToolKind::MultiEdit, f.file_path.as_str())),
+        Tool::Edit(f) => Some((ToolKind::Edit, f.file_path.as_deref()?)),
+        Tool::Write(f) => Some((ToolKind::Write, f.file_path.as_deref()?)),
+        Tool::MultiEdit(f) => Some((ToolKind::MultiEdit, f.file_path.as_deref()?)),
         _ => None,
     }
 }
+
+/// The Claude Code tool name a policy refers to
+fn tool_name(kind: ToolKind) -> &'static str {
+    match kind {
+        ToolKind::Edit => "Edit",
+        ToolKind::Write => "Write",
+        ToolKind::MultiEdit => "MultiEdit",
+        ToolKind::Bash => "Bash",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ToolActions, UnsavedPolicy};
+    use crate::hook::HookBuilder;
+    use crate::session;
+
+    /// Stand-in editor that reports fixed per-instance statuses.
+    #[derive(Default)]
+    struct MockAction {
+        statuses: Vec<BufferStatus>,
+        contents: Vec<String>,
+        views: Vec<EditorView>,
+        selections: Vec<EditorContext>,
+        refreshes: std::cell::Cell<usize>,
+        status_queries: std::cell::Cell<usize>,
+        content_fetches: std::cell::Cell<usize>,
+        unfocused: bool,
+        focus_queries: std::cell::Cell<usize>,
+        /// Fail every status query, as a wedged editor would
+        wedged: bool,
+        /// `(active, detail)` per status change
+        indicator: std::cell::RefCell<Vec<(bool, Option<String>)>>,
+        /// `(instance, message)` per send; `None` for a broadcast
+        sent: std::cell::RefCell<Vec<(Option<String>, String)>>,
+    }
+
+    impl Action for MockAction {
+        fn buffer_status(&self, _file_path: &str) -> anyhow::Result<Vec<BufferStatus>> {
+            self.status_queries.set(self.status_queries.get() + 1);
+            anyhow::ensure!(!self.wedged, "couldn't query every Neovim instance");
+            Ok(self.statuses.clone())
+        }
+
+        fn buffer_contents(&self, _file_path: &str) -> anyhow::Result<Vec<String>> {
+            self.content_fetches.set(self.content_fetches.get() + 1);
+            Ok(self.contents.clone())
+        }
+
+        fn refresh_buffer(&self, _file_path: &str) -> anyhow::Result<()> {
+            self.refreshes.set(self.refreshes.get() + 1);
+            Ok(())
+        }
+
+        fn send_message(&self, message: &str, _level: action::NotifyLevel) -> anyhow::Result<()> {
+            self.sent.borrow_mut().push((None, message.to_string()));
+            Ok(())
+        }
+
+        fn send_message_to(
+            &self,
+            instance: &str,
+            message: &str,
+            _level: action::NotifyLevel,
+        ) -> anyhow::Result<()> {
+            self.sent
+                .borrow_mut()
+                .push((Some(instance.to_string()), message.to_string()));
+            Ok(())
+        }
+
+        fn is_focused(&self) -> anyhow::Result<bool> {
+            self.focus_queries.set(self.focus_queries.get() + 1);
+            Ok(!self.unfocused)
+        }
+
+        fn set_status(&self, active: bool, detail: Option<String>) -> anyhow::Result<()> {
+            self.indicator.borrow_mut().push((active, detail));
+            Ok(())
+        }
+
+        fn list_buffers(&self) -> anyhow::Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn get_visual_selections(
+            &self,
+            _max_bytes: Option<usize>,
+        ) -> anyhow::Result<Vec<EditorContext>> {
+            Ok(self.selections.clone())
+        }
+
+        fn current_views(&self) -> anyhow::Result<Vec<EditorView>> {
+            Ok(self.views.clone())
+        }
+    }
+
+    fn status(is_current: bool, has_unsaved_changes: bool) -> BufferStatus {
+        BufferStatus {
+            is_current,
+            has_unsaved_changes,
+            instance: None,
+        }
+    }
+
+    /// PreToolUse Edit of `foo.rs`. Session ids are unique per test since
+    /// denies are remembered in the shared session cache.
+    fn edit_hook(session: &str) -> ToolHook {
+        HookBuilder::new()
+            .session(format!("test-{}-{}", session, std::process::id()))
+            .edit("foo.rs")
+            .build_tool()
+    }
+
+    #[test]
+    fn built_hooks_go_through_the_handler() {
+        let session_id = format!("built-{}", std::process::id());
+        let dirty = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let config = Config::default();
+
+        let edit = HookBuilder::new()
+            .session(&session_id)
+            .edit("foo.rs")
+            .build();
+        let output = dispatch(edit, Some(&dirty), 1, &config);
+        let _ = std::fs::remove_file(session::path(&session_id));
+        assert!(output.denies());
+
+        let refresh = HookBuilder::new()
+            .session(&session_id)
+            .event(HookEvent::PostToolUse)
+            .write("foo.rs", "x")
+            .build();
+        dispatch(refresh, Some(&dirty), 1, &config);
+        let _ = std::fs::remove_file(session::path(&session_id));
+        assert_eq!(dirty.refreshes.get(), 1);
+
+        let bash = HookBuilder::new().bash("ls").build();
+        assert!(!dispatch(bash, Some(&dirty), 1, &config).denies());
+    }
+
+    #[test]
+    fn stdin_timeout_allows_when_the_pipe_never_closes() {
+        let (reader, _writer) = io::pipe().unwrap();
+        let options = HookOptions {
+            stdin_timeout: Some(Duration::from_millis(50)),
+            exit_code: true,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        assert_eq!(handle_hook_fd(reader, &mut output, &options).unwrap(), 0);
+        let response: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(response, serde_json::json!({}));
+    }
+
+    #[test]
+    fn stdin_timeout_reads_input_that_arrives_in_time() {
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(b"not a hook").unwrap();
+        drop(writer);
+        let options = HookOptions {
+            stdin_timeout: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+
+        assert!(handle_hook_fd(reader, Vec::new(), &options).is_err());
+    }
+
+    #[test]
+    fn echoed_hook_names_its_variant_and_resolved_path() {
+        let edit = HookBuilder::new().cwd("/p/src").edit("../lib.rs").build();
+        let echoed = parsed_json(&edit);
+        assert_eq!(echoed["hook"], "Tool");
+        assert_eq!(echoed["hook_event_name"], "PreToolUse");
+        assert_eq!(echoed["tool_name"], "Edit");
+        assert_eq!(echoed["tool_input"]["file_path"], "../lib.rs");
+        assert_eq!(echoed["resolved_path"], "/p/lib.rs");
+
+        let bash = parsed_json(&HookBuilder::new().bash("ls").build());
+        assert_eq!(bash["tool_name"], "Bash");
+        assert!(bash.get("resolved_path").is_none());
+
+        assert_eq!(
+            parsed_json(&Hook::UserPrompt),
+            serde_json::json!({"hook": "UserPrompt"})
+        );
+    }
+
+    #[test]
+    fn allowed_edits_show_the_indicator_until_stop() {
+        let session_id = format!("indicator-{}", std::process::id());
+        let config = Config::default();
+        let clean = MockAction {
+            statuses: vec![status(true, false)],
+            ..Default::default()
+        };
+        let edit = |file: &str| HookBuilder::new().session(&session_id).edit(file).build();
+        let stop = || {
+            hook::parse_hook(&format!(
+                r#"{{"session_id":"{session_id}","hook_event_name":"Stop"}}"#
+            ))
+            .unwrap()
+        };
+        dispatch(edit("foo.rs"), Some(&clean), 1, &config);
+        dispatch(edit("foo.rs"), Some(&clean), 1, &config);
+        dispatch(edit("bar.rs"), Some(&clean), 1, &config);
+        assert!(!dispatch(stop(), Some(&clean), 1, &config).denies());
+        dispatch(edit("foo.rs"), Some(&clean), 1, &config);
+        let _ = std::fs::remove_file(session::path(&session_id));
+        let shown = |file: &str| (true, Some(file.to_string()));
+        assert_eq!(
+            *clean.indicator.borrow(),
+            [
+                shown("foo.rs"),
+                shown("bar.rs"),
+                (false, None),
+                shown("foo.rs")
+            ]
+        );
+
+        let dirty = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let edit = HookBuilder::new()
+            .session(&session_id)
+            .edit("foo.rs")
+            .build();
+        dispatch(edit, Some(&dirty), 1, &config);
+        let _ = std::fs::remove_file(session::path(&session_id));
+        assert!(dirty.indicator.borrow().is_empty());
+    }
+
+    #[test]
+    fn deny_details_name_the_editor_holding_the_changes() {
+        let h = edit_hook("details");
+        let action = MockAction {
+            statuses: vec![
+                status(false, false),
+                BufferStatus {
+                    instance: Some("/tmp/b.sock".to_string()),
+                    ..status(true, true)
+                },
+            ],
+            ..Default::default()
+        };
+        let (output, _) =
+            check_buffer_modifications(&h, Some(&action), "foo.rs", &Config::default());
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+
+        let reason = output
+            .hook_specific_output
+            .and_then(|o| o.permission_decision_reason)
+            .unwrap();
+        let details = DenyDetails::parse(&reason).unwrap();
+        assert_eq!(details.file, "foo.rs");
+        assert_eq!(details.editor.as_deref(), Some("/tmp/b.sock"));
+    }
+
+    #[test]
+    fn allows_when_dirty_and_current_are_in_different_instances() {
+        let action = MockAction {
+            statuses: vec![status(false, true), status(true, false)],
+            ..Default::default()
+        };
+        let h = edit_hook("mixed");
+        let (_, reason) =
+            check_buffer_modifications(&h, Some(&action), "foo.rs", &Config::default());
+        assert_eq!(reason, DecisionReason::BufferAvailable);
+    }
+
+    #[test]
+    fn denies_when_one_instance_is_dirty_and_current() {
+        let action = MockAction {
+            statuses: vec![status(true, false), status(true, true)],
+            ..Default::default()
+        };
+        let h = edit_hook("deny");
+        let (output, reason) =
+            check_buffer_modifications(&h, Some(&action), "foo.rs", &Config::default());
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+
+        assert_eq!(reason, DecisionReason::BufferDirtyAndCurrent);
+        assert!(
+            output
+                .to_json()
+                .unwrap()
+                .contains("\"permissionDecision\":\"deny\"")
+        );
+    }
+
+    #[test]
+    fn modified_flag_alone_denies_without_a_line_range() {
+        // The buffer matches the disk line for line; only 'modified' is set
+        let action = MockAction {
+            statuses: vec![status(true, true)],
+            contents: vec![String::new()],
+            ..Default::default()
+        };
+        let h = edit_hook("flag-only");
+        let (output, reason) =
+            check_buffer_modifications(&h, Some(&action), "no-such-file.rs", &Config::default());
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+
+        assert_eq!(reason, DecisionReason::BufferDirtyAndCurrent);
+        let reason = output
+            .hook_specific_output
+            .and_then(|o| o.permission_decision_reason)
+            .unwrap();
+        let details = DenyDetails::parse(&reason).unwrap();
+        assert_eq!(details.lines, None);
+    }
+
+    #[test]
+    fn block_notice_goes_only_to_the_instance_with_the_changes() {
+        let at = |instance: &str, is_current, dirty| BufferStatus {
+            instance: Some(instance.to_string()),
+            ..status(is_current, dirty)
+        };
+        let action = MockAction {
+            statuses: vec![
+                at("/tmp/clean.sock", false, false),
+                at("/tmp/dirty.sock", true, true),
+            ],
+            ..Default::default()
+        };
+        let h = edit_hook("targeted");
+        let (_, reason) =
+            check_buffer_modifications(&h, Some(&action), "foo.rs", &Config::default());
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+
+        assert_eq!(reason, DecisionReason::BufferDirtyAndCurrent);
+        let sent = action.sent.borrow();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0.as_deref(), Some("/tmp/dirty.sock"));
+    }
+
+    #[test]
+    fn block_notice_goes_to_stderr_when_no_editor_is_focused() {
+        for unfocused in [false, true] {
+            let action = MockAction {
+                statuses: vec![status(true, true)],
+                unfocused,
+                ..Default::default()
+            };
+            let h = edit_hook(&format!("focus-{}", unfocused));
+            let (_, reason) =
+                check_buffer_modifications(&h, Some(&action), "foo.rs", &Config::default());
+            let _ = std::fs::remove_file(session::path(&h.session_id));
+
+            assert_eq!(reason, DecisionReason::BufferDirtyAndCurrent);
+            assert_eq!(action.sent.borrow().is_empty(), unfocused);
+        }
+    }
+
+    #[test]
+    fn focus_is_only_asked_about_when_a_notice_goes_out() {
+        let action = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let capped = Config {
+            notify_limit: Some(1),
+            ..Default::default()
+        };
+        let h = edit_hook("focus-capped");
+        check_buffer_modifications(&h, Some(&action), "foo.rs", &capped);
+        check_buffer_modifications(&h, Some(&action), "foo.rs", &capped);
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+
+        assert_eq!(action.sent.borrow().len(), 1);
+        assert_eq!(action.focus_queries.get(), 1);
+    }
+
+    #[test]
+    fn soft_block_lets_an_unchanged_retry_through_within_the_grace_window() {
+        let action = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let config = Config {
+            soft_block: true,
+            ..Default::default()
+        };
+        let h = edit_hook("soft");
+        let check =
+            |h: &ToolHook| check_buffer_modifications(h, Some(&action), "foo.rs", &config).1;
+
+        assert_eq!(check(&h), DecisionReason::BufferDirtyAndCurrent);
+        assert_eq!(check(&h), DecisionReason::SoftBlockRetry);
+
+        // The grace is used up; a retry after the window is denied too
+        assert_eq!(check(&h), DecisionReason::BufferDirtyAndCurrent);
+        let mut state = SessionState::load(&h.session_id);
+        for (_, at) in state.soft_denied.values_mut() {
+            *at -= chrono::Duration::from_std(SOFT_BLOCK_GRACE * 2).unwrap();
+        }
+        state.save(&h.session_id);
+        assert_eq!(check(&h), DecisionReason::BufferDirtyAndCurrent);
+
+        // A different edit to the same file isn't a retry
+        let other = tool_hook(&format!(
+            r#"{{"session_id":"{}","transcript_path":"","cwd":".","hook_event_name":"PreToolUse",
+                "tool_name":"Edit","tool_input":{{"file_path":"foo.rs","old_string":"a"}}}}"#,
+            h.session_id
+        ));
+        assert_eq!(check(&other), DecisionReason::BufferDirtyAndCurrent);
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+    }
+
+    #[test]
+    fn edit_overlapping_the_selection_is_denied_with_its_lines() {
+        let h = tool_hook(
+            r#"{"session_id":"overlap","transcript_path":"","cwd":".","hook_event_name":"PreToolUse",
+                "tool_name":"Edit","tool_input":{"file_path":"/p/foo.rs","old_string":"c\nd","new_string":"x"}}"#,
+        );
+        let selected = |start_line, end_line| EditorContext {
+            file_path: "/p/foo.rs".to_string(),
+            start_line,
+            end_line,
+            content: None,
+            truncated: true,
+            language: None,
+            git_branch: None,
+            git_sha: None,
+        };
+        let action = |selection| MockAction {
+            statuses: vec![status(true, true)],
+            contents: vec!["a\nb\nc\nd\ne\n".to_string()],
+            selections: vec![selection],
+            ..Default::default()
+        };
+
+        let overlapping = action(selected(4, 5));
+        assert_eq!(
+            edit_overlaps_selection(&h.tool, &overlapping, "/p/foo.rs", &overlapping.contents),
+            Some((4, 5))
+        );
+        let (output, _) =
+            check_buffer_modifications(&h, Some(&overlapping), "/p/foo.rs", &Config::default());
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+        assert!(output.to_json().unwrap().contains("working on lines 4-5"));
+        assert_eq!(overlapping.content_fetches.get(), 1);
+        let reason = output
+            .hook_specific_output
+            .and_then(|o| o.permission_decision_reason)
+            .unwrap();
+        assert_eq!(
+            DenyDetails::parse(&reason),
+            Some(DenyDetails::new("/p/foo.rs").with_lines(4, 5))
+        );
+
+        let elsewhere = action(selected(1, 2));
+        assert_eq!(
+            edit_overlaps_selection(&h.tool, &elsewhere, "/p/foo.rs", &elsewhere.contents),
+            None
+        );
+        let (output, _) =
+            check_buffer_modifications(&h, Some(&elsewhere), "/p/foo.rs", &Config::default());
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+        assert_eq!(elsewhere.content_fetches.get(), 1);
+        assert!(
+            output
+                .to_json()
+                .unwrap()
+                .contains("being edited by the user")
+        );
+    }
+
+    #[test]
+    fn bash_rm_of_a_dirty_file_is_denied_when_guarded() {
+        let dirty = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let guarded = Config {
+            guard_bash_deletes: true,
+            ..Default::default()
+        };
+        let bash = |command: &str| {
+            tool_hook(&format!(
+                r#"{{"session_id":"test-bash-{}","transcript_path":"","cwd":"/p","hook_event_name":"PreToolUse",
+                    "tool_name":"Bash","tool_input":{{"command":"{}","description":""}}}}"#,
+                std::process::id(),
+                command
+            ))
+        };
+
+        for command in ["rm foo.rs", "git rm foo.rs"] {
+            let h = bash(command);
+            let output = handle_pre_tool_use(&h, Some(&dirty), 1, &guarded);
+            let _ = std::fs::remove_file(session::path(&h.session_id));
+            assert!(output.denies(), "{command}");
+            assert!(output.to_json().unwrap().contains("delete or move foo.rs"));
+        }
+
+        assert!(!handle_pre_tool_use(&bash("rm *.rs"), Some(&dirty), 1, &guarded).denies());
+        let off = handle_pre_tool_use(&bash("rm foo.rs"), Some(&dirty), 1, &Config::default());
+        assert!(!off.denies());
+    }
+
+    #[test]
+    fn bash_deletes_follow_the_tool_policy_and_protect_dirs() {
+        let dirty = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let h = tool_hook(&format!(
+            r#"{{"session_id":"test-bash-policy-{}","transcript_path":"","cwd":"/p","hook_event_name":"PreToolUse",
+                "tool_name":"Bash","tool_input":{{"command":"rm foo.rs","description":""}}}}"#,
+            std::process::id()
+        ));
+        let guarded = |pre: Option<PreAction>, protect_dirs: &[&str]| Config {
+            guard_bash_deletes: true,
+            tools: [("Bash".to_string(), ToolActions { pre, post: None })].into(),
+            protect_dirs: protect_dirs.iter().map(PathBuf::from).collect(),
+            ..Default::default()
+        };
+
+        let asked = handle_pre_tool_use(&h, Some(&dirty), 1, &guarded(Some(PreAction::Ask), &[]));
+        let ignored =
+            handle_pre_tool_use(&h, Some(&dirty), 1, &guarded(Some(PreAction::Ignore), &[]));
+        let elsewhere = handle_pre_tool_use(&h, Some(&dirty), 1, &guarded(None, &["other"]));
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+
+        assert!(!asked.denies());
+        assert!(
+            asked
+                .to_json()
+                .unwrap()
+                .contains(r#""permissionDecision":"ask""#)
+        );
+        assert!(!ignored.denies());
+        assert!(!elsewhere.denies());
+    }
+
+    #[test]
+    fn is_open_defaults_to_having_a_status() {
+        let open = MockAction {
+            statuses: vec![status(false, false)],
+            ..Default::default()
+        };
+        assert!(open.is_open("foo.rs").unwrap());
+        assert!(!MockAction::default().is_open("foo.rs").unwrap());
+    }
+
+    #[test]
+    fn long_notifications_are_cut_to_the_limit() {
+        let short = "[sidekick] Edit blocked — file has unsaved changes";
+        assert_eq!(truncate_message(short, Some(200)), short);
+
+        let long = format!("[sidekick] Edit blocked — {}", "é".repeat(300));
+        let cut = truncate_message(&long, Some(200));
+        assert_eq!(cut.chars().count(), 200);
+        assert!(cut.ends_with('…'));
+        assert!(long.starts_with(cut.trim_end_matches('…')));
+
+        assert_eq!(truncate_message(&long, None), long);
+        assert_eq!(Config::default().max_msg_len, Some(200));
+    }
+
+    #[test]
+    fn every_block_notice_is_cut_on_its_way_out() {
+        let action = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let notifier = Notifier {
+            action: &action,
+            max: Some(20),
+        };
+        let long = "x".repeat(100);
+        notifier
+            .send_message(&long, action::NotifyLevel::Warn)
+            .unwrap();
+        let _ = notifier.send_message_to("/tmp/a.sock", &long, action::NotifyLevel::Warn);
+
+        let sent = action.sent.borrow();
+        assert!(!sent.is_empty());
+        assert!(
+            sent.iter()
+                .all(|(_, message)| message.chars().count() <= 20)
+        );
+    }
+
+    #[test]
+    fn notify_prefix_is_applied_once() {
+        let once = with_prefix("[sidekick] ", "Edit blocked");
+        assert_eq!(once, "[sidekick] Edit blocked");
+        assert_eq!(with_prefix("[sidekick] ", &once), once);
+        assert_eq!(with_prefix("", "Edit blocked"), "Edit blocked");
+    }
+
+    #[test]
+    fn exit_code_flag_turns_a_deny_into_exit_2() {
+        let action = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let h = edit_hook("exit-code");
+        let (denied, _) =
+            check_buffer_modifications(&h, Some(&action), "foo.rs", &Config::default());
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+        let allowed = HookOutput::allow();
+
+        let flagged = HookOptions {
+            exit_code: true,
+            ..Default::default()
+        };
+        assert_eq!(exit_code(&denied, &flagged), DENY_EXIT_CODE);
+        assert_eq!(exit_code(&allowed, &flagged), 0);
+        assert_eq!(exit_code(&denied, &HookOptions::default()), 0);
+    }
+
+    fn tool_hook(json: &str) -> ToolHook {
+        match hook::parse_hook(json).unwrap() {
+            Hook::Tool(h) => h,
+            _ => panic!("expected a tool hook"),
+        }
+    }
+
+    #[test]
+    fn skips_refresh_when_disabled() {
+        let h = tool_hook(
+            r#"{"session_id":"s","transcript_path":"","cwd":".","hook_event_name":"PostToolUse",
+                "tool_name":"Write","tool_input":{"file_path":"foo.rs","content":"x"}}"#,
+        );
+        let action = MockAction::default();
+        let config = Config {
+            disable_refresh: true,
+            ..Default::default()
+        };
+
+        let output = handle_post_tool_use(&h, Some(&action), &config);
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+
+        assert_eq!(action.refreshes.get(), 0);
+        assert_eq!(output.to_json().unwrap(), "{}");
+    }
+
+    #[test]
+    fn files_written_in_a_session_are_remembered() {
+        let session_id = format!("written-{}", std::process::id());
+        let write = |file: &str| {
+            tool_hook(&format!(
+                r#"{{"session_id":"{session_id}","transcript_path":"","cwd":".",
+                    "hook_event_name":"PostToolUse","tool_name":"Write",
+                    "tool_input":{{"file_path":"{file}","content":"x"}}}}"#
+            ))
+        };
+
+        handle_post_tool_use(&write("/p/a.rs"), None, &Config::default());
+        handle_post_tool_use(&write("/p/b.rs"), None, &Config::default());
+        let modified = SessionState::load(&session_id).modified;
+        let _ = std::fs::remove_file(session::path(&session_id));
+
+        assert_eq!(
+            modified.into_iter().collect::<Vec<_>>(),
+            ["/p/a.rs", "/p/b.rs"]
+        );
+    }
+
+    #[test]
+    fn watchdog_gives_up_on_a_decision_past_the_deadline() {
+        let quick = with_deadline(Duration::from_secs(5), || HookOutput::deny("busy"));
+        assert!(quick.unwrap().denies());
+
+        let hung = with_deadline(Duration::from_millis(50), || {
+            std::thread::sleep(Duration::from_secs(5));
+            HookOutput::deny("too late")
+        });
+        assert_eq!(hung.err(), Some(RecvTimeoutError::Timeout));
+
+        let panicked = with_deadline(Duration::from_secs(5), || -> HookOutput { panic!("bug") });
+        assert_eq!(panicked.err(), Some(RecvTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn an_overdue_worker_leaves_no_trace() {
+        let session_id = format!("test-overdue-{}", std::process::id());
+        let saved = with_deadline(Duration::from_secs(5), move || {
+            utils::set_answer_due(Instant::now());
+            let mut state = SessionState::default();
+            state.record_denied("/p/foo.rs", Utc::now());
+            state.save(&session_id);
+            session::path(&session_id).exists()
+        });
+        assert_eq!(saved, Ok(false));
+    }
+
+    #[test]
+    fn files_outside_protect_dirs_are_allowed_without_asking_the_editor() {
+        let action = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let config = Config {
+            protect_dirs: vec![PathBuf::from("/home/me/src"), PathBuf::from("project")],
+            ..Default::default()
+        };
+        let edit = |cwd: &str, file: &str| {
+            let h = tool_hook(&format!(
+                r#"{{"session_id":"protect-{}","transcript_path":"","cwd":"{cwd}",
+                    "hook_event_name":"PreToolUse","tool_name":"Edit",
+                    "tool_input":{{"file_path":"{file}"}}}}"#,
+                std::process::id()
+            ));
+            let (_, reason) = check_buffer_modifications(&h, Some(&action), file, &config);
+            let _ = std::fs::remove_file(session::path(&h.session_id));
+            reason
+        };
+
+        assert_eq!(
+            edit("/home/me", "/tmp/scratch.rs"),
+            DecisionReason::Unprotected
+        );
+        assert_eq!(
+            edit("/home/me", "/home/me/src/../.bashrc"),
+            DecisionReason::Unprotected
+        );
+        assert_eq!(action.status_queries.get(), 0);
+
+        assert_eq!(
+            edit("/home/me", "/home/me/src/app/main.rs"),
+            DecisionReason::BufferDirtyAndCurrent
+        );
+        assert_eq!(
+            edit("/work", "project/lib.rs"),
+            DecisionReason::BufferDirtyAndCurrent
+        );
+        assert_eq!(action.status_queries.get(), 2);
+    }
+
+    #[test]
+    fn swap_file_asks_when_no_editor_is_reachable() {
+        let dir = std::env::temp_dir().join(format!("sidekick-swap-hook-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("notes.md").to_string_lossy().into_owned();
+        let h = tool_hook(&format!(
+            r#"{{"session_id":"s","transcript_path":"","cwd":".","hook_event_name":"PreToolUse",
+                "tool_name":"Edit","tool_input":{{"file_path":"{file}"}}}}"#
+        ));
+        let config = Config {
+            swapfile_fallback: true,
+            ..Default::default()
+        };
+
+        let (_, without_swap) = check_buffer_modifications(&h, None, &file, &config);
+        std::fs::write(dir.join(".notes.md.swp"), "").unwrap();
+        let (output, with_swap) = check_buffer_modifications(&h, None, &file, &config);
+        let (_, not_enabled) = check_buffer_modifications(&h, None, &file, &Config::default());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(without_swap, DecisionReason::NoNvimRunning);
+        assert_eq!(with_swap, DecisionReason::SwapFile);
+        assert_eq!(decision_of(&output), Decision::Ask);
+        assert!(
+            output
+                .to_json()
+                .unwrap()
+                .contains("\"permissionDecision\":\"ask\"")
+        );
+        assert_eq!(not_enabled, DecisionReason::NoNvimRunning);
+    }
+
+    #[test]
+    fn a_wedged_editor_is_asked_about_only_when_failing_closed() {
+        let wedged = MockAction {
+            wedged: true,
+            ..Default::default()
+        };
+        let h = edit_hook("wedged");
+        let closed = Config {
+            fail_closed: true,
+            ..Default::default()
+        };
+
+        let (open, open_reason) =
+            check_buffer_modifications(&h, Some(&wedged), "foo.rs", &Config::default());
+        let (asked, asked_reason) =
+            check_buffer_modifications(&h, Some(&wedged), "foo.rs", &closed);
+
+        assert_eq!(open_reason, DecisionReason::StatusCheckFailed);
+        assert!(open.hook_specific_output.is_none());
+        assert_eq!(asked_reason, DecisionReason::StatusCheckFailed);
+        assert!(
+            asked
+                .to_json()
+                .unwrap()
+                .contains("\"permissionDecision\":\"ask\"")
+        );
+    }
+
+    #[test]
+    fn one_wedged_editor_among_several_still_fails_closed() {
+        let editors = action::Editors(vec![
+            Box::new(MockAction {
+                wedged: true,
+                ..Default::default()
+            }),
+            Box::new(MockAction {
+                statuses: vec![status(true, false)],
+                ..Default::default()
+            }),
+        ]);
+        let h = edit_hook("wedged-among-several");
+        let closed = Config {
+            fail_closed: true,
+            ..Default::default()
+        };
+
+        let (asked, reason) = check_buffer_modifications(&h, Some(&editors), "foo.rs", &closed);
+
+        assert_eq!(reason, DecisionReason::StatusCheckFailed);
+        assert_eq!(decision_of(&asked), Decision::Ask);
+        assert!(editors.is_open("foo.rs").unwrap());
+
+        // A dirty, current buffer elsewhere settles it without the wedged one
+        let editors = action::Editors(vec![
+            Box::new(MockAction {
+                wedged: true,
+                ..Default::default()
+            }),
+            Box::new(MockAction {
+                statuses: vec![status(true, true)],
+                ..Default::default()
+            }),
+        ]);
+        let (_, reason) = check_buffer_modifications(&h, Some(&editors), "foo.rs", &closed);
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+        assert_eq!(reason, DecisionReason::BufferDirtyAndCurrent);
+    }
+
+    #[test]
+    fn edit_of_a_missing_file_is_flagged_when_asked() {
+        let hook = |tool: &str| {
+            tool_hook(&format!(
+                r#"{{"session_id":"s","transcript_path":"","cwd":".","hook_event_name":"PreToolUse",
+                    "tool_name":"{tool}","tool_input":{{"file_path":"/no/such/dir/foo.rs","content":"x"}}}}"#
+            ))
+        };
+        let enabled = Config {
+            warn_missing_target: true,
+            ..Default::default()
+        };
+
+        let output = handle_pre_tool_use(&hook("Edit"), None, 0, &enabled);
+        assert!(
+            output
+                .system_message
+                .unwrap()
+                .contains("/no/such/dir/foo.rs")
+        );
+
+        // A write creates the file; without the flag nothing is said
+        let output = handle_pre_tool_use(&hook("Write"), None, 0, &enabled);
+        assert!(output.system_message.is_none());
+        let output = handle_pre_tool_use(&hook("Edit"), None, 0, &Config::default());
+        assert!(output.system_message.is_none());
+    }
+
+    #[test]
+    fn warns_on_stderr_when_no_instance_found() {
+        let hook = Hook::Tool(tool_hook(
+            r#"{"session_id":"s","transcript_path":"","cwd":".","hook_event_name":"PreToolUse",
+                "tool_name":"Edit","tool_input":{"file_path":"foo.rs"}}"#,
+        ));
+        let enabled = Config {
+            warn_no_instance: true,
+            ..Default::default()
+        };
+
+        let warning = no_instance_warning(&hook, 0, &enabled).unwrap();
+        assert!(warning.contains("foo.rs"));
+        assert!(no_instance_warning(&hook, 1, &enabled).is_none());
+        assert!(no_instance_warning(&hook, 0, &Config::default()).is_none());
+        assert!(no_instance_warning(&Hook::UserPrompt, 0, &enabled).is_none());
+    }
+
+    #[test]
+    fn quiet_suppresses_no_instance_warning() {
+        let hook = Hook::Tool(tool_hook(
+            r#"{"session_id":"s","transcript_path":"","cwd":".","hook_event_name":"PreToolUse",
+                "tool_name":"Edit","tool_input":{"file_path":"foo.rs"}}"#,
+        ));
+        let quiet = Config {
+            warn_no_instance: true,
+            verbosity: Verbosity::Quiet,
+            ..Default::default()
+        };
+
+        assert!(no_instance_warning(&hook, 0, &quiet).is_none());
+    }
+
+    #[test]
+    fn allows_retry_after_user_saves() {
+        let h = edit_hook("retry");
+        let dirty = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let saved = MockAction {
+            statuses: vec![status(true, false)],
+            ..Default::default()
+        };
+
+        let (denied, reason) =
+            check_buffer_modifications(&h, Some(&dirty), "foo.rs", &Config::default());
+        assert_eq!(reason, DecisionReason::BufferDirtyAndCurrent);
+        assert!(denied.to_json().unwrap().contains(RETRY_AFTER_SAVE_MARKER));
+        assert!(
+            SessionState::load(&h.session_id)
+                .awaiting_save
+                .contains_key("foo.rs")
+        );
+
+        let (retried, reason) =
+            check_buffer_modifications(&h, Some(&saved), "foo.rs", &Config::default());
+        let awaiting = SessionState::load(&h.session_id).awaiting_save;
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+
+        assert_eq!(reason, DecisionReason::BufferAvailable);
+        assert!(retried.system_message.unwrap().contains("foo.rs"));
+        assert!(awaiting.is_empty());
+    }
+
+    #[test]
+    fn approval_from_the_editor_lets_the_blocked_edit_through_once() {
+        let h = tool_hook(&format!(
+            r#"{{"session_id":"test-approve-{}","transcript_path":"","cwd":".",
+                "hook_event_name":"PreToolUse","tool_name":"Edit",
+                "tool_input":{{"file_path":"approve-me.rs"}}}}"#,
+            std::process::id()
+        ));
+        let dirty = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let check =
+            || check_buffer_modifications(&h, Some(&dirty), "approve-me.rs", &Config::default()).1;
+
+        assert_eq!(check(), DecisionReason::BufferDirtyAndCurrent);
+        assert!(session::approve_everywhere(Path::new("approve-me.rs")) >= 1);
+        let approved = check();
+        let denied_again = check();
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+
+        assert_eq!(approved, DecisionReason::UserOverride);
+        assert_eq!(denied_again, DecisionReason::BufferDirtyAndCurrent);
+    }
+
+    #[test]
+    fn trusted_bypass_mode_skips_the_editor_check() {
+        let h = tool_hook(
+            r#"{"session_id":"bypass","transcript_path":"","cwd":".","hook_event_name":"PreToolUse",
+                "permission_mode":"bypassPermissions","tool_name":"Edit",
+                "tool_input":{"file_path":"foo.rs"}}"#,
+        );
+        let dirty = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let trusting = Config {
+            trust_bypass_mode: true,
+            ..Default::default()
+        };
+
+        let output = handle_pre_tool_use(&h, Some(&dirty), 1, &trusting);
+        assert!(output.hook_specific_output.is_none());
+
+        // Off by default: bypass mode alone doesn't lift protection
+        let output = handle_pre_tool_use(&h, Some(&dirty), 1, &Config::default());
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+        assert!(output.to_json().unwrap().contains("\"deny\""));
+    }
+
+    #[test]
+    fn per_tool_actions_ignore_write_and_ask_on_edit() {
+        let dirty = MockAction {
+            statuses: vec![status(true, true)],
+            ..Default::default()
+        };
+        let config = Config::from_sources(
+            |_| None,
+            Some(
+                r#"
+                [tools.Write]
+                pre = "ignore"
+                post = "ignore"
+                [tools.Edit]
+                pre = "ask"
+                "#,
+            ),
+            None,
+            Verbosity::default(),
+        );
+
+        let write = |event: &str| {
+            tool_hook(&format!(
+                r#"{{"session_id":"tools","transcript_path":"","cwd":".","hook_event_name":"{event}",
+                    "tool_name":"Write","tool_input":{{"file_path":"foo.rs","content":"x"}}}}"#
+            ))
+        };
+        let output = handle_pre_tool_use(&write("PreToolUse"), Some(&dirty), 1, &config);
+        assert!(output.hook_specific_output.is_none());
+        handle_post_tool_use(&write("PostToolUse"), Some(&dirty), &config);
+        let _ = std::fs::remove_file(session::path("tools"));
+        assert_eq!(dirty.refreshes.get(), 0);
+
+        let h = edit_hook("tools-edit");
+        let output = handle_pre_tool_use(&h, Some(&dirty), 1, &config);
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+        assert!(
+            output
+                .to_json()
+                .unwrap()
+                .contains("\"permissionDecision\":\"ask\"")
+        );
+
+        // Tools without an entry keep the defaults
+        assert_eq!(config.pre_action("MultiEdit"), PreAction::Block);
+        assert_eq!(config.post_action("Edit"), PostAction::Refresh);
+    }
+
+    #[test]
+    fn block_background_decides_dirty_background_buffers() {
+        let action = MockAction {
+            statuses: vec![status(false, true)],
+            ..Default::default()
+        };
+        for (value, expected) in [
+            ("0", DecisionReason::BufferAvailable),
+            ("1", DecisionReason::BufferDirty),
+        ] {
+            let config = Config::from_sources(
+                |name| (name == "SIDEKICK_BLOCK_BACKGROUND").then(|| value.to_string()),
+                None,
+                None,
+                Verbosity::default(),
+            );
+            let h = edit_hook(&format!("background-{value}"));
+            let (_, reason) = check_buffer_modifications(&h, Some(&action), "foo.rs", &config);
+            let _ = std::fs::remove_file(session::path(&h.session_id));
+            assert_eq!(reason, expected, "SIDEKICK_BLOCK_BACKGROUND={value}");
+        }
+    }
+
+    #[test]
+    fn project_policy_blocks_background_edits_with_its_message() {
+        let action = MockAction {
+            statuses: vec![status(false, true)],
+            ..Default::default()
+        };
+        let config = Config {
+            unsaved: UnsavedPolicy::Any,
+            deny_message: Some("Wait for the human to save.".to_string()),
+            ..Default::default()
+        };
+        let h = edit_hook("policy");
+        let (output, reason) = check_buffer_modifications(&h, Some(&action), "foo.rs", &config);
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+
+        assert_eq!(reason, DecisionReason::BufferDirty);
+        assert_eq!(decision_of(&output), Decision::Deny);
+        let json = output.to_json().unwrap();
+        assert!(json.contains("Wait for the human to save."));
+        assert!(json.contains(RETRY_AFTER_SAVE_MARKER));
+    }
+
+    #[test]
+    fn denies_edit_whose_old_string_is_gone_from_the_buffer() {
+        let h = tool_hook(&format!(
+            r#"{{"session_id":"test-stale-{}","transcript_path":"","cwd":".",
+                "hook_event_name":"PreToolUse","tool_name":"Edit",
+                "tool_input":{{"file_path":"foo.rs","old_string":"fn old()","new_string":"fn new()"}}}}"#,
+            std::process::id()
+        ));
+        let background_edit = |content: &str| MockAction {
+            statuses: vec![status(false, true)],
+            contents: vec![content.to_string()],
+            ..Default::default()
+        };
+
+        let (_, reason) = check_buffer_modifications(
+            &h,
+            Some(&background_edit("fn old() {}")),
+            "foo.rs",
+            &Config::default(),
+        );
+        assert_eq!(reason, DecisionReason::BufferAvailable);
+
+        let (output, reason) = check_buffer_modifications(
+            &h,
+            Some(&background_edit("fn renamed_by_user() {}")),
+            "foo.rs",
+            &Config::default(),
+        );
+        let _ = std::fs::remove_file(session::path(&h.session_id));
+        assert_eq!(reason, DecisionReason::StaleContent);
+        assert!(output.to_json().unwrap().contains("old_string"));
+    }
+
+    #[test]
+    fn warns_without_blocking_on_read_of_dirty_file() {
+        let h = tool_hook(
+            r#"{"session_id":"s","transcript_path":"","cwd":".","hook_event_name":"PreToolUse",
+                "tool_name":"Read","tool_input":{"file_path":"foo.rs"}}"#,
+        );
+        let dirty = MockAction {
+            statuses: vec![status(false, true)],
+            ..Default::default()
+        };
+        let clean = MockAction {
+            statuses: vec![status(true, false)],
+            ..Default::default()
+        };
+
+        let output = handle_pre_tool_use(&h, Some(&dirty), 1, &Config::default());
+        assert!(output.hook_specific_output.is_none());
+        assert!(
+            output
+                .system_message
+                .unwrap()
+                .contains("foo.rs has unsaved changes")
+        );
+
+        let output = handle_pre_tool_use(&h, Some(&clean), 1, &Config::default());
+        assert!(output.system_message.is_none());
+    }
+
+    #[test]
+    fn attaches_live_content_to_read_of_dirty_file() {
+        let h = tool_hook(
+            r#"{"session_id":"s","transcript_path":"","cwd":".","hook_event_name":"PreToolUse",
+                "tool_name":"Read","tool_input":{"file_path":"does-not-exist.rs"}}"#,
+        );
+        let dirty = MockAction {
+            statuses: vec![status(true, true)],
+            contents: vec!["fn unsaved() {}".to_string()],
+            ..Default::default()
+        };
+        let config = Config {
+            read_live_content: true,
+            ..Default::default()
+        };
+
+        let output = handle_pre_tool_use(&h, Some(&dirty), 1, &config);
+        let context = output
+            .hook_specific_output
+            .unwrap()
+            .additional_context
+            .unwrap();
+        assert!(context.contains("does-not-exist.rs"));
+        assert!(context.contains("fn unsaved() {}"));
+        assert!(output.system_message.is_some());
+    }
+
+    #[test]
+    fn prompt_context_says_what_the_user_is_looking_at() {
+        let action = MockAction {
+            views: vec![EditorView {
+                file_path: "/p/foo.rs".into(),
+                first_line: 10,
+                last_line: 20,
+            }],
+            ..Default::default()
+        };
+        let prompt = || {
+            hook::parse_hook(
+                r#"{"session_id":"s","transcript_path":"","cwd":"","hook_event_name":"UserPromptSubmit","prompt":"hi"}"#,
+            )
+            .unwrap()
+        };
+
+        let off = dispatch(prompt(), Some(&action), 1, &Config::default());
+        assert!(off.hook_specific_output.is_none());
+
+        let config = Config {
+            prompt_context: true,
+            ..Config::default()
+        };
+        let on = dispatch(prompt(), Some(&action), 1, &config);
+        assert_eq!(
+            on.hook_specific_output.unwrap().additional_context.unwrap(),
+            "[User is looking at /p/foo.rs:10-20]"
+        );
+    }
+
+    #[test]
+    fn ndjson_answers_each_hook_on_its_own_line() {
+        let prompt = r#"{"session_id":"s","transcript_path":"","cwd":"","hook_event_name":"UserPromptSubmit","prompt":"hi"}"#;
+        let input = format!("{prompt}\n\n{prompt}\n");
+        let options = HookOptions {
+            cwd: Some(std::env::temp_dir().join("sidekick-ndjson-no-editors")),
+            ndjson: true,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        handle_hook_io(input.as_bytes(), &mut output, &options).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for line in lines {
+            serde_json::from_str::<serde_json::Value>(line).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_on_stdin_still_gets_an_answer() {
+        let mut input = br#"{"session_id":"s","transcript_path":"","cwd":"","hook_event_name":"UserPromptSubmit","prompt":"caf"#.to_vec();
+        input.extend_from_slice(b"\xff\xfe\"}");
+        let options = HookOptions {
+            cwd: Some(std::env::temp_dir().join("sidekick-utf8-no-editors")),
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        assert_eq!(
+            handle_hook_io(&input[..], &mut output, &options).unwrap(),
+            0
+        );
+        serde_json::from_slice::<serde_json::Value>(&output).unwrap();
+    }
+}
@@ -18,13 +18,12 @@
 //! # Example
 //!
 //! ```no_run
-//! use sidekick::hook::{parse_hook, HookOutput, PermissionDecision};
+//! use sidekick::hook::{parse_hook, HookOutput};
 //!
 //! let json = r#"{"session_id":"abc","transcript_path":"","cwd":".","hook_event_name":"PreToolUse","tool_name":"Edit","tool_input":{"file_path":"test.txt"}}"#;
 //! let hook = parse_hook(json).unwrap();
 //!
-//! let output = HookOutput::new()
-//!     .with_permission_decision(PermissionDecision::Deny, Some("File has unsaved changes".into()));
+//! let output = HookOutput::deny("File has unsaved changes");
 //!
 //! println!("{}", output.to_json().unwrap());
 //! ```
@@ -46,15 +45,33 @@ pub struct ToolHook {
     pub transcript_path: String,
     pub cwd: String,
     pub hook_event_name: HookEvent,
+    /// Claude Code's permission mode: `default`, `acceptEdits`, `plan` or
+    /// `bypassPermissions`. Older payloads and other hosts leave it out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission_mode: Option<String>,
     #[serde(flatten)]
     pub tool: Tool,
 }
 
-/// Parsed hook - either tool-related or user prompt
-#[derive(Debug)]
+impl ToolHook {
+    /// Whether the user told Claude Code to skip permission checks
+    pub fn bypasses_permissions(&self) -> bool {
+        self.permission_mode.as_deref() == Some("bypassPermissions")
+    }
+}
+
+/// Parsed hook - tool-related, user prompt, or the end of a turn
+// One per invocation, so the size gap between variants costs nothing
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "hook")]
 pub enum Hook {
     Tool(ToolHook),
     UserPrompt,
+    /// Claude finished responding
+    Stop {
+        session_id: String,
+    },
 }
 
 /// Tool types discriminated by tool_name
@@ -69,10 +86,13 @@ pub enum Tool {
     Bash(BashToolInput),
 }
 
-/// File operation tool input
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+/// File operation tool input. Every field is optional so a partial payload
+/// still parses; a tool without a `file_path` is simply not acted on.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct FileToolInput {
-    pub file_path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -88,9 +108,131 @@ pub struct BashToolInput {
     pub description: String,
 }
 
+impl BashToolInput {
+    /// Files a plain `rm`, `mv` or `git rm` in the command would delete or
+    /// move, as written. Conservative: a command part with globs, variables,
+    /// quoting, substitutions or redirections yields nothing, since its
+    /// targets can't be known without a shell.
+    pub fn delete_targets(&self) -> Vec<String> {
+        self.command
+            .split(['\n', ';'])
+            .flat_map(|line| line.split("&&"))
+            .flat_map(|part| part.split("||"))
+            .filter(|part| !part.contains(SHELL_SPECIALS))
+            .flat_map(|part| {
+                let words: Vec<&str> = part.split_whitespace().collect();
+                let operands = match words.as_slice() {
+                    ["rm" | "mv", rest @ ..] | ["git", "rm", rest @ ..] => rest,
+                    _ => &[],
+                };
+                let mut after_dashes = false;
+                operands
+                    .iter()
+                    .filter(|word| {
+                        if after_dashes {
+                            return true;
+                        }
+                        after_dashes = **word == "--";
+                        !word.starts_with('-')
+                    })
+                    .map(|word| word.to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+/// Builds hooks for tests, starting from a PreToolUse `Edit` with no file in
+/// session `test`, cwd `.`
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug)]
+pub struct HookBuilder {
+    hook: ToolHook,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl HookBuilder {
+    pub fn new() -> Self {
+        Self {
+            hook: ToolHook {
+                session_id: "test".to_string(),
+                transcript_path: String::new(),
+                cwd: ".".to_string(),
+                hook_event_name: HookEvent::PreToolUse,
+                permission_mode: None,
+                tool: Tool::Edit(FileToolInput::default()),
+            },
+        }
+    }
+
+    pub fn session(mut self, session_id: impl Into<String>) -> Self {
+        self.hook.session_id = session_id.into();
+        self
+    }
+
+    pub fn cwd(mut self, cwd: impl Into<String>) -> Self {
+        self.hook.cwd = cwd.into();
+        self
+    }
+
+    pub fn event(mut self, event: HookEvent) -> Self {
+        self.hook.hook_event_name = event;
+        self
+    }
+
+    /// An `Edit` of `path`
+    pub fn edit(mut self, path: impl Into<String>) -> Self {
+        self.hook.tool = Tool::Edit(FileToolInput {
+            file_path: Some(path.into()),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// A `Write` of `content` to `path`
+    pub fn write(mut self, path: impl Into<String>, content: impl Into<String>) -> Self {
+        self.hook.tool = Tool::Write(FileToolInput {
+            file_path: Some(path.into()),
+            content: Some(content.into()),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// A `Bash` run of `command`
+    pub fn bash(mut self, command: impl Into<String>) -> Self {
+        self.hook.tool = Tool::Bash(BashToolInput {
+            command: command.into(),
+            description: String::new(),
+        });
+        self
+    }
+
+    pub fn build(self) -> Hook {
+        Hook::Tool(self.hook)
+    }
+
+    /// The hook as the handler's tool functions take it
+    pub fn build_tool(self) -> ToolHook {
+        self.hook
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Default for HookBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Characters that make a command's file arguments unknowable without a shell
+const SHELL_SPECIALS: &[char] = &[
+    '$', '`', '*', '?', '[', '{', '~', '\'', '"', '\\', '(', ')', '<', '>', '|', '&',
+];
+
 pub fn parse_hook(input: &str) -> anyhow::Result<Hook> {
     // First, peek at the hook_event_name to determine which struct to parse
-    let value: serde_json::Value =
+    let mut value: serde_json::Value =
         serde_json::from_str(input).context("couldn't parse hook input")?;
     let event_name = value
         .get("hook_event_name")
@@ -99,9 +241,23 @@ pub fn parse_hook(input: &str) -> anyhow::Result<Hook> {
 
     match event_name {
         "UserPromptSubmit" => Ok(Hook::UserPrompt),
+        "Stop" => Ok(Hook::Stop {
+            session_id: value
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string(),
+        }),
         "PreToolUse" | "PostToolUse" => {
+            // A tool call without `tool_input` parses like one with an empty
+            // input, rather than failing the hook
+            if let Some(fields) = value.as_object_mut() {
+                fields
+                    .entry("tool_input")
+                    .or_insert_with(|| serde_json::json!({}));
+            }
             let hook: ToolHook =
-                serde_json::from_str(input).context("unrecognized tool in hook")?;
+                serde_json::from_value(value).context("unrecognized tool in hook")?;
             Ok(Hook::Tool(hook))
         }
         _ => {
@@ -110,9 +266,64 @@ pub fn parse_hook(input: &str) -> anyhow::Result<Hook> {
     }
 }
 
+/// Appended to the deny reason when an edit is blocked on unsaved changes.
+/// Tells tooling (and the model) the same edit will go through once the
+/// user saves — the next PreToolUse for that file re-checks and allows.
+pub const RETRY_AFTER_SAVE_MARKER: &str = "[sidekick:retry-after-save]";
+
+/// Starts the last line of a deny reason that carries `DenyDetails`, with
+/// the details as compact JSON after a space
+pub const DENY_DETAILS_MARKER: &str = "[sidekick:details]";
+
+/// What a deny is about, for an editor plugin or UI to render richly.
+/// `permissionDecisionReason` is a plain string, so the details ride at its
+/// end: `<reason>\n[sidekick:details] {"file":...}`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DenyDetails {
+    /// The file the edit targeted, as the AI named it
+    pub file: String,
+    /// First and last line, 1-based and inclusive, the conflict is about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lines: Option<[u32; 2]>,
+    /// Editor instance holding the unsaved changes, as
+    /// `Action::send_message_to` names it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
+}
+
+impl DenyDetails {
+    pub fn new(file: impl Into<String>) -> Self {
+        Self {
+            file: file.into(),
+            lines: None,
+            editor: None,
+        }
+    }
+
+    pub fn with_lines(mut self, first: u32, last: u32) -> Self {
+        self.lines = Some([first, last]);
+        self
+    }
+
+    /// `reason` with these details on a last line of their own
+    pub fn append_to(&self, reason: &str) -> String {
+        match serde_json::to_string(self) {
+            Ok(json) => format!("{}\n{} {}", reason, DENY_DETAILS_MARKER, json),
+            Err(_) => reason.to_string(),
+        }
+    }
+
+    /// The details carried at the end of a deny `reason`, if any
+    #[cfg(any(test, feature = "testing"))]
+    pub fn parse(reason: &str) -> Option<Self> {
+        let (_, json) = reason.rsplit_once(DENY_DETAILS_MARKER)?;
+        serde_json::from_str(json.trim()).ok()
+    }
+}
+
 /// Permission decision for PreToolUse hooks
 #[non_exhaustive]
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum PermissionDecision {
     Allow,
@@ -130,7 +341,7 @@ pub struct HookSpecificOutput {
     pub permission_decision: Option<PermissionDecision>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub permission_decision_reason: Option<String>,
-    // PostToolUse fields
+    // PreToolUse, PostToolUse and UserPromptSubmit fields
     #[serde(skip_serializing_if = "Option::is_none")]
     pub additional_context: Option<String>,
 }
@@ -170,6 +381,41 @@ impl HookOutput {
         }
     }
 
+    /// PreToolUse: let the tool run without asking the user. `new()` instead
+    /// leaves it to Claude Code's usual permission flow.
+    #[allow(dead_code)]
+    pub fn allow() -> Self {
+        Self::new().with_permission_decision(PermissionDecision::Allow, None)
+    }
+
+    /// PreToolUse: block the tool call, telling the AI why
+    pub fn deny(reason: impl Into<String>) -> Self {
+        Self::new().with_permission_decision(PermissionDecision::Deny, Some(reason.into()))
+    }
+
+    /// PreToolUse: ask the user to confirm the tool call, showing them why
+    #[allow(dead_code)]
+    pub fn ask(reason: impl Into<String>) -> Self {
+        Self::new().with_permission_decision(PermissionDecision::Ask, Some(reason.into()))
+    }
+
+    /// PostToolUse: the tool already ran; feed `reason` back to the AI as a
+    /// problem to address
+    #[allow(dead_code)]
+    pub fn post_block(reason: impl Into<String>) -> Self {
+        Self {
+            decision: Some("block".to_string()),
+            reason: Some(reason.into()),
+            ..Self::new()
+        }
+    }
+
+    /// Any event: stop the AI altogether, showing the user `reason`
+    #[allow(dead_code)]
+    pub fn stop(reason: impl Into<String>) -> Self {
+        Self::new().with_continue(false).with_stop_reason(reason)
+    }
+
     /// Set continue execution flag
     #[allow(dead_code)]
     pub fn with_continue(mut self, continue_execution: bool) -> Self {
@@ -213,6 +459,17 @@ impl HookOutput {
         self
     }
 
+    /// Set additional context alongside a PreToolUse call that goes ahead
+    pub fn with_tool_context(mut self, context: impl Into<String>) -> Self {
+        self.hook_specific_output = Some(HookSpecificOutput {
+            hook_event_name: "PreToolUse".to_string(),
+            permission_decision: None,
+            permission_decision_reason: None,
+            additional_context: Some(context.into()),
+        });
+        self
+    }
+
     /// Set additional context for UserPromptSubmit
     pub fn with_additional_context(mut self, context: impl Into<String>) -> Self {
         self.hook_specific_output = Some(HookSpecificOutput {
@@ -224,16 +481,57 @@ impl HookOutput {
         self
     }
 
+    /// Whether this response denies the tool call
+    pub fn denies(&self) -> bool {
+        self.hook_specific_output
+            .as_ref()
+            .is_some_and(|o| o.permission_decision == Some(PermissionDecision::Deny))
+    }
+
+    /// Whether this response asks the user about the tool call
+    pub fn asks(&self) -> bool {
+        self.hook_specific_output
+            .as_ref()
+            .is_some_and(|o| o.permission_decision == Some(PermissionDecision::Ask))
+    }
+
+    /// PreToolUse: turn a deny into asking the user, keeping the reason
+    pub fn ask_instead(mut self) -> Self {
+        if let Some(output) = self.hook_specific_output.as_mut()
+            && output.permission_decision == Some(PermissionDecision::Deny)
+        {
+            output.permission_decision = Some(PermissionDecision::Ask);
+        }
+        self
+    }
+
     /// Convert to JSON string
     pub fn to_json(&self) -> anyhow::Result<String> {
         serde_json::to_string(self).context("couldn't serialize hook output")
     }
 
     /// Convert to pretty JSON string
-    #[allow(dead_code)]
     pub fn to_json_pretty(&self) -> anyhow::Result<String> {
         serde_json::to_string_pretty(self).context("couldn't serialize hook output")
     }
+
+    /// Serialize in the requested format
+    pub fn render(&self, format: OutputFormat) -> anyhow::Result<String> {
+        match format {
+            OutputFormat::Json => self.to_json(),
+            OutputFormat::Pretty => self.to_json_pretty(),
+        }
+    }
+}
+
+/// How the hook response is written to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Compact single-line JSON — what Claude Code consumes
+    #[default]
+    Json,
+    /// Indented JSON, for reading by hand while debugging
+    Pretty,
 }
 
 impl Default for HookOutput {
@@ -241,3 +539,36 @@ impl Default for HookOutput {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deny_details_round_trip_through_the_reason() {
+        let details = DenyDetails {
+            editor: Some("/tmp/a.sock".to_string()),
+            ..DenyDetails::new("src/main.rs").with_lines(3, 7)
+        };
+        let reason = details.append_to("The file is being edited by the user");
+        assert!(reason.starts_with("The file is being edited by the user\n"));
+
+        // Through the JSON Claude Code receives and back
+        let json = HookOutput::deny(reason).to_json().unwrap();
+        let output: HookOutput = serde_json::from_str(&json).unwrap();
+        let reason = output
+            .hook_specific_output
+            .and_then(|o| o.permission_decision_reason)
+            .unwrap();
+        let last_line = reason.lines().last().unwrap();
+        assert!(last_line.starts_with(DENY_DETAILS_MARKER));
+        assert_eq!(DenyDetails::parse(&reason), Some(details));
+
+        assert_eq!(DenyDetails::parse("no details here"), None);
+        let bare = DenyDetails::new("a.rs").append_to("blocked");
+        assert_eq!(
+            bare,
+            format!("blocked\n{} {{\"file\":\"a.rs\"}}", DENY_DETAILS_MARKER)
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! `sidekick install` / `sidekick uninstall` — add or remove the hooks in
+//! Claude Code's settings without prompts, for scripts and dotfile setups.
+//!
+//! The merge is `fix.rs`'s, pointed at this binary's path. Running it again
+//! changes nothing, unless the binary moved: then sidekick hooks are
+//! repointed at it. Before an existing file is rewritten, by either command,
+//! it is copied to `settings.json.bak`. Uninstall drops only hook commands
+//! that run `sidekick hook`, plus any matcher group or event list that
+//! leaves empty.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::fix;
+
+/// Merge the sidekick hooks into `settings_path` (default
+/// `~/.claude/settings.json`), running this binary
+pub fn run(settings_path: Option<PathBuf>) -> Result<()> {
+    let path = settings_path_or_default(settings_path)?;
+    let before = std::fs::read_to_string(&path).ok();
+    let after = fix::claude_settings_after(before.as_deref(), &hook_command()?)?;
+
+    if before.as_deref() == Some(after.as_str()) {
+        println!("sidekick hooks already in {}", path.display());
+        return Ok(());
+    }
+
+    if before.is_some() {
+        back_up(&path)?;
+    }
+    fix::Fix {
+        title: "Register the Claude Code hooks".into(),
+        path: path.clone(),
+        before,
+        after,
+    }
+    .apply()?;
+
+    println!("Added sidekick hooks to {}", path.display());
+    Ok(())
+}
+
+/// Remove the sidekick hooks from `settings_path` (default
+/// `~/.claude/settings.json`). Nothing to remove is not an error.
+pub fn uninstall(settings_path: Option<PathBuf>) -> Result<()> {
+    let path = settings_path_or_default(settings_path)?;
+    let Ok(before) = std::fs::read_to_string(&path) else {
+        println!("No sidekick hooks in {}", path.display());
+        return Ok(());
+    };
+    let Some(after) = settings_without_sidekick(&path, &before)? else {
+        println!("No sidekick hooks in {}", path.display());
+        return Ok(());
+    };
+
+    back_up(&path)?;
+    fix::Fix {
+        title: "Remove the Claude Code hooks".into(),
+        path: path.clone(),
+        before: Some(before),
+        after,
+    }
+    .apply()?;
+
+    println!("Removed sidekick hooks from {}", path.display());
+    Ok(())
+}
+
+/// The settings at `path`, read as `before`, with every `sidekick hook`
+/// command taken out; `None` if there were none
+fn settings_without_sidekick(path: &Path, before: &str) -> Result<Option<String>> {
+    let mut root: serde_json::Value = serde_json::from_str(before)
+        .with_context(|| format!("{} isn't valid JSON", path.display()))?;
+    let Some(hooks) = root.get_mut("hooks").and_then(|h| h.as_object_mut()) else {
+        return Ok(None);
+    };
+
+    let mut removed = false;
+    hooks.retain(|_, entries| {
+        let Some(entries) = entries.as_array_mut() else {
+            return true;
+        };
+        let had = entries.len();
+        entries.retain_mut(|entry| {
+            let Some(commands) = entry.get_mut("hooks").and_then(|h| h.as_array_mut()) else {
+                return true;
+            };
+            let count = commands.len();
+            commands.retain(|c| {
+                !c["command"]
+                    .as_str()
+                    .is_some_and(fix::is_sidekick_hook_command)
+            });
+            removed |= commands.len() < count;
+            // A group we emptied goes too; one that was already empty stays
+            !(commands.is_empty() && count > 0)
+        });
+        !(entries.is_empty() && had > 0)
+    });
+    if !removed {
+        return Ok(None);
+    }
+
+    if hooks.is_empty()
+        && let Some(obj) = root.as_object_mut()
+    {
+        obj.remove("hooks");
+    }
+
+    let mut s = serde_json::to_string_pretty(&root)?;
+    s.push('\n');
+    Ok(Some(s))
+}
+
+fn settings_path_or_default(settings_path: Option<PathBuf>) -> Result<PathBuf> {
+    match settings_path {
+        Some(path) => Ok(path),
+        None => Ok(dirs::home_dir()
+            .context("couldn't find home directory")?
+            .join(".claude")
+            .join("settings.json")),
+    }
+}
+
+/// `<this binary> hook`, quoted if the path has spaces
+fn hook_command() -> Result<String> {
+    let exe = std::env::current_exe().context("couldn't locate the sidekick binary")?;
+    let exe = exe.to_string_lossy();
+    if exe.contains(char::is_whitespace) {
+        Ok(format!("\"{}\" hook", exe))
+    } else {
+        Ok(format!("{} hook", exe))
+    }
+}
+
+/// Copy the settings at `path` to `backup_path` before they're rewritten
+fn back_up(path: &Path) -> Result<()> {
+    let backup = backup_path(path);
+    std::fs::copy(path, &backup).with_context(|| format!("couldn't back up {}", path.display()))?;
+    println!("Backed up {} to {}", path.display(), backup.display());
+    Ok(())
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn installing_twice_changes_nothing_the_second_time() {
+        let before = r#"{
+  "model": "opus",
+  "hooks": {
+    "PreToolUse": [
+      {
+        "matcher": "Bash",
+        "hooks": [{ "type": "command", "command": "audit.sh" }]
+      }
+    ]
+  }
+}
+"#;
+        let command = "/opt/bin/sidekick hook";
+        let once = fix::claude_settings_after(Some(before), command).unwrap();
+        let twice = fix::claude_settings_after(Some(&once), command).unwrap();
+        assert_eq!(once, twice);
+
+        let v: serde_json::Value = serde_json::from_str(&twice).unwrap();
+        let pre = v["hooks"]["PreToolUse"].as_array().unwrap();
+        assert_eq!(pre.len(), 2);
+        assert_eq!(pre[0]["hooks"][0]["command"], "audit.sh");
+        assert_eq!(pre[1]["hooks"][0]["command"], command);
+    }
+
+    #[test]
+    fn installing_from_a_new_path_repoints_the_old_hooks() {
+        let old = fix::claude_settings_after(None, "/old/bin/sidekick hook --cwd x").unwrap();
+        let moved =
+            fix::claude_settings_after(Some(&old), "\"/new/my bin/sidekick\" hook").unwrap();
+
+        let v: serde_json::Value = serde_json::from_str(&moved).unwrap();
+        for event in ["PreToolUse", "PostToolUse", "UserPromptSubmit", "Stop"] {
+            let entries = v["hooks"][event].as_array().unwrap();
+            assert_eq!(entries.len(), 1, "{event}");
+            assert_eq!(
+                entries[0]["hooks"][0]["command"],
+                "\"/new/my bin/sidekick\" hook --cwd x"
+            );
+        }
+        let again = fix::claude_settings_after(Some(&moved), "\"/new/my bin/sidekick\" hook");
+        assert_eq!(again.unwrap(), moved);
+    }
+
+    #[test]
+    fn uninstall_undoes_install() {
+        let command = "/opt/bin/sidekick hook";
+        let settings = Path::new("/h/.claude/settings.json");
+        for original in [
+            "{\n  \"model\": \"opus\"\n}\n",
+            r#"{
+  "hooks": {
+    "PreToolUse": [
+      {
+        "matcher": "Bash",
+        "hooks": [
+          {
+            "type": "command",
+            "command": "audit.sh"
+          }
+        ]
+      }
+    ]
+  },
+  "model": "opus"
+}
+"#,
+        ] {
+            let installed = fix::claude_settings_after(Some(original), command).unwrap();
+            let uninstalled = settings_without_sidekick(settings, &installed)
+                .unwrap()
+                .unwrap();
+            assert_eq!(uninstalled, original);
+            assert_eq!(settings_without_sidekick(settings, original).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn uninstall_backs_up_and_names_the_file_it_read() {
+        let dir = std::env::temp_dir().join(format!("sidekick-uninstall-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("custom.json");
+        let installed = fix::claude_settings_after(None, "/opt/bin/sidekick hook").unwrap();
+        std::fs::write(&path, &installed).unwrap();
+
+        let removed = uninstall(Some(path.clone()));
+        let backup = std::fs::read_to_string(backup_path(&path));
+        std::fs::write(&path, "{ not json").unwrap();
+        let broken = uninstall(Some(path.clone()));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        removed.unwrap();
+        assert_eq!(backup.unwrap(), installed);
+        let error = format!("{:#}", broken.unwrap_err());
+        assert!(error.contains("custom.json isn't valid JSON"), "{error}");
+    }
+
+    #[test]
+    fn backup_sits_next_to_the_settings() {
+        assert_eq!(
+            backup_path(Path::new("/h/.claude/settings.json")),
+            Path::new("/h/.claude/settings.json.bak")
+        );
+    }
+}
@@ -10,30 +10,46 @@
 //!
 //! - `handler`: Hook processing logic for Claude Code
 //! - `hook`: Data structures for hook protocol
-//! - `action`: Editor operations abstraction (buffer status, refresh, messages)
+//! - `action`: Editor operations abstraction (buffer status, refresh, messages),
+//!   with Neovim, Kakoune and JetBrains backends
 //! - `utils`: Socket path computation and discovery
+//! - `config`: Layered settings from env, project and user config
+//! - `session`: Per-session state kept between hook invocations
+//! - `transcript`: Best-effort reading of the AI session transcript
 //! - `constants`: Shared constants (timeouts, paths)
 //!
+//! The `Action` trait, its backends and the types they return are
+//! re-exported here at the crate root; that's the surface meant for library
+//! use.
+//!
 //! # Example: Using as a Library
 //!
 //! ```no_run
-//! use sidekick::action::{Action, neovim::NeovimAction};
+//! use sidekick::{Action, BufferStatus, NeovimAction};
 //! use sidekick::utils;
 //!
 //! // Find Neovim instances in current directory
 //! let sockets = utils::find_matching_sockets().unwrap();
-//! let action = NeovimAction::new(sockets);
+//! let action = NeovimAction::from_sockets(sockets);
 //!
 //! // Check if file can be modified
-//! let status = action.buffer_status("file.txt").unwrap();
-//! if !status.has_unsaved_changes {
+//! let statuses: Vec<BufferStatus> = action.buffer_status("file.txt").unwrap();
+//! if !statuses.iter().any(|s| s.has_unsaved_changes) {
 //!     // Safe to modify file
 //! }
 //! ```
 
 pub mod action;
 pub mod analytics;
+pub mod config;
 pub mod constants;
 pub mod handler;
 pub mod hook;
+pub mod session;
+pub mod transcript;
 pub mod utils;
+
+pub use action::jetbrains::JetBrainsAction;
+pub use action::kakoune::KakouneAction;
+pub use action::neovim::{NeovimAction, NeovimEndpoint};
+pub use action::{Action, BufferStatus, EditorContext, EditorView, NotifyLevel};
@@ -1,42 +1,160 @@
+use anyhow::Context;
 use chrono::Utc;
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
 use std::io;
 use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 mod action;
 mod analytics;
+mod config;
 mod constants;
 mod demo;
 mod doctor;
 mod fix;
 mod handler;
+// The `testing` helpers are for the library's users; the binary never
+// calls them
+#[cfg_attr(feature = "testing", allow(dead_code))]
 mod hook;
 mod init;
+mod install;
+mod session;
+mod transcript;
 mod utils;
 
+use action::{Action, neovim::NeovimAction};
 use analytics::event::{Event, NvimLaunch, StatsView};
 use analytics::render::{Renderer, terminal::TerminalRenderer};
 use analytics::{TimeRange, aggregate};
+use config::{Config, Verbosity};
+use hook::OutputFormat;
 
 #[derive(Parser)]
-#[command(name = "sidekick")]
+#[command(name = "sidekick", version)]
 #[command(about = "Protects your unsaved Neovim work from Claude Code.", long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Print nothing on stderr, not even warnings.
+    #[arg(long, short, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Explain what the hook found and decided on stderr.
+    #[arg(long, short, global = true)]
+    verbose: bool,
+}
+
+impl Cli {
+    fn verbosity(&self) -> Verbosity {
+        match (self.quiet, self.verbose) {
+            (true, _) => Verbosity::Quiet,
+            (_, true) => Verbosity::Verbose,
+            _ => Verbosity::Normal,
+        }
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Run as a Claude Code hook
-    Hook,
+    Hook {
+        /// Output format for the hook response.
+        #[arg(long, value_enum, default_value_t = HookFormat::Json)]
+        format: HookFormat,
+        /// Project directory to discover editors for (defaults to the cwd).
+        #[arg(long)]
+        cwd: Option<PathBuf>,
+        /// Read one hook per line and write one compact response per line.
+        #[arg(long)]
+        ndjson: bool,
+        /// Exit 2 when the response denies the tool call (the JSON is still
+        /// written); 0 otherwise. Without it the exit code is always 0.
+        #[arg(long)]
+        exit_code: bool,
+        /// Allow the tool call if nothing arrives on stdin within this many
+        /// milliseconds, instead of waiting for input forever.
+        #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(1..))]
+        stdin_timeout: Option<u64>,
+        /// Also print each hook as sidekick parsed it to stderr, as JSON.
+        #[arg(long)]
+        echo_parsed: bool,
+    },
     /// Launch Neovim with sidekick wired in
     Neovim {
+        /// Print the socket Neovim will listen on to stderr before launching.
+        #[arg(long)]
+        print_socket: bool,
+        /// Launch plain nvim, without the socket that lets sidekick find it.
+        #[arg(long, conflicts_with = "print_socket")]
+        no_listen: bool,
+        /// Set KEY=VALUE in Neovim's environment, e.g. NVIM_APPNAME.
+        /// Repeatable.
+        #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env)]
+        env: Vec<(String, String)>,
         /// Arguments to pass to Neovim
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         args: Vec<String>,
     },
+    /// Launch Kakoune with sidekick wired in
+    Kakoune {
+        /// Arguments to pass to Kakoune
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// List the files open in editors for this directory.
+    Buffers {
+        /// Only show buffers with unsaved changes.
+        #[arg(long)]
+        dirty_only: bool,
+    },
+    /// Exit nonzero if any of these files, or any open file, has unsaved
+    /// changes.
+    Check {
+        /// Files to check (defaults to every open buffer).
+        paths: Vec<PathBuf>,
+    },
+    /// Let the AI overwrite a file whose edit sidekick blocked, unsaved
+    /// changes and all. Meant for an editor command.
+    Approve {
+        /// The blocked file.
+        path: PathBuf,
+    },
+    /// Open a file as it was at a git revision, read-only, in the editor.
+    Open {
+        /// `<file>@<rev>`, e.g. `src/main.rs@HEAD~2`.
+        target: String,
+    },
+    /// Reload sidekick's editor-side integration in every running editor
+    /// for this directory, e.g. after an upgrade.
+    ReloadPlugin,
+    /// List the files the AI changed in a session.
+    SessionFiles {
+        /// Session to list (defaults to the most recently active one).
+        #[arg(long)]
+        session: Option<String>,
+    },
+    /// Show each Neovim instance for this directory and its open files.
+    Status {
+        /// Inspect only the instance launched with this pid.
+        #[arg(long)]
+        pid: Option<u32>,
+    },
+    /// Print the Neovim socket path for this directory.
+    SocketPath {
+        /// Process the socket is for (defaults to the calling shell, which
+        /// keeps its pid across `exec sidekick neovim`).
+        #[arg(long, conflicts_with_all = ["all", "all_dirs"])]
+        pid: Option<u32>,
+        /// List the sockets of every running instance instead.
+        #[arg(long)]
+        all: bool,
+        /// List every sidekick socket whatever its directory, each after
+        /// its directory hash.
+        #[arg(long, conflicts_with = "all")]
+        all_dirs: bool,
+    },
     /// Show your sidekick story — what the AI did, what got caught.
     Stats {
         /// Time window to summarize.
@@ -46,6 +164,12 @@ enum Commands {
         #[arg(long)]
         no_color: bool,
     },
+    /// Print sidekick's decision log, one event per line.
+    Log {
+        /// Keep printing events as they are logged.
+        #[arg(short, long)]
+        follow: bool,
+    },
     /// Check that sidekick is installed and wired up.
     Doctor {
         /// Disable colors.
@@ -61,8 +185,28 @@ enum Commands {
         #[arg(long)]
         no_color: bool,
     },
+    /// Register the hooks in Claude Code's settings, non-interactively.
+    Install {
+        /// Settings file to update (defaults to ~/.claude/settings.json).
+        #[arg(long)]
+        settings_path: Option<PathBuf>,
+    },
+    /// Remove the sidekick hooks from Claude Code's settings.
+    Uninstall {
+        /// Settings file to update (defaults to ~/.claude/settings.json).
+        #[arg(long)]
+        settings_path: Option<PathBuf>,
+    },
     /// Play a short demo of sidekick.
     Demo,
+    /// Print the version, the editor plugin protocol and the backends.
+    Version,
+    /// Print a shell completion script.
+    Completions {
+        /// Shell to generate completions for.
+        #[arg(value_enum)]
+        shell: Shell,
+    },
 }
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
@@ -73,6 +217,21 @@ enum StatsRange {
     All,
 }
 
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum HookFormat {
+    Json,
+    Pretty,
+}
+
+impl From<HookFormat> for OutputFormat {
+    fn from(f: HookFormat) -> Self {
+        match f {
+            HookFormat::Json => OutputFormat::Json,
+            HookFormat::Pretty => OutputFormat::Pretty,
+        }
+    }
+}
+
 impl From<StatsRange> for TimeRange {
     fn from(r: StatsRange) -> Self {
         match r {
@@ -85,10 +244,27 @@ impl From<StatsRange> for TimeRange {
 }
 
 /// Handle the 'neovim' command
-fn handle_neovim(args: Vec<String>) -> anyhow::Result<()> {
+fn handle_neovim(
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    print_socket: bool,
+    no_listen: bool,
+) -> anyhow::Result<()> {
+    // Just an alias for nvim: no socket, so nothing to record either
+    if no_listen {
+        let err = nvim_command(None, &env, &args).exec();
+        return Err(anyhow::anyhow!("couldn't launch nvim: {}", err));
+    }
+
     let pid = std::process::id();
+    utils::prepare_nvim_socket_dir()?;
     let socket_path = utils::compute_socket_path_with_pid(pid)?;
 
+    // exec keeps our pid, so this is the socket the hook will find
+    if print_socket {
+        eprintln!("{}", socket_path.display());
+    }
+
     // Record the launch before we hand the process off to nvim via exec.
     // `write_all` on an O_APPEND file goes straight to the kernel — the bytes
     // survive the exec(2) replacement of our process image.
@@ -103,20 +279,48 @@ fn handle_neovim(args: Vec<String>) -> anyhow::Result<()> {
         args: args.clone(),
     }));
 
-    // Build neovim command with --listen flag
-    let mut cmd = Command::new("nvim");
-    cmd.arg("--listen").arg(&socket_path);
-
-    // Add all trailing arguments
-    cmd.args(&args);
-
     // Execute neovim, replacing current process
-    let err = cmd.exec();
+    let err = nvim_command(Some(&socket_path), &env, &args).exec();
 
     // If exec returns, it failed
     Err(anyhow::anyhow!("couldn't launch nvim: {}", err))
 }
 
+/// `nvim` with the user's arguments, listening on `socket` if given. `env`
+/// is set on top of the environment it inherits.
+fn nvim_command(socket: Option<&Path>, env: &[(String, String)], args: &[String]) -> Command {
+    let mut cmd = Command::new("nvim");
+    cmd.envs(env.iter().map(|(key, value)| (key, value)));
+    if let Some(socket) = socket {
+        cmd.arg("--listen").arg(socket);
+    }
+    cmd.args(args);
+    cmd
+}
+
+/// A `--env` value, `KEY=VALUE`
+fn parse_env(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {:?}", value)),
+    }
+}
+
+/// Handle the 'kakoune' command
+fn handle_kakoune(args: Vec<String>) -> anyhow::Result<()> {
+    let cwd = std::env::current_dir()?;
+    let session = utils::compute_kak_session_name(&cwd, std::process::id())?;
+
+    // Name the session so the hook can find it, then hand off to kak
+    let err = Command::new("kak")
+        .arg("-s")
+        .arg(&session)
+        .args(&args)
+        .exec();
+
+    Err(anyhow::anyhow!("couldn't launch kak: {}", err))
+}
+
 fn handle_stats(range: StatsRange, no_color: bool) -> anyhow::Result<()> {
     // Log this view first; the rendered "Nth look today" counts include it.
     let range_label = match range {
@@ -138,13 +342,284 @@ fn handle_stats(range: StatsRange, no_color: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn handle_log(follow: bool) -> anyhow::Result<()> {
+    use std::io::{IsTerminal, Write};
+
+    let color = io::stdout().is_terminal();
+    let mut offset = 0;
+    loop {
+        let (events, next) = analytics::store::read_from(offset)?;
+        let mut stdout = io::stdout().lock();
+        for event in &events {
+            writeln!(stdout, "{}", analytics::render::log::line(event, color))?;
+        }
+        stdout.flush()?;
+
+        if !follow {
+            return Ok(());
+        }
+        offset = next;
+        std::thread::sleep(std::time::Duration::from_millis(500));
+    }
+}
+
+/// Split `<file>@<rev>` at the first `@` that doesn't start a path
+/// component, so scoped paths like `node_modules/@scope/x.js` survive and
+/// revisions like `HEAD@{1}` keep theirs
+fn parse_revision_target(target: &str) -> anyhow::Result<(&str, &str)> {
+    target
+        .match_indices('@')
+        .map(|(at, _)| (&target[..at], &target[at + 1..]))
+        .find(|(file, _)| !file.is_empty() && !file.ends_with('/'))
+        .filter(|(_, rev)| !rev.is_empty())
+        .with_context(|| format!("expected <file>@<rev>, got {}", target))
+}
+
+fn handle_open(target: &str) -> anyhow::Result<()> {
+    let (file, rev) = parse_revision_target(target)?;
+    let cwd = std::env::current_dir()?;
+    let Some(editors) = discover_editors(&cwd) else {
+        anyhow::bail!("no editor open in this directory");
+    };
+    let path = utils::normalize_path(&cwd.join(file));
+    editors.open_at_revision(&path.to_string_lossy(), rev)
+}
+
+fn handle_reload_plugin() -> anyhow::Result<()> {
+    let cwd = std::env::current_dir()?;
+    let Some(editors) = discover_editors(&cwd) else {
+        anyhow::bail!("no editor open in this directory");
+    };
+    editors.reload_plugin()
+}
+
+/// Editors opened from `cwd`, found as the hook finds them, under the
+/// user's and the project's config
+fn discover_editors(cwd: &Path) -> Option<Box<dyn Action>> {
+    action::discover(cwd, &Config::load(cwd, Verbosity::default())).0
+}
+
+fn handle_buffers(dirty_only: bool) -> anyhow::Result<()> {
+    let cwd = std::env::current_dir()?;
+    let Some(editors) = discover_editors(&cwd) else {
+        eprintln!("No editor open in this directory");
+        return Ok(());
+    };
+
+    for line in buffer_lines(editors.as_ref(), dirty_only)? {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Print the dirty files among `paths`, or among every open buffer, and say
+/// whether there were any
+fn handle_check(paths: Vec<PathBuf>) -> anyhow::Result<bool> {
+    let cwd = std::env::current_dir()?;
+    let Some(editors) = discover_editors(&cwd) else {
+        eprintln!("No editor open in this directory");
+        return Ok(false);
+    };
+
+    let paths: Vec<String> = paths
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+    let dirty = dirty_files(editors.as_ref(), &paths)?;
+    for name in &dirty {
+        println!("{} [+]", name);
+    }
+    Ok(!dirty.is_empty())
+}
+
+/// Which of `paths` (every open buffer if empty) have unsaved changes in
+/// some editor instance
+fn dirty_files(editors: &dyn Action, paths: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut names = if paths.is_empty() {
+        editors.list_buffers()?
+    } else {
+        paths.to_vec()
+    };
+    names.sort();
+    names.dedup();
+
+    let mut dirty = Vec::new();
+    for name in names {
+        let statuses = editors
+            .buffer_status(&name)
+            .with_context(|| format!("couldn't check {}", name))?;
+        if statuses.iter().any(|s| s.has_unsaved_changes) {
+            dirty.push(name);
+        }
+    }
+    Ok(dirty)
+}
+
+fn handle_status(pid: Option<u32>) -> anyhow::Result<()> {
+    let instances = match pid {
+        Some(pid) => vec![utils::InstanceInfo {
+            socket: pid_socket(pid)?,
+            pid,
+            editor: utils::EditorKind::Neovim,
+            alive: utils::process_alive(pid),
+        }],
+        None => utils::discover_instances()?
+            .into_iter()
+            .filter(|i| i.editor == utils::EditorKind::Neovim)
+            .collect(),
+    };
+    if instances.is_empty() {
+        eprintln!("No Neovim open in this directory");
+        return Ok(());
+    }
+
+    for utils::InstanceInfo {
+        socket, pid, alive, ..
+    } in instances
+    {
+        if !alive {
+            println!("{}  stale, pid {} has exited", socket.display(), pid);
+            continue;
+        }
+        let instance = NeovimAction::from_sockets(vec![socket.clone()]);
+        let Some(version) = instance.versions().pop() else {
+            println!("{}  unreachable", socket.display());
+            continue;
+        };
+
+        println!("{}  NVIM {}", socket.display(), version);
+        for line in buffer_lines(&instance, false)? {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Socket paths `socket-path` prints: the one for `pid`, or every live one
+fn socket_paths(pid: Option<u32>, all: bool) -> anyhow::Result<Vec<PathBuf>> {
+    if all {
+        return utils::find_matching_sockets();
+    }
+    let pid = pid.unwrap_or_else(std::os::unix::process::parent_id);
+    Ok(vec![utils::compute_socket_path_with_pid(pid)?])
+}
+
+/// Socket of the instance `sidekick neovim` launched as `pid` from here
+fn pid_socket(pid: u32) -> anyhow::Result<PathBuf> {
+    let socket = utils::compute_socket_path_with_pid(pid)?;
+    if !socket.exists() {
+        anyhow::bail!(
+            "no Neovim socket for pid {} in this directory (expected {})",
+            pid,
+            socket.display()
+        );
+    }
+    Ok(socket)
+}
+
+/// One line per open file, `[+]` marking unsaved changes in any instance
+fn buffer_lines(editors: &dyn Action, dirty_only: bool) -> anyhow::Result<Vec<String>> {
+    let mut names = editors.list_buffers()?;
+    names.sort();
+    names.dedup();
+
+    Ok(names
+        .into_iter()
+        .filter_map(|name| {
+            let dirty = editors
+                .buffer_status(&name)
+                .is_ok_and(|statuses| statuses.iter().any(|s| s.has_unsaved_changes));
+            match (dirty, dirty_only) {
+                (true, _) => Some(format!("{} [+]", name)),
+                (false, false) => Some(name),
+                (false, true) => None,
+            }
+        })
+        .collect())
+}
+
+/// What `sidekick version` prints: the crate version, the editor plugin
+/// protocol it speaks, and the editor backends built in
+fn version_lines() -> Vec<String> {
+    vec![
+        format!("sidekick {}", env!("CARGO_PKG_VERSION")),
+        format!("plugin protocol: {}", constants::PLUGIN_PROTOCOL_VERSION),
+        "backends: neovim, kakoune, jetbrains".to_string(),
+    ]
+}
+
+/// Write the completion script for `shell` to `out`
+fn write_completions(shell: Shell, out: &mut impl io::Write) {
+    clap_complete::generate(shell, &mut Cli::command(), "sidekick", out);
+}
+
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
+    let verbosity = cli.verbosity();
 
     match cli.command {
-        Commands::Hook => handler::handle_hook()?,
-        Commands::Neovim { args } => handle_neovim(args)?,
+        Commands::Hook {
+            format,
+            cwd,
+            ndjson,
+            exit_code,
+            stdin_timeout,
+            echo_parsed,
+        } => {
+            let code = handler::handle_hook(&handler::HookOptions {
+                format: format.into(),
+                cwd,
+                verbosity,
+                ndjson,
+                exit_code,
+                stdin_timeout: stdin_timeout.map(std::time::Duration::from_millis),
+                echo_parsed,
+            })?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        Commands::Neovim {
+            args,
+            env,
+            print_socket,
+            no_listen,
+        } => handle_neovim(args, env, print_socket, no_listen)?,
+        Commands::Kakoune { args } => handle_kakoune(args)?,
+        Commands::Buffers { dirty_only } => handle_buffers(dirty_only)?,
+        Commands::Check { paths } => {
+            if handle_check(paths)? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Open { target } => handle_open(&target)?,
+        Commands::ReloadPlugin => handle_reload_plugin()?,
+        Commands::Approve { path } => {
+            if session::approve_everywhere(&path) == 0 {
+                anyhow::bail!("no blocked edit of {} to approve", path.display());
+            }
+        }
+        Commands::SessionFiles { session } => {
+            let session_id = session
+                .or_else(session::latest)
+                .context("no session has been recorded yet")?;
+            for file in session::SessionState::load(&session_id).modified {
+                println!("{}", file);
+            }
+        }
+        Commands::Status { pid } => handle_status(pid)?,
+        Commands::SocketPath { all_dirs: true, .. } => {
+            for (hash, path) in utils::find_all_sockets()? {
+                println!("{}  {}", hash, path.display());
+            }
+        }
+        Commands::SocketPath { pid, all, .. } => {
+            for path in socket_paths(pid, all)? {
+                println!("{}", path.display());
+            }
+        }
         Commands::Stats { range, no_color } => handle_stats(range, no_color)?,
+        Commands::Log { follow } => handle_log(follow)?,
         Commands::Doctor { no_color, fix } => {
             let any_failed = doctor::run(no_color, fix)?;
             if fix {
@@ -154,8 +629,247 @@ fn main() -> anyhow::Result<()> {
             }
         }
         Commands::Init { no_color } => init::run(no_color)?,
+        Commands::Install { settings_path } => install::run(settings_path)?,
+        Commands::Uninstall { settings_path } => install::uninstall(settings_path)?,
         Commands::Demo => demo::run()?,
+        Commands::Version => {
+            for line in version_lines() {
+                println!("{}", line);
+            }
+        }
+        Commands::Completions { shell } => write_completions(shell, &mut io::stdout()),
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use action::{BufferStatus, EditorContext};
+
+    /// Editor with a fixed set of open files, some of them dirty
+    struct Buffers(Vec<(&'static str, bool)>);
+
+    impl Action for Buffers {
+        fn buffer_status(&self, file_path: &str) -> anyhow::Result<Vec<BufferStatus>> {
+            Ok(self
+                .0
+                .iter()
+                .filter(|(name, _)| *name == file_path)
+                .map(|(_, dirty)| BufferStatus {
+                    is_current: false,
+                    has_unsaved_changes: *dirty,
+                    instance: None,
+                })
+                .collect())
+        }
+
+        fn buffer_contents(&self, _file_path: &str) -> anyhow::Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn refresh_buffer(&self, _file_path: &str) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn send_message(&self, _message: &str, _level: action::NotifyLevel) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn list_buffers(&self) -> anyhow::Result<Vec<String>> {
+            Ok(self.0.iter().map(|(name, _)| name.to_string()).collect())
+        }
+
+        fn get_visual_selections(
+            &self,
+            _max_bytes: Option<usize>,
+        ) -> anyhow::Result<Vec<EditorContext>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn version_names_the_crate_version() {
+        let lines = version_lines();
+        assert_eq!(lines[0], format!("sidekick {}", env!("CARGO_PKG_VERSION")));
+        assert!(lines.iter().any(|l| l == "plugin protocol: 1"));
+    }
+
+    #[test]
+    fn buffer_lines_mark_dirty_and_merge_instances() {
+        let editors = Buffers(vec![
+            ("/p/src/main.rs", false),
+            ("/p/README.md", false),
+            ("/p/src/lib.rs", true),
+            // Also open, clean, in a second instance
+            ("/p/src/lib.rs", false),
+        ]);
+
+        assert_eq!(
+            buffer_lines(&editors, false).unwrap(),
+            ["/p/README.md", "/p/src/lib.rs [+]", "/p/src/main.rs"]
+        );
+        assert_eq!(buffer_lines(&editors, true).unwrap(), ["/p/src/lib.rs [+]"]);
+    }
+
+    #[test]
+    fn check_reports_only_dirty_files() {
+        let editors = Buffers(vec![
+            ("/p/src/main.rs", false),
+            ("/p/src/lib.rs", true),
+            ("/p/README.md", true),
+        ]);
+
+        assert_eq!(
+            dirty_files(&editors, &[]).unwrap(),
+            ["/p/README.md", "/p/src/lib.rs"]
+        );
+        let given = ["/p/src/main.rs".to_string(), "/p/src/lib.rs".to_string()];
+        assert_eq!(dirty_files(&editors, &given).unwrap(), ["/p/src/lib.rs"]);
+        assert!(
+            dirty_files(&editors, &["/p/src/main.rs".to_string()])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn no_listen_launches_nvim_without_a_socket() {
+        let cli = Cli::try_parse_from(["sidekick", "neovim", "--no-listen", "notes.md"]);
+        let Commands::Neovim {
+            no_listen, args, ..
+        } = cli.unwrap().command
+        else {
+            panic!("expected the neovim command");
+        };
+        assert!(no_listen);
+
+        let plain = nvim_command(None, &[], &args);
+        assert_eq!(plain.get_args().collect::<Vec<_>>(), ["notes.md"]);
+        let listening = nvim_command(Some(Path::new("/tmp/x.sock")), &[], &args);
+        assert_eq!(
+            listening.get_args().collect::<Vec<_>>(),
+            ["--listen", "/tmp/x.sock", "notes.md"]
+        );
+    }
+
+    #[test]
+    fn env_flags_are_set_on_nvim_only() {
+        let cli = Cli::try_parse_from([
+            "sidekick",
+            "neovim",
+            "--env",
+            "NVIM_APPNAME=nvim-minimal",
+            "--env",
+            "GREETING=a=b",
+            "notes.md",
+        ]);
+        let Commands::Neovim { env, args, .. } = cli.unwrap().command else {
+            panic!("expected the neovim command");
+        };
+
+        let cmd = nvim_command(None, &env, &args);
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(
+            envs,
+            [
+                ("GREETING".as_ref(), Some("a=b".as_ref())),
+                ("NVIM_APPNAME".as_ref(), Some("nvim-minimal".as_ref())),
+            ]
+        );
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["notes.md"]);
+
+        assert!(Cli::try_parse_from(["sidekick", "neovim", "--env", "NOVALUE"]).is_err());
+    }
+
+    #[test]
+    fn print_socket_is_ours_and_leaves_nvim_args_alone() {
+        let cli = Cli::try_parse_from(["sidekick", "neovim", "--print-socket", "-O", "a.rs"]);
+        let Commands::Neovim {
+            print_socket, args, ..
+        } = cli.unwrap().command
+        else {
+            panic!("expected the neovim command");
+        };
+        assert!(print_socket);
+        assert_eq!(args, ["-O", "a.rs"]);
+
+        let pid = std::process::id();
+        let printed = utils::compute_socket_path_with_pid(pid).unwrap();
+        assert_eq!(printed.parent(), Some(utils::nvim_socket_dir().as_path()));
+        assert!(
+            printed
+                .to_string_lossy()
+                .ends_with(&format!("-{}.sock", pid))
+        );
+    }
+
+    #[test]
+    fn socket_path_matches_computed_paths() {
+        assert_eq!(
+            socket_paths(Some(4321), false).unwrap(),
+            [utils::compute_socket_path_with_pid(4321).unwrap()]
+        );
+        assert_eq!(
+            socket_paths(None, false).unwrap(),
+            [utils::compute_socket_path_with_pid(std::os::unix::process::parent_id()).unwrap()]
+        );
+        assert_eq!(
+            socket_paths(None, true).unwrap(),
+            utils::find_matching_sockets().unwrap()
+        );
+        assert!(Cli::try_parse_from(["sidekick", "socket-path", "--pid", "1", "--all"]).is_err());
+    }
+
+    #[test]
+    fn global_verbosity_flags() {
+        let verbosity = |args: &[&str]| Cli::try_parse_from(args).unwrap().verbosity();
+        assert_eq!(verbosity(&["sidekick", "hook"]), Verbosity::Normal);
+        assert_eq!(
+            verbosity(&["sidekick", "hook", "--quiet"]),
+            Verbosity::Quiet
+        );
+        assert_eq!(verbosity(&["sidekick", "-v", "hook"]), Verbosity::Verbose);
+        assert!(Cli::try_parse_from(["sidekick", "-q", "-v", "hook"]).is_err());
+    }
+
+    #[test]
+    fn pid_socket_requires_the_socket_to_exist() {
+        let pid = u32::MAX - std::process::id();
+        let expected = utils::compute_socket_path_with_pid(pid).unwrap();
+
+        let missing = pid_socket(pid).unwrap_err().to_string();
+        assert!(missing.contains(&expected.display().to_string()));
+
+        std::fs::write(&expected, "").unwrap();
+        let found = pid_socket(pid);
+        std::fs::remove_file(&expected).unwrap();
+        assert_eq!(found.unwrap(), expected);
+    }
+
+    #[test]
+    fn revision_targets_split_at_the_revision_at() {
+        let parse = |target| parse_revision_target(target).ok();
+        assert_eq!(parse("src/main.rs@HEAD~2"), Some(("src/main.rs", "HEAD~2")));
+        assert_eq!(parse("lib.rs@origin/main"), Some(("lib.rs", "origin/main")));
+        assert_eq!(parse("a.rs@HEAD@{1}"), Some(("a.rs", "HEAD@{1}")));
+        assert_eq!(
+            parse("node_modules/@scope/x.js@v1"),
+            Some(("node_modules/@scope/x.js", "v1"))
+        );
+        for bad in ["main.rs", "main.rs@", "@HEAD", "node_modules/@scope"] {
+            assert_eq!(parse(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn completions_generate_for_each_shell() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let mut out = Vec::new();
+            write_completions(shell, &mut out);
+            let script = String::from_utf8(out).unwrap();
+            assert!(script.contains("sidekick"), "{shell} script is empty");
+        }
+    }
+}
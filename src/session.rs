@@ -0,0 +1,264 @@
+//! Per-session state shared across hook invocations.
+//!
+//! Every hook runs as a fresh process, so anything that has to outlive one
+//! invocation — notification rate limits, edits awaiting a save — is kept in a small JSON
+//! file per Claude session in the user's private `utils::state_dir()`. Files
+//! are replaced whole, by rename, so a reader never sees half a write. Like
+//! the analytics store this is best-effort: a missing or unreadable file is
+//! an empty state, and a failed write is dropped rather than failing the
+//! hook.
+//!
+//! The files the AI changed are kept too, for `sidekick session-files`.
+//!
+//! A denied edit doubles as a resume token: `sidekick approve <file>`, run
+//! from an editor command, marks it approved in every session waiting on
+//! that file, and the next attempt at the edit goes through.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// State remembered for one session
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionState {
+    /// When editor notifications were sent, for rate limiting
+    #[serde(default)]
+    pub notifications: Vec<DateTime<Utc>>,
+    /// Files whose edit was denied, waiting for the user to save and the AI
+    /// to retry
+    #[serde(default)]
+    pub awaiting_save: BTreeMap<String, DateTime<Utc>>,
+    /// Denied files the user has since approved overwriting
+    #[serde(default)]
+    pub approved: BTreeSet<String>,
+    /// Under soft blocking, the edit last denied per file: its hash and when
+    #[serde(default)]
+    pub soft_denied: BTreeMap<String, (String, DateTime<Utc>)>,
+    /// Files the AI changed in this session
+    #[serde(default)]
+    pub modified: BTreeSet<String>,
+    /// The file the editor status shows being edited, until the turn stops,
+    /// so it's only sent when that changes
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+impl SessionState {
+    /// Load the state for `session_id`, or an empty state if there is none
+    pub fn load(session_id: &str) -> Self {
+        // Only from a state dir that is really ours
+        if ensure_private().is_err() {
+            return Self::default();
+        }
+        std::fs::read(path(session_id))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Persist the state for `session_id`. Never propagates errors.
+    pub fn save(&self, session_id: &str) {
+        let _ = self.try_save(session_id);
+    }
+
+    fn try_save(&self, session_id: &str) -> anyhow::Result<()> {
+        if utils::answer_overdue() {
+            anyhow::bail!("couldn't save session state: the hook answer is overdue");
+        }
+        ensure_private()?;
+        let path = path(session_id);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        utils::write_atomically(&path, &serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Record a notification at `now` unless `limit` were already sent within
+    /// `window`. Returns whether the notification may be sent.
+    pub fn try_record_notification(
+        &mut self,
+        now: DateTime<Utc>,
+        limit: usize,
+        window: std::time::Duration,
+    ) -> bool {
+        let window = Duration::from_std(window).unwrap_or(Duration::MAX);
+        self.notifications.retain(|at| now - *at < window);
+        if self.notifications.len() >= limit {
+            return false;
+        }
+        self.notifications.push(now);
+        true
+    }
+
+    /// Remember that an edit to `file_path` was denied at `now`
+    pub fn record_denied(&mut self, file_path: &str, now: DateTime<Utc>) {
+        self.awaiting_save.insert(file_path.to_string(), now);
+    }
+
+    /// Forget a denied edit to `file_path`, returning whether there was one
+    pub fn take_denied(&mut self, file_path: &str) -> bool {
+        self.awaiting_save.remove(file_path).is_some()
+    }
+
+    /// Remember that the edit hashing to `edit` was denied on `file_path`
+    /// at `now`, so a retry can be recognized
+    pub fn record_soft_denied(&mut self, file_path: &str, edit: &str, now: DateTime<Utc>) {
+        self.soft_denied
+            .insert(file_path.to_string(), (edit.to_string(), now));
+    }
+
+    /// Consume a soft deny of the same `edit` to `file_path` made within
+    /// `grace` of `now`, returning whether there was one
+    pub fn take_soft_retry(
+        &mut self,
+        file_path: &str,
+        edit: &str,
+        now: DateTime<Utc>,
+        grace: std::time::Duration,
+    ) -> bool {
+        let grace = Duration::from_std(grace).unwrap_or(Duration::MAX);
+        let Some((denied, at)) = self.soft_denied.remove(file_path) else {
+            return false;
+        };
+        denied == edit && now - at <= grace
+    }
+
+    /// Approve the denied edits to `file`, however the hook spelled its
+    /// path. Returns whether there was one.
+    pub fn approve(&mut self, file: &Path) -> bool {
+        let denied: Vec<String> = self
+            .awaiting_save
+            .keys()
+            .filter(|key| utils::normalize_path(Path::new(key)) == utils::normalize_path(file))
+            .cloned()
+            .collect();
+        let any = !denied.is_empty();
+        self.approved.extend(denied);
+        any
+    }
+
+    /// Consume an approval for `file_path`, returning whether there was one
+    pub fn take_approved(&mut self, file_path: &str) -> bool {
+        if !self.approved.remove(file_path) {
+            return false;
+        }
+        self.awaiting_save.remove(file_path);
+        true
+    }
+}
+
+/// Approve the denied edits to `file` in every session, returning how many
+/// sessions had one
+pub fn approve_everywhere(file: &Path) -> usize {
+    if ensure_private().is_err() {
+        return 0;
+    }
+    let Ok(entries) = std::fs::read_dir(sessions_dir()) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            // Skips the `.tmp` files of writes in flight, too
+            Some(name.to_str()?.strip_suffix(".json")?.to_string())
+        })
+        .filter(|session_id| {
+            let mut state = SessionState::load(session_id);
+            let approved = state.approve(file);
+            if approved {
+                state.save(session_id);
+            }
+            approved
+        })
+        .count()
+}
+
+/// The session whose state changed last, if any has state
+pub fn latest() -> Option<String> {
+    ensure_private().ok()?;
+    std::fs::read_dir(sessions_dir())
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            let name = entry.file_name();
+            Some((modified, name.to_str()?.strip_suffix(".json")?.to_string()))
+        })
+        .max()
+        .map(|(_, session_id)| session_id)
+}
+
+/// Where session files live. Unit tests get a directory of their own, so
+/// they never read or approve the user's live sessions.
+fn sessions_dir() -> PathBuf {
+    if cfg!(test) {
+        return std::env::temp_dir().join(format!("sidekick-test-sessions-{}", std::process::id()));
+    }
+    utils::state_dir_path().join("sidekick-sessions")
+}
+
+/// Check the state dir is really ours before touching sessions in it
+fn ensure_private() -> anyhow::Result<()> {
+    if !cfg!(test) {
+        utils::state_dir()?;
+    }
+    Ok(())
+}
+
+/// State file for a session. Session ids are UUIDs in practice; anything
+/// else is reduced to filename-safe characters.
+pub(crate) fn path(session_id: &str) -> PathBuf {
+    let mut name: String = session_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+    if name.is_empty() {
+        name.push_str("anonymous");
+    }
+    sessions_dir().join(format!("{}.json", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SessionState, path};
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn state_is_saved_privately_and_read_back() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let session_id = format!("private-{}", std::process::id());
+        let mut state = SessionState::default();
+        state.modified.insert("src/lib.rs".to_string());
+        state.save(&session_id);
+
+        let mode =
+            std::fs::metadata(path(&session_id)).map(|meta| meta.permissions().mode() & 0o777);
+        let loaded = SessionState::load(&session_id);
+        let _ = std::fs::remove_file(path(&session_id));
+
+        assert_eq!(mode.unwrap(), 0o600);
+        assert_eq!(loaded.modified, state.modified);
+    }
+
+    #[test]
+    fn suppresses_notifications_past_the_limit_within_window() {
+        let window = std::time::Duration::from_secs(60);
+        let start = Utc::now();
+        let mut state = SessionState::default();
+
+        for i in 0..3 {
+            assert!(state.try_record_notification(start + Duration::seconds(i), 3, window));
+        }
+        assert!(!state.try_record_notification(start + Duration::seconds(10), 3, window));
+
+        // Once the first ones age out of the window, notifications resume.
+        assert!(state.try_record_notification(start + Duration::seconds(61), 3, window));
+    }
+}
@@ -0,0 +1,282 @@
+//! Best-effort reading of the Claude Code session transcript.
+//!
+//! The transcript is a JSONL file, one entry per line. User turns look like
+//! `{"type":"user","message":{"role":"user","content":...}}` where `content`
+//! is either a string or a list of blocks; only `text` blocks are what the
+//! user typed (tool results arrive as user turns too and are skipped).
+//!
+//! Only the last lines are read, in chunks backwards from the end, so the
+//! cost stays flat however long the session runs. Anything unparseable is
+//! skipped rather than reported.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::constants::{TRANSCRIPT_RECENT_TURNS, TRANSCRIPT_TAIL_BYTES, TRANSCRIPT_TAIL_LINES};
+
+/// Phrases that mean "write it even though I have unsaved changes"
+const OVERWRITE_PHRASES: &[&str] = &[
+    "overwrite",
+    "discard my changes",
+    "ignore my changes",
+    "ignore my unsaved",
+    "ignore unsaved",
+];
+
+/// Something the user recently asked the AI to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Intent {
+    /// Write over unsaved editor changes, for the files the message names
+    Overwrite { mentions: Vec<String> },
+}
+
+impl Intent {
+    /// Whether this intent lets the AI overwrite `file_path`. The file must
+    /// be named, by path or by file name; a bare "overwrite it" isn't enough.
+    pub fn permits_overwrite(&self, file_path: &str) -> bool {
+        let Intent::Overwrite { mentions } = self;
+        let file_name = Path::new(file_path)
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+
+        mentions.iter().any(|m| {
+            file_path.ends_with(m.as_str())
+                && Path::new(m).file_name().is_some_and(|n| *n == *file_name)
+        })
+    }
+}
+
+/// Intents from the last few user turns of the transcript at `path`
+pub fn recent_intents(path: &Path) -> Result<Vec<Intent>> {
+    let lines = tail_lines(path, TRANSCRIPT_TAIL_LINES)?;
+    Ok(parse_intents(&lines.join("\n")))
+}
+
+/// The last `n` lines of the file at `path`, oldest first, reading only as
+/// far back from the end as they reach
+pub fn tail_lines(path: &Path, n: usize) -> Result<Vec<String>> {
+    let mut file = File::open(path).context("couldn't open transcript")?;
+    tail_lines_of(&mut file, n).context("couldn't read transcript")
+}
+
+fn tail_lines_of(reader: &mut (impl Read + Seek), n: usize) -> std::io::Result<Vec<String>> {
+    let mut start = reader.seek(SeekFrom::End(0))?;
+    let mut bytes = Vec::new();
+    let mut newlines = 0;
+    // One newline more than `n` marks where the first wanted line begins
+    while start > 0 && newlines <= n {
+        let from = start.saturating_sub(TRANSCRIPT_TAIL_BYTES);
+        let mut chunk = vec![0; (start - from) as usize];
+        reader.seek(SeekFrom::Start(from))?;
+        reader.read_exact(&mut chunk)?;
+        newlines += chunk.iter().filter(|&&b| b == b'\n').count();
+        chunk.extend_from_slice(&bytes);
+        bytes = chunk;
+        start = from;
+    }
+
+    let text = String::from_utf8_lossy(&bytes);
+    let mut lines: Vec<&str> = text.lines().collect();
+    // Stopped mid-file: the first line is a fragment
+    if start > 0 && !lines.is_empty() {
+        lines.remove(0);
+    }
+    let skip = lines.len().saturating_sub(n);
+    Ok(lines[skip..].iter().map(|l| l.to_string()).collect())
+}
+
+/// Intents from a chunk of transcript JSONL. A line cut off by a partial
+/// read fails to parse and is skipped like any other.
+pub fn parse_intents(jsonl: &str) -> Vec<Intent> {
+    let texts: Vec<String> = jsonl.lines().filter_map(user_text).collect();
+
+    texts
+        .iter()
+        .rev()
+        .take(TRANSCRIPT_RECENT_TURNS)
+        .filter_map(|text| intent(text))
+        .collect()
+}
+
+/// What the user typed in a transcript entry, if it is a user turn
+fn user_text(line: &str) -> Option<String> {
+    let entry: Value = serde_json::from_str(line).ok()?;
+    if entry.get("type")?.as_str()? != "user" {
+        return None;
+    }
+
+    match entry.get("message")?.get("content")? {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(blocks) => {
+            let text: Vec<&str> = blocks
+                .iter()
+                .filter(|b| b.get("type").and_then(Value::as_str) == Some("text"))
+                .filter_map(|b| b.get("text")?.as_str())
+                .collect();
+            (!text.is_empty()).then(|| text.join("\n"))
+        }
+        _ => None,
+    }
+}
+
+fn intent(text: &str) -> Option<Intent> {
+    let lower = text.to_lowercase().replace('\u{2019}', "'");
+    let asked = OVERWRITE_PHRASES.iter().any(|phrase| {
+        lower
+            .match_indices(phrase)
+            .any(|(at, _)| !negated(clause_before(&lower[..at])))
+    });
+    if !asked {
+        return None;
+    }
+
+    let mentions = text
+        .split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| matches!(c, '`' | '"' | '\'' | ',' | ';' | ':' | '(' | ')'))
+                .trim_end_matches(['.', '!', '?'])
+        })
+        .filter(|word| word.contains('.') || word.contains('/'))
+        .map(String::from)
+        .collect();
+
+    Some(Intent::Overwrite { mentions })
+}
+
+/// The end of `text` back to the start of its clause
+fn clause_before(text: &str) -> &str {
+    let start = text
+        .char_indices()
+        .rev()
+        .find(|&(i, c)| {
+            matches!(c, ',' | ';' | ':' | '!' | '?' | '\n')
+                // A full stop ends a sentence; one inside a file name doesn't
+                || (c == '.' && text[i + 1..].starts_with(char::is_whitespace))
+        })
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let clause = &text[start..];
+    clause.rsplit_once(" but ").map_or(clause, |(_, rest)| rest)
+}
+
+/// Whether a clause says not to: "don't", "do not", "never", "no",
+/// "without"
+fn negated(clause: &str) -> bool {
+    let words: Vec<&str> = clause
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric() && c != '\''))
+        .collect();
+    words
+        .iter()
+        .any(|w| matches!(*w, "don't" | "dont" | "never" | "no" | "without"))
+        || words.windows(2).any(|pair| pair == ["do", "not"])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_overwrite_request_from_recent_user_turns() {
+        let jsonl = [
+            r#"":"cut off by the tail read"}"#,
+            r#"{"type":"user","message":{"role":"user","content":"Refactor `src/lib.rs`, overwrite it."}}"#,
+            r#"{"type":"assistant","message":{"role":"assistant","content":[{"type":"text","text":"Overwrite src/main.rs?"}]}}"#,
+            r#"{"type":"user","message":{"role":"user","content":[{"type":"tool_result","content":"overwrite a.rs"}]}}"#,
+            r#"{"type":"user","message":{"role":"user","content":[{"type":"text","text":"yes, go ahead"}]}}"#,
+        ]
+        .join("\n");
+
+        let intents = parse_intents(&jsonl);
+        assert_eq!(
+            intents,
+            [Intent::Overwrite {
+                mentions: vec!["src/lib.rs".to_string()]
+            }]
+        );
+        assert!(intents[0].permits_overwrite("/p/src/lib.rs"));
+        assert!(!intents[0].permits_overwrite("/p/src/main.rs"));
+        assert!(!intents[0].permits_overwrite("/p/src/mylib.rs"));
+    }
+
+    #[test]
+    fn negated_overwrite_is_no_request() {
+        let mentions = |text: &str| intent(text).map(|Intent::Overwrite { mentions }| mentions);
+
+        for text in [
+            "don't overwrite src/lib.rs",
+            "Do not overwrite src/lib.rs!",
+            "never overwrite src/lib.rs",
+            "Don\u{2019}t discard my changes in src/lib.rs",
+            "fix src/lib.rs without overwriting it",
+            "no need to overwrite src/lib.rs",
+        ] {
+            assert_eq!(mentions(text), None, "{text}");
+        }
+
+        assert_eq!(
+            mentions("overwrite src/lib.rs"),
+            Some(vec!["src/lib.rs".to_string()])
+        );
+        // The negation belongs to another clause or sentence
+        assert_eq!(
+            mentions("Don't worry about src/lib.rs, just overwrite it"),
+            Some(vec!["src/lib.rs".to_string()])
+        );
+        assert_eq!(
+            mentions("No tests yet. Overwrite src/lib.rs"),
+            Some(vec!["src/lib.rs".to_string()])
+        );
+    }
+
+    /// Counts the bytes read through it
+    struct Counting<R> {
+        inner: R,
+        read: u64,
+    }
+
+    impl<R: Read> Read for Counting<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl<R: Seek> Seek for Counting<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn tail_lines_reads_only_the_end_of_a_large_transcript() {
+        let jsonl: String = (0..100_000)
+            .map(|i| format!("{{\"type\":\"assistant\",\"n\":{}}}\n", i))
+            .collect();
+        let mut reader = Counting {
+            inner: std::io::Cursor::new(jsonl.as_bytes()),
+            read: 0,
+        };
+
+        let lines = tail_lines_of(&mut reader, 3).unwrap();
+        assert_eq!(
+            lines,
+            [
+                r#"{"type":"assistant","n":99997}"#,
+                r#"{"type":"assistant","n":99998}"#,
+                r#"{"type":"assistant","n":99999}"#,
+            ]
+        );
+        assert!(reader.read <= TRANSCRIPT_TAIL_BYTES);
+        assert!((reader.read as usize) < jsonl.len() / 10);
+
+        // Asking for more lines than there are returns the whole file
+        let mut short = std::io::Cursor::new(b"a\nb\n".to_vec());
+        assert_eq!(tail_lines_of(&mut short, 10).unwrap(), ["a", "b"]);
+    }
+}
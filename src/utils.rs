@@ -14,6 +14,32 @@
 //! - Easy discovery of all instances for a directory (glob pattern)
 //! - No socket conflicts between different directories
 //!
+//! When `SIDEKICK_SOCKET_SALT` is set it is mixed into the hash, so users who
+//! share `/tmp` and the same project path (e.g. CI containers bind-mounting
+//! to one location) can keep their instances apart. The launcher and the
+//! hook must see the same salt to find each other.
+//!
+//! Sockets that refused a connection are remembered for a short while in
+//! `sidekick-dead-sockets.json` in the user's private `state_dir()`, where
+//! nobody else can plant entries. With a dead-socket TTL
+//! configured, discovery skips those, so a burst of hooks pays for a stale
+//! socket once instead of on every invocation.
+//!
+//! With `SIDEKICK_READABLE_SOCKETS=1` the launcher puts the project's
+//! directory name in front, `/tmp/myproject-<hash16>-<pid>.sock`, keeping
+//! the first 16 hex digits of the hash. Discovery matches on the hash either
+//! way, so only the launcher needs the setting.
+//!
+//! With `SIDEKICK_SOCKET_DIR=<dir>` Neovim sockets go in a per-user
+//! `<dir>/<uid>/` instead of `/tmp`, created `0700` by the launcher, so
+//! other users can neither list which projects you have open nor connect.
+//! Launcher and hook must both see it, like the salt.
+//!
+//! With `SIDEKICK_USE_LOGICAL_CWD=1` the hashed path is the logical one, as
+//! the shell's `$PWD` spells it, instead of the canonicalized one. Through
+//! a bind mount or a symlinked checkout, `canonicalize` may land on a path
+//! the launcher and the hook don't agree on; `$PWD` is what the user typed.
+//!
 //! # Example
 //!
 //! ```no_run
@@ -30,37 +56,629 @@
 //! ```
 
 use anyhow::Context;
+use chrono::{DateTime, Utc};
+use std::cell::Cell;
+use std::collections::BTreeMap;
 use std::env;
-use std::path::PathBuf;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::constants::{
+    DEAD_SOCKET_CACHE_MAX_AGE, JETBRAINS_TIMEOUT, READABLE_SOCKET_HASH_LEN,
+    READABLE_SOCKET_PREFIX_MAX, rpc_timeout,
+};
 
 /// Compute socket path based on current working directory hash and process ID
 pub fn compute_socket_path_with_pid(pid: u32) -> anyhow::Result<PathBuf> {
     let cwd = env::current_dir().context("couldn't read current directory")?;
-    let cwd_absolute = cwd
-        .canonicalize()
-        .context("couldn't resolve current directory")?;
+    compute_socket_path_for(&cwd, pid)
+}
 
-    let hash = blake3::hash(cwd_absolute.to_string_lossy().as_bytes());
-    let hash_hex = hash.to_hex();
+/// Directory sockets live in. Sidekick's short-lived per-session cache sits
+/// alongside them, so it's cleaned up with the rest of the temp dir.
+pub fn socket_dir() -> PathBuf {
+    PathBuf::from("/tmp")
+}
+
+/// Directory Neovim sockets live in: `socket_dir()`, or the current user's
+/// subdirectory of `SIDEKICK_SOCKET_DIR` when that is set
+pub fn nvim_socket_dir() -> PathBuf {
+    match env::var_os("SIDEKICK_SOCKET_DIR").filter(|d| !d.is_empty()) {
+        Some(base) => user_dir(Path::new(&base)),
+        None => socket_dir(),
+    }
+}
+
+/// `base/<uid>`, the current user's own directory under a shared one
+pub fn user_dir(base: &Path) -> PathBuf {
+    // SAFETY: getuid has no preconditions and can't fail
+    base.join(unsafe { libc::getuid() }.to_string())
+}
+
+/// Sidekick's own per-user state, private to the user: the
+/// `SIDEKICK_SOCKET_DIR` socket dir when set, else
+/// `$XDG_RUNTIME_DIR/sidekick`, else `/tmp/sidekick-<uid>`. Created `0700`
+/// if missing; fails if someone else got there first.
+pub fn state_dir() -> anyhow::Result<PathBuf> {
+    let dir = state_dir_path();
+    create_private_dir(&dir)?;
+    Ok(dir)
+}
 
-    Ok(PathBuf::from(format!("/tmp/{}-{}.sock", hash_hex, pid)))
+/// Where `state_dir` is, without creating or checking it
+pub fn state_dir_path() -> PathBuf {
+    if env::var_os("SIDEKICK_SOCKET_DIR").is_some_and(|d| !d.is_empty()) {
+        nvim_socket_dir()
+    } else if let Some(runtime) = env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        PathBuf::from(runtime).join("sidekick")
+    } else {
+        // SAFETY: getuid has no preconditions and can't fail
+        socket_dir().join(format!("sidekick-{}", unsafe { libc::getuid() }))
+    }
+}
+
+/// Create the per-user socket dir before launching, when
+/// `SIDEKICK_SOCKET_DIR` asks for one. `/tmp` is left as it is.
+pub fn prepare_nvim_socket_dir() -> anyhow::Result<()> {
+    let dir = nvim_socket_dir();
+    if dir == socket_dir() {
+        return Ok(());
+    }
+    create_private_dir(&dir)
+}
+
+/// Create `dir` as `0700`, along with any missing parents, also `0700`.
+/// Existing parents are left as they are. An existing `dir` must be a
+/// directory the current user owns, and is tightened to `0700` if it is
+/// looser.
+pub fn create_private_dir(dir: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = dir.parent().filter(|p| !p.exists()) {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)
+            .with_context(|| format!("couldn't create {}", parent.display()))?;
+    }
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e).with_context(|| format!("couldn't create {}", dir.display())),
+    }
+
+    let meta = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("couldn't read {}", dir.display()))?;
+    // SAFETY: getuid has no preconditions and can't fail
+    if !meta.is_dir() || meta.uid() != unsafe { libc::getuid() } {
+        anyhow::bail!(
+            "{} isn't a directory of yours, not putting sockets in it",
+            dir.display()
+        );
+    }
+    if meta.mode() & 0o777 != 0o700 {
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("couldn't set permissions on {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Compute socket path for an explicit project directory and process ID,
+/// readable under `SIDEKICK_READABLE_SOCKETS`
+pub fn compute_socket_path_for(dir: &Path, pid: u32) -> anyhow::Result<PathBuf> {
+    if crate::config::flag(env::var("SIDEKICK_READABLE_SOCKETS").ok()).unwrap_or(false) {
+        return compute_readable_socket_path_for(dir, pid);
+    }
+    let hash_hex = dir_hash(dir)?;
+    Ok(nvim_socket_dir().join(format!("{}-{}.sock", hash_hex, pid)))
+}
+
+/// Socket path led by the project's directory name:
+/// `<name>-<hash16>-<pid>.sock`
+pub fn compute_readable_socket_path_for(dir: &Path, pid: u32) -> anyhow::Result<PathBuf> {
+    let hash_hex = dir_hash(dir)?;
+    let name: String = resolve_dir(dir)
+        .ok()
+        .and_then(|dir| Some(dir.file_name()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "root".to_string())
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(READABLE_SOCKET_PREFIX_MAX)
+        .collect();
+    Ok(nvim_socket_dir().join(format!(
+        "{}-{}-{}.sock",
+        name,
+        &hash_hex[..READABLE_SOCKET_HASH_LEN],
+        pid
+    )))
 }
 
 /// Find all socket paths matching the current working directory hash
 pub fn find_matching_sockets() -> anyhow::Result<Vec<PathBuf>> {
     let cwd = env::current_dir().context("couldn't read current directory")?;
-    let cwd_absolute = cwd
-        .canonicalize()
-        .context("couldn't resolve current directory")?;
+    find_matching_sockets_for(&cwd)
+}
 
-    let hash = blake3::hash(cwd_absolute.to_string_lossy().as_bytes());
-    let hash_hex = hash.to_hex();
+/// Find all socket paths matching an explicit project directory's hash.
+/// Only actual sockets count: a stray file that happens to match the
+/// pattern would just fail to connect.
+pub fn find_matching_sockets_for(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    find_matching_sockets_up_to(dir, usize::MAX, None)
+}
 
-    let pattern = format!("/tmp/{}-*.sock", hash_hex);
+/// Like `find_matching_sockets_for`, but stop after the first `max`, in
+/// pid order, leaving out sockets that failed within `dead_ttl`. Only names
+/// carrying the directory's hash, plain or readable, are stat'ed, so a
+/// socket dir crowded with other projects' files stays cheap.
+pub fn find_matching_sockets_up_to(
+    dir: &Path,
+    max: usize,
+    dead_ttl: Option<Duration>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let hash = dir_hash(dir)?;
+    let socket_dir = nvim_socket_dir();
+    let mut names: Vec<_> = std::fs::read_dir(&socket_dir)
+        .context("couldn't search for Neovim sockets")?
+        .filter_map(Result::ok)
+        .map(|entry| entry.file_name())
+        .filter(|name| {
+            name.to_str()
+                .and_then(socket_name_hash)
+                .is_some_and(|h| hash.starts_with(h))
+        })
+        .collect();
+    // Oldest instance first, whatever order the directory lists them in, so
+    // which instance answers first is the same from one hook to the next
+    names.sort_by_key(|name| (name.to_str().and_then(socket_pid), name.clone()));
 
-    Ok(glob::glob(&pattern)
+    let dead = match dead_ttl {
+        Some(ttl) => DeadSockets::load().failed_within(ttl, Utc::now()),
+        None => Vec::new(),
+    };
+    Ok(names
+        .into_iter()
+        .map(|name| socket_dir.join(name))
+        .filter(|path| !dead.contains(path) && is_socket(path))
+        .take(max)
+        .collect())
+}
+
+/// First `READABLE_SOCKET_HASH_LEN` hex digits of the blake3 digest naming
+/// a project directory's sockets, which plain and readable names both carry
+pub type DirHash = String;
+
+/// Every sidekick socket in the socket dir, whatever project it's for, as
+/// `(hash, path)` sorted so each project's sockets sit together, plain and
+/// readable alike. For a process serving several repos at once.
+pub fn find_all_sockets() -> anyhow::Result<Vec<(DirHash, PathBuf)>> {
+    let mut sockets: Vec<_> = std::fs::read_dir(nvim_socket_dir())
         .context("couldn't search for Neovim sockets")?
         .filter_map(Result::ok)
-        .filter(|path| path.exists())
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let hash = socket_name_hash(name.to_str()?)?;
+            Some((hash[..READABLE_SOCKET_HASH_LEN].to_string(), entry.path()))
+        })
+        .filter(|(_, path)| is_socket(path))
+        .collect();
+    sockets.sort();
+    Ok(sockets)
+}
+
+/// The hash in a `<hash>-<pid>.sock` or readable `<name>-<hash16>-<pid>.sock`
+/// name, `None` for anything else
+fn socket_name_hash(name: &str) -> Option<&str> {
+    let (head, pid) = name.strip_suffix(".sock")?.rsplit_once('-')?;
+    if pid.is_empty() || !pid.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let is_hash = |s: &str, len| s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit());
+    if is_hash(head, 64) {
+        return Some(head);
+    }
+    let (_, short) = head.rsplit_once('-')?;
+    is_hash(short, READABLE_SOCKET_HASH_LEN).then_some(short)
+}
+
+/// Whether refused sockets are remembered, see `track_dead_sockets`
+static DEAD_SOCKET_TRACKING: AtomicBool = AtomicBool::new(false);
+
+/// Remember refused sockets from here on. Only worth it with a dead-socket
+/// TTL configured: nothing reads the cache otherwise.
+pub fn track_dead_sockets(on: bool) {
+    DEAD_SOCKET_TRACKING.store(on, Ordering::Relaxed);
+}
+
+thread_local! {
+    /// When the hook answer this thread is working out is due, if ever
+    static ANSWER_DUE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Say the hook answer worked out on this thread is due at `at`. Past it the
+/// host has already been told to allow, see `answer_overdue`.
+pub fn set_answer_due(at: Instant) {
+    ANSWER_DUE.with(|due| due.set(Some(at)));
+}
+
+/// Whether this thread's hook answer is past due. Its side effects, session
+/// state, analytics and editor messages, are dropped then: they'd describe
+/// a decision the host never got.
+pub fn answer_overdue() -> bool {
+    ANSWER_DUE.with(|due| due.get().is_some_and(|at| Instant::now() >= at))
+}
+
+/// Remember that `socket` refused a connection just now, when
+/// `track_dead_sockets` is on. Best-effort, like the session cache: a
+/// failed write is dropped.
+pub fn record_dead_socket(socket: &Path) {
+    if !DEAD_SOCKET_TRACKING.load(Ordering::Relaxed) {
+        return;
+    }
+    let now = Utc::now();
+    let mut cache = DeadSockets::load();
+    let max_age = chrono::Duration::from_std(DEAD_SOCKET_CACHE_MAX_AGE).unwrap_or_default();
+    cache.0.retain(|_, at| now - *at < max_age);
+    cache.0.insert(socket.to_path_buf(), now);
+    if let (Some(path), Ok(bytes)) = (DeadSockets::path(), serde_json::to_vec(&cache.0)) {
+        let _ = write_atomically(&path, &bytes);
+    }
+}
+
+/// Replace `path` with `bytes` all at once: written to a fresh `0600` file
+/// beside it, then renamed over it. Readers see the old content or the new,
+/// never half of it, and a symlink planted at `path` is replaced, not
+/// followed.
+pub fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    let tmp = path.with_file_name(name);
+    let _ = std::fs::remove_file(&tmp);
+
+    let written = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)
+        .and_then(|mut file| file.write_all(bytes));
+    match written.and_then(|()| std::fs::rename(&tmp, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// When each recently dead socket last failed
+#[derive(Default)]
+struct DeadSockets(BTreeMap<PathBuf, DateTime<Utc>>);
+
+impl DeadSockets {
+    fn path() -> Option<PathBuf> {
+        Some(state_dir().ok()?.join("sidekick-dead-sockets.json"))
+    }
+
+    fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .map(Self)
+            .unwrap_or_default()
+    }
+
+    fn failed_within(self, ttl: Duration, now: DateTime<Utc>) -> Vec<PathBuf> {
+        let ttl = chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX);
+        self.0
+            .into_iter()
+            .filter(|(_, at)| now - *at < ttl)
+            .map(|(path, _)| path)
+            .collect()
+    }
+}
+
+fn is_socket(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket())
+}
+
+/// Directory Kakoune keeps its session sockets in: `$XDG_RUNTIME_DIR/kakoune`,
+/// else `$TMPDIR/kakoune-$USER` — the same lookup `kak` itself does
+pub fn kak_socket_dir() -> PathBuf {
+    if let Some(runtime) = env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        return PathBuf::from(runtime).join("kakoune");
+    }
+    let tmp = env::var_os("TMPDIR")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(socket_dir);
+    let user = env::var("USER").unwrap_or_default();
+    tmp.join(format!("kakoune-{}", user))
+}
+
+/// Kakoune session name for a project directory and process ID. Same
+/// `<hash>-<pid>` shape as the Neovim socket so both are found the same way.
+pub fn compute_kak_session_name(dir: &Path, pid: u32) -> anyhow::Result<String> {
+    Ok(format!("{}-{}", dir_hash(dir)?, pid))
+}
+
+/// Find all Kakoune sessions launched for an explicit project directory
+pub fn find_matching_kak_sessions_for(dir: &Path) -> anyhow::Result<Vec<String>> {
+    let hash_hex = dir_hash(dir)?;
+    let pattern = kak_socket_dir().join(format!("{}-*", hash_hex));
+
+    Ok(glob::glob(&pattern.to_string_lossy())
+        .context("couldn't search for Kakoune sessions")?
+        .filter_map(Result::ok)
+        .filter_map(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
         .collect())
 }
+
+/// Ports of the JetBrains IDEs that registered an open project for an
+/// explicit project directory. The companion plugin writes the port to
+/// `<hash>-<pid>.jetbrains` next to the Neovim sockets.
+pub fn find_matching_jetbrains_ports_for(dir: &Path) -> anyhow::Result<Vec<u16>> {
+    let hash_hex = dir_hash(dir)?;
+    let pattern = socket_dir().join(format!("{}-*.jetbrains", hash_hex));
+
+    Ok(glob::glob(&pattern.to_string_lossy())
+        .context("couldn't search for JetBrains IDEs")?
+        .filter_map(Result::ok)
+        .filter_map(|path| live_jetbrains_port(&path))
+        .collect())
+}
+
+/// The port in a `.jetbrains` file, unless the IDE behind it is gone. A
+/// crashed IDE never removes its file, so one whose pid has exited or
+/// whose port refuses the connection is deleted rather than probed on
+/// every hook.
+pub fn live_jetbrains_port(path: &Path) -> Option<u16> {
+    let port: u16 = std::fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    let pid = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(socket_pid);
+    let exited = pid.is_some_and(|pid| !process_alive(pid));
+    let refused = || {
+        let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+        std::net::TcpStream::connect_timeout(&addr, rpc_timeout(JETBRAINS_TIMEOUT))
+            .is_err_and(|e| e.kind() == std::io::ErrorKind::ConnectionRefused)
+    };
+    if exited || refused() {
+        let _ = std::fs::remove_file(path);
+        return None;
+    }
+    Some(port)
+}
+
+/// Which editor an instance is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorKind {
+    Neovim,
+    Kakoune,
+    JetBrains,
+}
+
+/// An editor instance found for a project, the structured counterpart to
+/// the raw paths the `find_matching_*` functions return
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceInfo {
+    /// Neovim socket, Kakoune session socket, or JetBrains port file
+    pub socket: PathBuf,
+    /// Process that launched it, from the `<hash>-<pid>` name
+    pub pid: u32,
+    pub editor: EditorKind,
+    /// Whether that process still exists; a dead one left a stale socket
+    pub alive: bool,
+}
+
+/// Every editor instance launched for the current working directory
+pub fn discover_instances() -> anyhow::Result<Vec<InstanceInfo>> {
+    let cwd = env::current_dir().context("couldn't read current directory")?;
+    discover_instances_for(&cwd)
+}
+
+/// Every editor instance launched for an explicit project directory:
+/// Neovim first, then Kakoune, then JetBrains. Names without a pid are
+/// skipped.
+pub fn discover_instances_for(dir: &Path) -> anyhow::Result<Vec<InstanceInfo>> {
+    let neovim = find_matching_sockets_for(dir)?
+        .into_iter()
+        .map(|socket| (socket, EditorKind::Neovim));
+    let kakoune = find_matching_kak_sessions_for(dir)?
+        .into_iter()
+        .map(|session| (kak_socket_dir().join(session), EditorKind::Kakoune));
+    let pattern = socket_dir().join(format!("{}-*.jetbrains", dir_hash(dir)?));
+    let jetbrains = glob::glob(&pattern.to_string_lossy())
+        .context("couldn't search for JetBrains IDEs")?
+        .filter_map(Result::ok)
+        .map(|path| (path, EditorKind::JetBrains));
+
+    Ok(neovim
+        .chain(kakoune)
+        .chain(jetbrains)
+        .filter_map(|(socket, editor)| {
+            let pid = socket_pid(socket.file_name()?.to_str()?)?;
+            Some(InstanceInfo {
+                alive: process_alive(pid),
+                socket,
+                pid,
+                editor,
+            })
+        })
+        .collect())
+}
+
+/// The pid in a `<hash>-<pid>` name, with or without its extension
+pub fn socket_pid(name: &str) -> Option<u32> {
+    let stem = name
+        .strip_suffix(".sock")
+        .or_else(|| name.strip_suffix(".jetbrains"))
+        .unwrap_or(name);
+    stem.rsplit_once('-')?.1.parse().ok()
+}
+
+/// Whether a process with `pid` exists, asked with signal 0. One we may not
+/// signal, another user's, still exists.
+pub fn process_alive(pid: u32) -> bool {
+    // 0 and negative pids name process groups, not a process
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid == 0 {
+        return false;
+    }
+    let signalled = unsafe { libc::kill(pid, 0) };
+    signalled == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Directory hash with the salt from `SIDEKICK_SOCKET_SALT`, if any, of the
+/// logical path under `SIDEKICK_USE_LOGICAL_CWD`
+fn dir_hash(dir: &Path) -> anyhow::Result<String> {
+    let salt = env::var("SIDEKICK_SOCKET_SALT").unwrap_or_default();
+    if !crate::config::flag(env::var("SIDEKICK_USE_LOGICAL_CWD").ok()).unwrap_or(false) {
+        return salted_dir_hash(dir, &salt);
+    }
+    let pwd = env::var_os("PWD").map(PathBuf::from);
+    Ok(path_hash(&logical_path(dir, pwd.as_deref())?, &salt))
+}
+
+/// blake3 hex digest of the canonicalized directory path, namespaced by
+/// `salt`. An empty salt gives the plain, unsalted digest.
+pub fn salted_dir_hash(dir: &Path, salt: &str) -> anyhow::Result<String> {
+    Ok(path_hash(&resolve_dir(dir)?, salt))
+}
+
+fn path_hash(path: &Path, salt: &str) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(path.to_string_lossy().as_bytes());
+    if !salt.is_empty() {
+        hasher.update(b"\0");
+        hasher.update(salt.as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// `dir` as the shell would spell it: `pwd` when that's the same directory
+/// (the process cwd has its symlinks resolved, `$PWD` doesn't), otherwise
+/// `dir` made absolute against `pwd` without resolving symlinks. `.` and
+/// `..` are folded lexically, as `cd` does.
+pub fn logical_path(dir: &Path, pwd: Option<&Path>) -> anyhow::Result<PathBuf> {
+    let pwd = pwd.filter(|p| p.is_absolute());
+    if let Some(pwd) = pwd
+        && same_dir(dir, pwd)
+    {
+        return Ok(normalize_lexically(pwd));
+    }
+    if dir.is_absolute() {
+        return Ok(normalize_lexically(dir));
+    }
+    let base = match pwd {
+        Some(pwd) => pwd.to_path_buf(),
+        None => env::current_dir().context("couldn't read current directory")?,
+    };
+    Ok(normalize_lexically(&base.join(dir)))
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Whether Vim or Neovim keeps a swap file for `path`, a sign that some
+/// editor, maybe one that has since died, holds changes to it
+pub fn has_swapfile(path: &Path) -> bool {
+    has_swapfile_in(path, nvim_swap_dir().as_deref())
+}
+
+/// `has_swapfile`, with Neovim's swap directory given. Vim's default is
+/// `.<name>.swp` beside the file, then `.swo`, `.swn` and on down to `.swa`
+/// while the earlier ones are taken. Neovim keeps them in `swap_dir`, named
+/// for the full path with `/` spelled `%`.
+pub fn has_swapfile_in(path: &Path, swap_dir: Option<&Path>) -> bool {
+    let path = normalize_path(path);
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+        return false;
+    };
+    let suffixes = || ('a'..='p').rev().map(|c| format!(".sw{}", c));
+
+    let beside = path.parent().is_some_and(|dir| {
+        suffixes().any(|suffix| dir.join(format!(".{}{}", name, suffix)).exists())
+    });
+    let in_swap_dir = swap_dir.is_some_and(|dir| {
+        let flat = path.to_string_lossy().replace('/', "%");
+        suffixes().any(|suffix| dir.join(format!("{}{}", flat, suffix)).exists())
+    });
+    beside || in_swap_dir
+}
+
+/// Neovim's default swap directory: `$XDG_STATE_HOME/nvim/swap`, with
+/// `NVIM_APPNAME` in place of `nvim` when set
+fn nvim_swap_dir() -> Option<PathBuf> {
+    let state = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| Some(dirs::home_dir()?.join(".local").join("state")))?;
+    let app = env::var("NVIM_APPNAME").unwrap_or_else(|_| "nvim".to_string());
+    Some(state.join(app).join("swap"))
+}
+
+/// `file_path` resolved to the absolute, symlink-free spelling editors
+/// report, for matching against their buffer names. Left as given when it
+/// doesn't exist.
+pub fn canonical(file_path: &str) -> String {
+    Path::new(file_path)
+        .canonicalize()
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| file_path.to_string())
+}
+
+/// `path` with symlinks resolved where it exists. A path that doesn't yet,
+/// such as a file the AI is about to write, has its `.` and `..` folded
+/// lexically instead, so `./src/../src/foo.rs` still reads as `src/foo.rs`.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .unwrap_or_else(|_| normalize_lexically(path))
+}
+
+/// `path` with `.` and `..` folded without touching the filesystem. `..`
+/// at the root stays at the root; leading `..` of a relative path are kept.
+pub fn normalize_lexically(path: &Path) -> PathBuf {
+    use std::path::Component;
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match out.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => out.push(".."),
+            },
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// `dir` resolved for hashing: canonical where it exists, else made
+/// absolute and folded lexically
+fn resolve_dir(dir: &Path) -> anyhow::Result<PathBuf> {
+    if let Ok(dir) = dir.canonicalize() {
+        return Ok(dir);
+    }
+    let absolute =
+        std::path::absolute(dir).with_context(|| format!("couldn't resolve {}", dir.display()))?;
+    Ok(normalize_lexically(&absolute))
+}
@@ -0,0 +1,85 @@
+//! Unit tests for layered configuration
+
+use sidekick::config::{Config, PostAction, PreAction, UnsavedPolicy, Verbosity};
+
+fn config_with(vars: &[(&str, &str)]) -> Config {
+    layered(vars, None, None)
+}
+
+fn layered(vars: &[(&str, &str)], project: Option<&str>, user: Option<&str>) -> Config {
+    Config::from_sources(
+        |name| {
+            vars.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        },
+        project,
+        user,
+        Verbosity::default(),
+    )
+}
+
+#[test]
+fn test_defaults_when_unset() {
+    let config = config_with(&[]);
+    assert!(!config.disable_refresh);
+    assert_eq!(config.unsaved, UnsavedPolicy::Current);
+    assert!(config.guards("Edit") && config.guards("Write") && config.guards("MultiEdit"));
+}
+
+#[test]
+fn test_disable_refresh_flag_values() {
+    for on in ["1", "true", "YES", "on"] {
+        assert!(config_with(&[("SIDEKICK_DISABLE_REFRESH", on)]).disable_refresh);
+    }
+    for off in ["0", "false", ""] {
+        assert!(!config_with(&[("SIDEKICK_DISABLE_REFRESH", off)]).disable_refresh);
+    }
+}
+
+#[test]
+fn test_precedence_env_over_project_over_user() {
+    let user = r#"
+        unsaved = "any"
+        deny_message = "user message"
+        notify_limit = 3
+    "#;
+    let project = r#"
+        guarded_tools = ["Edit"]
+        deny_message = "project message"
+    "#;
+    let env = [("SIDEKICK_GUARDED_TOOLS", "Write")];
+
+    let config = layered(&env, Some(project), Some(user));
+    // Env beats project
+    assert!(config.guards("Write"));
+    assert!(!config.guards("Edit"));
+    // Project beats user
+    assert_eq!(config.deny_message.as_deref(), Some("project message"));
+    // User fills what nobody above set
+    assert_eq!(config.unsaved, UnsavedPolicy::Any);
+    assert_eq!(config.notify_limit, Some(3));
+    // Default for what nobody set
+    assert!(!config.disable_refresh);
+}
+
+#[test]
+fn test_invalid_file_is_skipped() {
+    let config = layered(&[], Some("unsaved = ["), Some("unsaved = \"any\""));
+    assert_eq!(config.unsaved, UnsavedPolicy::Any);
+}
+
+#[test]
+fn test_tool_actions_from_env() {
+    let config = config_with(&[(
+        "SIDEKICK_TOOLS",
+        "Edit:block, write:ignore:refresh, MultiEdit::ignore, Bad:nope",
+    )]);
+    assert_eq!(config.pre_action("Edit"), PreAction::Block);
+    assert_eq!(config.pre_action("Write"), PreAction::Ignore);
+    assert_eq!(config.post_action("Write"), PostAction::Refresh);
+    // Only post set: pre follows guarded_tools
+    assert_eq!(config.pre_action("MultiEdit"), PreAction::Block);
+    assert_eq!(config.post_action("MultiEdit"), PostAction::Ignore);
+    assert!(!config.tools.contains_key("Bad"));
+}
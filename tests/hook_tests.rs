@@ -1,6 +1,8 @@
 //! Integration tests for hook processing
 
-use sidekick::hook::{Hook, HookEvent, HookOutput, PermissionDecision, Tool, parse_hook};
+use sidekick::hook::{
+    BashToolInput, Hook, HookEvent, HookOutput, OutputFormat, PermissionDecision, Tool, parse_hook,
+};
 
 #[test]
 fn test_parse_pre_tool_use_edit_hook() {
@@ -26,10 +28,11 @@ fn test_parse_pre_tool_use_edit_hook() {
     assert_eq!(h.session_id, "test-session");
     assert_eq!(h.cwd, "/test/dir");
     assert_eq!(h.hook_event_name, HookEvent::PreToolUse);
+    assert_eq!(h.permission_mode, None);
 
     match h.tool {
         Tool::Edit(input) => {
-            assert_eq!(input.file_path, "test.txt");
+            assert_eq!(input.file_path.as_deref(), Some("test.txt"));
             assert_eq!(input.old_string, Some("old".to_string()));
             assert_eq!(input.new_string, Some("new".to_string()));
         }
@@ -61,13 +64,33 @@ fn test_parse_post_tool_use_write_hook() {
 
     match h.tool {
         Tool::Write(input) => {
-            assert_eq!(input.file_path, "test.txt");
+            assert_eq!(input.file_path.as_deref(), Some("test.txt"));
             assert_eq!(input.content, Some("file content".to_string()));
         }
         _ => panic!("Expected Write tool"),
     }
 }
 
+#[test]
+fn test_parse_hook_with_permission_mode() {
+    let json = r#"{
+        "session_id": "test-session",
+        "transcript_path": "/tmp/transcript",
+        "cwd": "/test/dir",
+        "permission_mode": "bypassPermissions",
+        "hook_event_name": "PreToolUse",
+        "tool_name": "Write",
+        "tool_input": {"file_path": "test.txt", "content": "x"}
+    }"#;
+
+    let Hook::Tool(h) = parse_hook(json).expect("Failed to parse hook") else {
+        panic!("Expected Tool hook");
+    };
+
+    assert_eq!(h.permission_mode.as_deref(), Some("bypassPermissions"));
+    assert!(h.bypasses_permissions());
+}
+
 #[test]
 fn test_parse_bash_hook() {
     let json = r#"{
@@ -97,6 +120,30 @@ fn test_parse_bash_hook() {
     }
 }
 
+#[test]
+fn test_bash_delete_targets() {
+    let targets = |command: &str| {
+        BashToolInput {
+            command: command.to_string(),
+            description: String::new(),
+        }
+        .delete_targets()
+    };
+
+    assert_eq!(targets("rm foo.rs"), ["foo.rs"]);
+    assert_eq!(
+        targets("rm -f -- -odd.rs src/a.rs"),
+        ["-odd.rs", "src/a.rs"]
+    );
+    assert_eq!(targets("git rm --cached foo.rs"), ["foo.rs"]);
+    assert_eq!(targets("cargo fmt && mv a.rs b.rs"), ["a.rs", "b.rs"]);
+    // Too complex to know: left alone
+    assert!(targets("rm *.rs").is_empty());
+    assert!(targets("rm \"$FILE\"").is_empty());
+    assert!(targets("find . -name x | xargs rm").is_empty());
+    assert!(targets("ls -la").is_empty());
+}
+
 #[test]
 fn test_hook_output_allow() {
     let output = HookOutput::new();
@@ -119,6 +166,61 @@ fn test_hook_output_deny() {
     assert!(json.contains("\"hookEventName\":\"PreToolUse\""));
 }
 
+#[test]
+fn test_hook_output_constructors_have_exact_shapes() {
+    let shape = |output: HookOutput| {
+        serde_json::from_str::<serde_json::Value>(&output.to_json().unwrap()).unwrap()
+    };
+
+    assert_eq!(
+        shape(HookOutput::allow()),
+        serde_json::json!({"hookSpecificOutput": {
+            "hookEventName": "PreToolUse", "permissionDecision": "allow"}})
+    );
+    assert_eq!(
+        shape(HookOutput::deny("dirty")),
+        serde_json::json!({"hookSpecificOutput": {
+            "hookEventName": "PreToolUse", "permissionDecision": "deny",
+            "permissionDecisionReason": "dirty"}})
+    );
+    assert_eq!(
+        shape(HookOutput::ask("sure?")),
+        serde_json::json!({"hookSpecificOutput": {
+            "hookEventName": "PreToolUse", "permissionDecision": "ask",
+            "permissionDecisionReason": "sure?"}})
+    );
+    assert_eq!(
+        shape(HookOutput::post_block("fix it")),
+        serde_json::json!({"decision": "block", "reason": "fix it"})
+    );
+    assert_eq!(
+        shape(HookOutput::stop("done")),
+        serde_json::json!({"continue": false, "stopReason": "done"})
+    );
+}
+
+#[test]
+fn test_hook_output_pretty_format() {
+    let output = HookOutput::new().with_permission_decision(
+        PermissionDecision::Deny,
+        Some("File has unsaved changes".to_string()),
+    );
+
+    let compact = output
+        .render(OutputFormat::Json)
+        .expect("Failed to serialize");
+    let pretty = output
+        .render(OutputFormat::Pretty)
+        .expect("Failed to serialize");
+
+    assert!(!compact.contains('\n'));
+    assert!(pretty.lines().count() > 1);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&compact).unwrap(),
+        serde_json::from_str::<serde_json::Value>(&pretty).unwrap()
+    );
+}
+
 #[test]
 fn test_hook_output_with_system_message() {
     let output = HookOutput::new().with_system_message("Test message");
@@ -149,12 +251,46 @@ fn test_parse_multiedit_hook() {
 
     match h.tool {
         Tool::MultiEdit(input) => {
-            assert_eq!(input.file_path, "test.txt");
+            assert_eq!(input.file_path.as_deref(), Some("test.txt"));
         }
         _ => panic!("Expected MultiEdit tool"),
     }
 }
 
+#[test]
+fn test_parse_edit_hook_without_file_path() {
+    let json = r#"{
+        "session_id": "test-session",
+        "transcript_path": "/tmp/transcript",
+        "cwd": "/test/dir",
+        "hook_event_name": "PreToolUse",
+        "tool_name": "Edit",
+        "tool_input": {
+            "old_string": "a",
+            "new_string": "b"
+        }
+    }"#;
+
+    let Hook::Tool(h) = parse_hook(json).expect("Failed to parse hook") else {
+        panic!("Expected Tool hook");
+    };
+    match h.tool {
+        Tool::Edit(input) => {
+            assert_eq!(input.file_path, None);
+            assert_eq!(input.old_string.as_deref(), Some("a"));
+        }
+        _ => panic!("Expected Edit tool"),
+    }
+
+    // No tool_input at all parses the same way
+    let bare = r#"{"session_id":"s","transcript_path":"","cwd":".",
+        "hook_event_name":"PreToolUse","tool_name":"Write"}"#;
+    let Hook::Tool(h) = parse_hook(bare).expect("Failed to parse hook") else {
+        panic!("Expected Tool hook");
+    };
+    assert!(matches!(h.tool, Tool::Write(ref input) if input.file_path.is_none()));
+}
+
 #[test]
 fn test_parse_user_prompt_submit_hook() {
     let json = r#"{
@@ -170,6 +306,21 @@ fn test_parse_user_prompt_submit_hook() {
     assert!(matches!(hook, Hook::UserPrompt));
 }
 
+#[test]
+fn test_parse_stop_hook() {
+    let json = r#"{
+        "session_id": "test-session",
+        "transcript_path": "/tmp/transcript",
+        "cwd": "/test/dir",
+        "hook_event_name": "Stop",
+        "stop_hook_active": false
+    }"#;
+
+    let hook = parse_hook(json).expect("Failed to parse hook");
+
+    assert!(matches!(hook, Hook::Stop { session_id } if session_id == "test-session"));
+}
+
 #[test]
 fn test_hook_output_with_additional_context() {
     let output = HookOutput::new().with_additional_context("Selected code here");
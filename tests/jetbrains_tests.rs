@@ -0,0 +1,160 @@
+//! `JetBrainsAction` against mock IDE servers
+
+mod support;
+
+use sidekick::{Action, JetBrainsAction, NotifyLevel};
+use support::MockIde;
+
+const DIRTY: &str = r#"{"open":true,"current":true,"modified":true,"text":"live text"}"#;
+const CLOSED: &str = r#"{"open":false}"#;
+
+#[test]
+fn test_buffer_status_and_contents_come_from_ides_with_the_file_open() {
+    let open = MockIde::serve(&[("buffer", 200, DIRTY)]);
+    let closed = MockIde::serve(&[("buffer", 200, CLOSED)]);
+    let action = JetBrainsAction::new(vec![closed.port(), open.port()]);
+
+    let statuses = action.buffer_status("/p/Main.kt").unwrap();
+    assert_eq!(statuses.len(), 1);
+    assert!(statuses[0].is_current && statuses[0].has_unsaved_changes);
+    assert_eq!(action.buffer_contents("/p/Main.kt").unwrap(), ["live text"]);
+
+    let asked = &open.requests()[0];
+    assert_eq!(asked.method, "GET");
+    assert!(asked.target.starts_with("/api/sidekick/buffer?path="));
+    assert!(asked.target.contains("Main.kt"));
+}
+
+#[test]
+fn test_unreachable_ide_is_left_out_of_the_status() {
+    let open = MockIde::serve(&[("buffer", 200, DIRTY)]);
+    let broken = MockIde::serve(&[("buffer", 500, "")]);
+    let action = JetBrainsAction::new(vec![broken.port(), open.port()]);
+
+    assert_eq!(action.buffer_status("/p/Main.kt").unwrap().len(), 1);
+}
+
+#[test]
+fn test_file_not_open_error_reads_as_closed_not_failed() {
+    let not_open = MockIde::serve(&[("buffer", 404, r#"{"code":1,"message":"no editor"}"#)]);
+    let action = JetBrainsAction::new(vec![not_open.port()]);
+    assert!(action.buffer_status("/p/Main.kt").unwrap().is_empty());
+
+    // Any other failure leaves the IDE's state unknown
+    let busy = MockIde::serve(&[("buffer", 503, r#"{"code":2,"message":"indexing"}"#)]);
+    let action = JetBrainsAction::new(vec![not_open.port(), busy.port()]);
+    let err = action.buffer_status("/p/Main.kt").unwrap_err();
+    assert!(format!("{:#}", err).contains("indexing"));
+}
+
+#[test]
+fn test_plain_text_buffer_state_is_understood() {
+    let dirty = MockIde::serve_as("text/plain", &[("buffer", 200, "dirty 1 current 1\n")]);
+    let clean = MockIde::serve_as("text/plain", &[("buffer", 200, "current 1 dirty 0")]);
+    let closed = MockIde::serve_as("text/plain", &[("buffer", 200, "closed")]);
+    let action = JetBrainsAction::new(vec![dirty.port(), clean.port(), closed.port()]);
+
+    let statuses = action.buffer_status("/p/Main.kt").unwrap();
+    assert_eq!(statuses.len(), 2);
+    assert!(statuses[0].is_current && statuses[0].has_unsaved_changes);
+    assert!(statuses[1].is_current && !statuses[1].has_unsaved_changes);
+    // No live text over the plain protocol
+    assert!(action.buffer_contents("/p/Main.kt").unwrap().is_empty());
+
+    // A garbled answer is a failure, not a clean file
+    let garbled = MockIde::serve_as("text/plain", &[("buffer", 200, "dirty yes")]);
+    let action = JetBrainsAction::new(vec![garbled.port()]);
+    assert!(action.buffer_status("/p/Main.kt").is_err());
+}
+
+#[test]
+fn test_refresh_succeeds_if_any_ide_reloads() {
+    let failing = MockIde::serve(&[("reload", 500, "")]);
+    let working = MockIde::serve(&[("reload", 200, "")]);
+
+    JetBrainsAction::new(vec![failing.port(), working.port()])
+        .refresh_buffer("/p/Main.kt")
+        .unwrap();
+    let reload = &working.requests()[0];
+    assert_eq!(reload.method, "POST");
+    assert!(reload.body.contains("Main.kt"));
+
+    assert!(
+        JetBrainsAction::new(vec![failing.port()])
+            .refresh_buffer("/p/Main.kt")
+            .is_err()
+    );
+}
+
+#[test]
+fn test_send_message_posts_text_and_level() {
+    let ide = MockIde::serve(&[("notify", 200, "")]);
+    JetBrainsAction::new(vec![ide.port()])
+        .send_message("Edit blocked", NotifyLevel::Error)
+        .unwrap();
+
+    let body: serde_json::Value = serde_json::from_str(&ide.requests()[0].body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"message": "Edit blocked", "level": "error"})
+    );
+}
+
+#[test]
+fn test_status_is_posted_to_every_ide_and_cleared() {
+    let ides = [
+        MockIde::serve(&[("status", 200, "")]),
+        MockIde::serve(&[("status", 200, "")]),
+    ];
+    let action = JetBrainsAction::new(ides.iter().map(MockIde::port).collect());
+    action
+        .set_status(true, Some("src/lib.rs".to_string()))
+        .unwrap();
+    action.set_status(false, None).unwrap();
+
+    for ide in &ides {
+        let bodies: Vec<serde_json::Value> = ide
+            .requests()
+            .iter()
+            .map(|r| serde_json::from_str(&r.body).unwrap())
+            .collect();
+        assert_eq!(
+            bodies,
+            [
+                serde_json::json!({"active": true, "detail": "src/lib.rs"}),
+                serde_json::json!({"active": false, "detail": null}),
+            ]
+        );
+    }
+}
+
+#[test]
+fn test_buffers_and_selections_are_gathered_across_ides() {
+    let one = MockIde::serve(&[
+        ("buffers", 200, r#"["/p/A.kt","/p/B.kt"]"#),
+        (
+            "selection",
+            200,
+            r#"{"file_path":"/p/A.kt","start_line":3,"end_line":5,"content":"val a = 1"}"#,
+        ),
+    ]);
+    let two = MockIde::serve(&[
+        ("buffers", 200, r#"["/p/C.kt"]"#),
+        ("selection", 200, "null"),
+    ]);
+    let action = JetBrainsAction::new(vec![one.port(), two.port()]);
+
+    assert_eq!(
+        action.list_buffers().unwrap(),
+        ["/p/A.kt", "/p/B.kt", "/p/C.kt"]
+    );
+
+    let selections = action.get_visual_selections(Some(1024)).unwrap();
+    assert_eq!(selections.len(), 1);
+    assert_eq!((selections[0].start_line, selections[0].end_line), (3, 5));
+    assert!(
+        one.requests()
+            .iter()
+            .any(|r| r.target == "/api/sidekick/selection?max_bytes=1024")
+    );
+}
@@ -0,0 +1,156 @@
+//! End-to-end tests against a real, headless Neovim. Skipped when `nvim`
+//! isn't on PATH.
+
+use std::io::BufReader;
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rmpv::Value;
+use sidekick::{Action, NeovimAction};
+
+/// A headless Neovim serving `socket`, killed and cleaned up on drop
+struct HeadlessNvim {
+    child: Child,
+    socket: PathBuf,
+}
+
+impl HeadlessNvim {
+    /// Open `file` and run `commands` on it, then wait for the socket
+    fn spawn(socket: &Path, file: &Path, commands: &[&str]) -> Self {
+        let _ = std::fs::remove_file(socket);
+        let mut cmd = Command::new("nvim");
+        cmd.args(["--headless", "--clean", "--listen"])
+            .arg(socket)
+            .arg(file);
+        for command in commands {
+            cmd.arg("-c").arg(command);
+        }
+        let child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("couldn't launch nvim");
+        let nvim = Self {
+            child,
+            socket: socket.to_path_buf(),
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !socket.exists() {
+            assert!(Instant::now() < deadline, "nvim never opened its socket");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        nvim
+    }
+}
+
+impl Drop for HeadlessNvim {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
+
+/// Stand-in for a Neovim serving `socket`: records the method and
+/// arguments of every RPC request and answers each with nil. The client
+/// holds its connection until the process exits, so the serving thread is
+/// left detached.
+struct MockNvim {
+    requests: Arc<Mutex<Vec<(String, Value)>>>,
+}
+
+impl MockNvim {
+    fn listen(socket: &Path) -> Self {
+        let _ = std::fs::remove_file(socket);
+        let listener = UnixListener::bind(socket).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(&stream);
+                while let Ok(message) = rmpv::decode::read_value(&mut reader) {
+                    let Some([_, id, method, params]) = message.as_array().map(Vec::as_slice)
+                    else {
+                        continue;
+                    };
+                    let method = method.as_str().unwrap_or_default().to_string();
+                    recorded.lock().unwrap().push((method, params.clone()));
+                    let reply = Value::Array(vec![1.into(), id.clone(), Value::Nil, Value::Nil]);
+                    if rmpv::encode::write_value(&mut &stream, &reply).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Self { requests }
+    }
+
+    /// Lua code of every `nvim_execute_lua` request so far, oldest first
+    fn lua_sent(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(method, _)| method == "nvim_execute_lua")
+            .filter_map(|(_, params)| Some(params.as_array()?.first()?.as_str()?.to_string()))
+            .collect()
+    }
+}
+
+fn nvim_available() -> bool {
+    Command::new("nvim")
+        .arg("--version")
+        .output()
+        .is_ok_and(|out| out.status.success())
+}
+
+#[test]
+fn reports_unsaved_changes_in_a_live_nvim() {
+    if !nvim_available() {
+        eprintln!("nvim not on PATH, skipping");
+        return;
+    }
+
+    let dir = std::env::temp_dir().join(format!("sidekick-nvim-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("dirty.txt");
+    std::fs::write(&file, "saved\n").unwrap();
+    let socket = dir.join("nvim.sock");
+
+    let nvim = HeadlessNvim::spawn(&socket, &file, &["call setline(1, 'unsaved')"]);
+    let action = NeovimAction::from_sockets(vec![socket.clone()]);
+    let file_path = file.to_string_lossy();
+    let statuses = action.buffer_status(&file_path);
+    let contents = action.buffer_contents(&file_path);
+    drop(nvim);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let statuses = statuses.unwrap();
+    assert_eq!(statuses.len(), 1);
+    assert!(statuses[0].has_unsaved_changes);
+    assert!(statuses[0].is_current);
+    assert_eq!(contents.unwrap(), ["unsaved"]);
+}
+
+#[test]
+fn reload_resets_only_sidekicks_own_state() {
+    let dir = std::env::temp_dir().join(format!("sidekick-mock-nvim-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let socket = dir.join("nvim.sock");
+    let nvim = MockNvim::listen(&socket);
+
+    let reloaded = NeovimAction::from_sockets(vec![socket]).reload_plugin();
+    let sent = nvim.lua_sent();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    reloaded.unwrap();
+    assert_eq!(sent.len(), 1);
+    assert!(sent[0].contains("nvim_del_augroup_by_name, 'sidekick_focus'"));
+    assert!(sent[0].contains("vim.g.sidekick_active = nil"));
+    assert!(!sent[0].contains("packloadall"));
+}
@@ -0,0 +1,140 @@
+//! Test doubles for editor backends.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// A request the mock received: method, path with query, and body
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub target: String,
+    pub body: String,
+}
+
+/// Stand-in for a JetBrains IDE's built-in server with the companion plugin.
+/// Answers each endpoint under `/api/sidekick/` with a scripted status and
+/// body, 404 for anything unscripted, and records every request. The server
+/// thread stops and is joined on drop.
+pub struct MockIde {
+    port: u16,
+    requests: Arc<Mutex<Vec<Request>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockIde {
+    /// Serve `routes`: endpoint name (`buffer`, `reload`, ...) to
+    /// `(status, body)`, as JSON
+    pub fn serve(routes: &[(&str, u16, &str)]) -> Self {
+        Self::serve_as("application/json", routes)
+    }
+
+    /// Serve `routes` as `serve` does, with bodies of `content_type`
+    pub fn serve_as(content_type: &'static str, routes: &[(&str, u16, &str)]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let routes: HashMap<String, (u16, String)> = routes
+            .iter()
+            .map(|(endpoint, status, body)| (endpoint.to_string(), (*status, body.to_string())))
+            .collect();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let (requests, stop) = (requests.clone(), stop.clone());
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        answer(stream, content_type, &routes, &requests);
+                    }
+                }
+            })
+        };
+
+        Self {
+            port,
+            requests,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Requests received so far, oldest first
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockIde {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake the blocked accept so the thread sees the flag
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn answer(
+    stream: TcpStream,
+    content_type: &str,
+    routes: &HashMap<String, (u16, String)>,
+    requests: &Mutex<Vec<Request>>,
+) {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).is_err() || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    let mut body = vec![0; content_length];
+    let _ = reader.read_exact(&mut body);
+
+    let path = target.split('?').next().unwrap_or_default();
+    let endpoint = path.strip_prefix("/api/sidekick/").unwrap_or(path);
+    let (status, response) = routes
+        .get(endpoint)
+        .cloned()
+        .unwrap_or((404, String::new()));
+    requests.lock().unwrap().push(Request {
+        method,
+        target,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    });
+
+    let _ = write!(
+        &stream,
+        "HTTP/1.1 {} Mock\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        response.len(),
+        response
+    );
+}
@@ -1,6 +1,17 @@
 //! Unit tests for socket path utilities
 
-use sidekick::utils::{compute_socket_path_with_pid, find_matching_sockets};
+use std::os::unix::net::UnixListener;
+use std::time::Duration;
+
+use sidekick::utils::{
+    EditorKind, compute_kak_session_name, compute_readable_socket_path_for,
+    compute_socket_path_for, compute_socket_path_with_pid, discover_instances_for,
+    find_all_sockets, find_matching_sockets, find_matching_sockets_for,
+    find_matching_sockets_up_to, has_swapfile_in, live_jetbrains_port, logical_path,
+    normalize_lexically, normalize_path, record_dead_socket, salted_dir_hash, socket_pid,
+    track_dead_sockets, write_atomically,
+};
+use sidekick::utils::{create_private_dir, process_alive, state_dir, user_dir};
 
 #[test]
 fn test_compute_socket_path_with_pid() {
@@ -101,3 +112,463 @@ fn test_socket_path_pattern() {
     assert!(components[0].len() == 64); // blake3 hash is 64 hex chars
     assert!(components[1].ends_with(".sock"));
 }
+
+#[test]
+fn test_find_matching_sockets_for_explicit_dir() {
+    // A project dir other than the cwd, with a socket for it
+    let dir = std::env::temp_dir().join(format!("sidekick-cwd-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let socket = compute_socket_path_for(&dir, 424242).unwrap();
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket).unwrap();
+
+    let found = find_matching_sockets_for(&dir).unwrap();
+    let from_cwd = find_matching_sockets().unwrap();
+
+    drop(listener);
+    std::fs::remove_file(&socket).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(found, vec![socket.clone()]);
+    assert!(!from_cwd.contains(&socket));
+}
+
+#[test]
+fn test_find_matching_sockets_skips_regular_files() {
+    let dir = std::env::temp_dir().join(format!("sidekick-notsock-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let stray = compute_socket_path_for(&dir, 434343).unwrap();
+    std::fs::write(&stray, b"not a socket").unwrap();
+
+    let found = find_matching_sockets_for(&dir).unwrap();
+
+    std::fs::remove_file(&stray).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(found.is_empty(), "{:?}", found);
+}
+
+#[test]
+fn test_find_matching_sockets_stops_at_the_cap() {
+    let dir = std::env::temp_dir().join(format!("sidekick-cap-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let sockets: Vec<_> = [515151, 525252, 535353]
+        .into_iter()
+        .map(|pid| compute_socket_path_for(&dir, pid).unwrap())
+        .collect();
+    let listeners: Vec<_> = sockets
+        .iter()
+        .map(|socket| {
+            let _ = std::fs::remove_file(socket);
+            UnixListener::bind(socket).unwrap()
+        })
+        .collect();
+
+    let capped = find_matching_sockets_up_to(&dir, 2, None).unwrap();
+    let all = find_matching_sockets_for(&dir).unwrap();
+
+    drop(listeners);
+    for socket in &sockets {
+        std::fs::remove_file(socket).unwrap();
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(capped, sockets[..2]);
+    assert_eq!(all, sockets);
+}
+
+#[test]
+fn test_find_matching_sockets_orders_by_pid() {
+    let dir = std::env::temp_dir().join(format!("sidekick-pid-order-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // Name order would put 1000 before 900 and 90
+    let sockets: Vec<_> = [1000, 90, 900]
+        .into_iter()
+        .map(|pid| compute_socket_path_for(&dir, pid).unwrap())
+        .collect();
+    let listeners: Vec<_> = sockets
+        .iter()
+        .map(|socket| {
+            let _ = std::fs::remove_file(socket);
+            UnixListener::bind(socket).unwrap()
+        })
+        .collect();
+
+    let found = find_matching_sockets_for(&dir).unwrap();
+
+    drop(listeners);
+    for socket in &sockets {
+        std::fs::remove_file(socket).unwrap();
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let pids: Vec<_> = found
+        .iter()
+        .map(|path| socket_pid(path.file_name().unwrap().to_str().unwrap()).unwrap())
+        .collect();
+    assert_eq!(pids, [90, 900, 1000]);
+}
+
+#[test]
+fn test_recently_dead_socket_is_skipped_within_ttl() {
+    let dir = std::env::temp_dir().join(format!("sidekick-dead-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let socket = compute_socket_path_for(&dir, 545454).unwrap();
+    let _ = std::fs::remove_file(&socket);
+    // Left behind by an nvim that exited without cleaning up
+    drop(UnixListener::bind(&socket).unwrap());
+
+    let ttl = Some(Duration::from_secs(5));
+    let before = find_matching_sockets_up_to(&dir, usize::MAX, ttl).unwrap();
+    track_dead_sockets(true);
+    record_dead_socket(&socket);
+    let within_ttl = find_matching_sockets_up_to(&dir, usize::MAX, ttl).unwrap();
+    let without_ttl = find_matching_sockets_up_to(&dir, usize::MAX, None).unwrap();
+    let expired = find_matching_sockets_up_to(&dir, usize::MAX, Some(Duration::ZERO)).unwrap();
+
+    std::fs::remove_file(&socket).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(before, vec![socket.clone()]);
+    assert!(within_ttl.is_empty(), "{:?}", within_ttl);
+    assert_eq!(without_ttl, vec![socket.clone()]);
+    assert_eq!(expired, vec![socket]);
+}
+
+#[test]
+fn test_kak_session_name_shares_socket_hash() {
+    let dir = std::env::current_dir().unwrap();
+    let session = compute_kak_session_name(&dir, 4242).unwrap();
+    let socket = compute_socket_path_for(&dir, 4242).unwrap();
+
+    assert_eq!(
+        socket.file_name().unwrap().to_string_lossy(),
+        format!("{}.sock", session)
+    );
+}
+
+#[test]
+fn test_salt_namespaces_the_dir_hash() {
+    let dir = std::env::current_dir().unwrap();
+    let plain = salted_dir_hash(&dir, "").unwrap();
+    let alice = salted_dir_hash(&dir, "alice").unwrap();
+    let bob = salted_dir_hash(&dir, "bob").unwrap();
+
+    assert_ne!(alice, bob);
+    assert_ne!(alice, plain);
+    assert_eq!(alice, salted_dir_hash(&dir, "alice").unwrap());
+    // Unsalted keeps the historical digest of the path alone
+    assert_eq!(
+        plain,
+        blake3::hash(dir.canonicalize().unwrap().to_string_lossy().as_bytes())
+            .to_hex()
+            .to_string()
+    );
+}
+
+#[test]
+fn test_swap_files_are_found_beside_the_file_and_in_the_swap_dir() {
+    let dir = std::env::temp_dir().join(format!("sidekick-swap-{}", std::process::id()));
+    let swap_dir = dir.join("swap");
+    std::fs::create_dir_all(&swap_dir).unwrap();
+    let file = dir.join("foo.rs");
+    std::fs::write(&file, "").unwrap();
+    let file = file.canonicalize().unwrap();
+
+    let absent = has_swapfile_in(&file, Some(&swap_dir));
+    // A second Vim on the file takes `.swo`
+    std::fs::write(file.with_file_name(".foo.rs.swo"), "").unwrap();
+    let beside = has_swapfile_in(&file, None);
+    std::fs::remove_file(file.with_file_name(".foo.rs.swo")).unwrap();
+    let nvim_swap = swap_dir.join(format!("{}.swp", file.to_string_lossy().replace('/', "%")));
+    std::fs::write(&nvim_swap, "").unwrap();
+    let in_swap_dir = has_swapfile_in(&file, Some(&swap_dir));
+    let other_file = has_swapfile_in(&dir.join("bar.rs"), Some(&swap_dir));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(!absent);
+    assert!(beside);
+    assert!(in_swap_dir);
+    assert!(!other_file);
+}
+
+#[test]
+fn test_paths_that_dont_exist_yet_are_normalized_lexically() {
+    use std::path::Path;
+
+    let cases = [
+        ("./src/../src/foo.rs", "src/foo.rs"),
+        ("/no/such/./dir/../file.rs", "/no/such/file.rs"),
+        ("/../etc", "/etc"),
+        ("../a/../../b", "../../b"),
+    ];
+    for (raw, clean) in cases {
+        assert_eq!(normalize_lexically(Path::new(raw)), Path::new(clean));
+    }
+
+    let missing = std::env::temp_dir().join("sidekick-missing-dir/../sidekick-missing.rs");
+    assert_eq!(
+        normalize_path(&missing),
+        std::env::temp_dir().join("sidekick-missing.rs")
+    );
+}
+
+#[test]
+fn test_logical_path_keeps_the_symlinked_spelling() {
+    let base = std::env::temp_dir().join(format!("sidekick-logical-{}", std::process::id()));
+    let real = base.join("real");
+    let link = base.join("link");
+    std::fs::create_dir_all(&real).unwrap();
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(&real, &link).unwrap();
+
+    // The process cwd comes back resolved; `$PWD` still says `link`
+    let resolved = link.canonicalize().unwrap();
+    let from_cwd = logical_path(&resolved, Some(&link)).unwrap();
+    let relative = logical_path(std::path::Path::new("./sub/.."), Some(&link)).unwrap();
+    let elsewhere = logical_path(&link, Some(std::path::Path::new("/"))).unwrap();
+
+    std::fs::remove_file(&link).unwrap();
+    std::fs::remove_dir_all(&base).unwrap();
+
+    assert_eq!(from_cwd, link);
+    assert_eq!(relative, link);
+    assert_eq!(elsewhere, link);
+    assert_ne!(resolved, link);
+}
+
+#[test]
+fn test_find_all_sockets_groups_by_dir_hash() {
+    let base = std::env::temp_dir().join(format!("sidekick-all-{}", std::process::id()));
+    let (one, two) = (base.join("one"), base.join("two"));
+    std::fs::create_dir_all(&one).unwrap();
+    std::fs::create_dir_all(&two).unwrap();
+    let sockets = [
+        compute_socket_path_for(&one, 616161).unwrap(),
+        compute_socket_path_for(&one, 626262).unwrap(),
+        compute_socket_path_for(&two, 636363).unwrap(),
+    ];
+    let listeners: Vec<_> = sockets
+        .iter()
+        .map(|socket| {
+            let _ = std::fs::remove_file(socket);
+            UnixListener::bind(socket).unwrap()
+        })
+        .collect();
+    let (hash_one, hash_two) = (
+        salted_dir_hash(&one, "").unwrap()[..16].to_string(),
+        salted_dir_hash(&two, "").unwrap()[..16].to_string(),
+    );
+
+    let found: Vec<_> = find_all_sockets()
+        .unwrap()
+        .into_iter()
+        .filter(|(_, path)| sockets.contains(path))
+        .collect();
+
+    drop(listeners);
+    for socket in &sockets {
+        std::fs::remove_file(socket).unwrap();
+    }
+    std::fs::remove_dir_all(&base).unwrap();
+
+    let mut expected = vec![
+        (hash_one.clone(), sockets[0].clone()),
+        (hash_one, sockets[1].clone()),
+        (hash_two, sockets[2].clone()),
+    ];
+    expected.sort();
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn test_discover_instances_reads_pid_and_liveness() {
+    let dir = std::env::temp_dir().join(format!("sidekick-discover-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let ours = std::process::id();
+    // Far above any pid_max, so never a live process
+    let gone = 4_000_000_000;
+    let _listeners: Vec<_> = [ours, gone]
+        .into_iter()
+        .map(|pid| {
+            let socket = compute_socket_path_for(&dir, pid).unwrap();
+            let _ = std::fs::remove_file(&socket);
+            UnixListener::bind(socket).unwrap()
+        })
+        .collect();
+
+    let instances = discover_instances_for(&dir).unwrap();
+    for pid in [ours, gone] {
+        let _ = std::fs::remove_file(compute_socket_path_for(&dir, pid).unwrap());
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Sockets come in name order, which needn't be pid order
+    let mut found: Vec<_> = instances
+        .iter()
+        .map(|i| (i.pid, i.editor, i.alive))
+        .collect();
+    found.sort_by_key(|&(pid, ..)| pid != ours);
+    assert_eq!(
+        found,
+        [
+            (ours, EditorKind::Neovim, true),
+            (gone, EditorKind::Neovim, false)
+        ]
+    );
+}
+
+#[test]
+fn test_stale_jetbrains_port_files_are_pruned() {
+    let dir = std::env::temp_dir().join(format!("sidekick-jetbrains-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let ours = std::process::id();
+    let listening = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let open_port = listening.local_addr().unwrap().port();
+    let closed_port = {
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        closed.local_addr().unwrap().port()
+    };
+    let write = |hash: char, pid: u32, port: u16| {
+        let path = dir.join(format!("{}-{}.jetbrains", hash.to_string().repeat(64), pid));
+        std::fs::write(&path, port.to_string()).unwrap();
+        path
+    };
+
+    let live = write('a', ours, open_port);
+    let crashed = write('b', u32::MAX, open_port);
+    let refused = write('c', ours, closed_port);
+
+    assert_eq!(live_jetbrains_port(&live), Some(open_port));
+    assert_eq!(live_jetbrains_port(&crashed), None);
+    assert_eq!(live_jetbrains_port(&refused), None);
+    let left: Vec<_> = [&live, &crashed, &refused]
+        .iter()
+        .map(|path| path.exists())
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(left, [true, false, false]);
+}
+
+#[test]
+fn test_socket_pid_parses_every_instance_name() {
+    let hash = "a".repeat(64);
+    assert_eq!(socket_pid(&format!("{hash}-4242.sock")), Some(4242));
+    assert_eq!(socket_pid(&format!("{hash}-17.jetbrains")), Some(17));
+    assert_eq!(socket_pid(&format!("{hash}-9")), Some(9));
+    assert_eq!(socket_pid(&format!("{hash}.sock")), None);
+}
+
+#[test]
+fn test_readable_socket_names_are_still_discovered() {
+    let dir = std::env::temp_dir()
+        .join(format!("sidekick-readable-{}", std::process::id()))
+        .join("my project");
+    std::fs::create_dir_all(&dir).unwrap();
+    let socket = compute_readable_socket_path_for(&dir, 515151).unwrap();
+    let plain = compute_socket_path_for(&dir, 525252).unwrap();
+    let name = socket.file_name().unwrap().to_string_lossy().into_owned();
+    assert!(name.starts_with("my_project-"), "{name}");
+    assert!(name.ends_with("-515151.sock"));
+
+    let listeners: Vec<_> = [&socket, &plain]
+        .iter()
+        .map(|path| {
+            let _ = std::fs::remove_file(path);
+            UnixListener::bind(path).unwrap()
+        })
+        .collect();
+    let found = find_matching_sockets_for(&dir).unwrap();
+    let all = find_all_sockets().unwrap();
+    drop(listeners);
+    let _ = std::fs::remove_file(&socket);
+    let _ = std::fs::remove_file(&plain);
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+
+    assert_eq!(found, [socket.clone(), plain.clone()]);
+    let hash_of = |socket: &std::path::PathBuf| {
+        all.iter()
+            .find(|(_, path)| path == socket)
+            .unwrap()
+            .0
+            .clone()
+    };
+    assert_eq!(hash_of(&socket).len(), 16);
+    assert_eq!(hash_of(&socket), hash_of(&plain));
+}
+
+#[test]
+fn test_private_socket_dir_is_created_user_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let base = std::env::temp_dir().join(format!("sidekick-private-{}", std::process::id()));
+    let dir = user_dir(&base);
+    assert_eq!(dir.parent(), Some(base.as_path()));
+
+    create_private_dir(&dir).unwrap();
+    let mode = |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode();
+    assert_eq!(mode(&dir) & 0o777, 0o700);
+    // A base that had to be created too is never opened up to others
+    assert_eq!(mode(&base) & 0o7777, 0o700);
+
+    // A looser one left behind is tightened
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+    create_private_dir(&dir).unwrap();
+    assert_eq!(mode(&dir) & 0o777, 0o700);
+
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn test_private_socket_dir_refuses_a_symlink() {
+    let base = std::env::temp_dir().join(format!("sidekick-private-link-{}", std::process::id()));
+    std::fs::create_dir_all(&base).unwrap();
+    let dir = user_dir(&base);
+    let _ = std::fs::remove_file(&dir);
+    std::os::unix::fs::symlink(std::env::temp_dir(), &dir).unwrap();
+
+    assert!(create_private_dir(&dir).is_err());
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[test]
+fn test_atomic_write_replaces_rather_than_follows_a_symlink() {
+    let dir = std::env::temp_dir().join(format!("sidekick-atomic-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let target = dir.join("target");
+    let path = dir.join("cache.json");
+    std::fs::write(&target, "theirs").unwrap();
+    let _ = std::fs::remove_file(&path);
+    std::os::unix::fs::symlink(&target, &path).unwrap();
+
+    write_atomically(&path, b"ours").unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    let untouched = std::fs::read_to_string(&target).unwrap();
+    let leftovers = std::fs::read_dir(&dir).unwrap().count();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(written, "ours");
+    assert_eq!(untouched, "theirs");
+    assert_eq!(leftovers, 2);
+}
+
+#[test]
+fn test_state_dir_is_private_to_the_user() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = state_dir().unwrap();
+    let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o700);
+}
+
+#[test]
+fn test_process_alive_asks_the_kernel() {
+    assert!(process_alive(std::process::id()));
+    // init, which only root may signal: alive all the same
+    assert!(process_alive(1));
+    // Far above any pid_max, and pids that name process groups
+    assert!(!process_alive(999_999_999));
+    assert!(!process_alive(0));
+    assert!(!process_alive(u32::MAX));
+}